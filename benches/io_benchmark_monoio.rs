@@ -0,0 +1,210 @@
+//! Buffer pool benchmarks driven by monoio runtimes.
+//!
+//! `concurrent_disjoint_pages` runs readers and writers on separate threads,
+//! each on its own set of pages. With per-frame latches the readers should not
+//! slow down when writers are added.
+//!
+//! `mixed_workload` interleaves point lookups on a hot set with large
+//! sequential scans and prints the hit ratio reached by each replacer.
+//!
+//! `batched_io` compares one submission per page with `read_pages` /
+//! `write_pages`, which coalesce contiguous pages and submit them together.
+use std::{
+    sync::{Arc, Barrier},
+    time::{Duration, Instant},
+};
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use ringdb::storage::{
+    buffer_pool::BufferPoolManager,
+    disk::DiskManager,
+    page::{PAGE_SIZE, PageId},
+    replacer::ReplacerKind,
+};
+
+const PAGES_PER_THREAD: PageId = 16;
+const OPS_PER_THREAD: u64 = 2_000;
+
+fn bench_file(name: &str) -> String {
+    let path = std::env::temp_dir().join(format!("ringdb_bench_{}_{}.db", name, std::process::id()));
+    path.to_string_lossy().into_owned()
+}
+
+/// Runs `readers` reader threads and `writers` writer threads against one pool
+/// and returns the wall-clock time until every reader finished its operations.
+fn run_disjoint(readers: usize, writers: usize, iters: u64) -> Duration {
+    let threads = readers + writers;
+    let file = bench_file("concurrent");
+    let disk_manager = Arc::new(futures::executor::block_on(DiskManager::new(&file)).unwrap());
+    let bpm = BufferPoolManager::new(threads * PAGES_PER_THREAD as usize, disk_manager);
+    let barrier = Arc::new(Barrier::new(threads));
+
+    let handles: Vec<_> = (0..threads)
+        .map(|t| {
+            let bpm = bpm.clone();
+            let barrier = barrier.clone();
+            std::thread::spawn(move || {
+                let mut rt = monoio::RuntimeBuilder::<monoio::FusionDriver>::new()
+                    .build()
+                    .unwrap();
+                rt.block_on(async move {
+                    let first = t as PageId * PAGES_PER_THREAD;
+                    let pages = first..first + PAGES_PER_THREAD;
+                    // Warm the pool so the measured loop never touches the disk.
+                    for page_id in pages.clone() {
+                        drop(bpm.fetch_page(page_id).await.unwrap());
+                    }
+
+                    barrier.wait();
+                    let start = Instant::now();
+                    for i in 0..iters * OPS_PER_THREAD {
+                        let page_id = pages.start + (i % PAGES_PER_THREAD as u64) as PageId;
+                        if t < readers {
+                            let guard = bpm.fetch_page(page_id).await.unwrap();
+                            std::hint::black_box(guard.iter().map(|&b| b as u64).sum::<u64>());
+                        } else {
+                            let mut guard = bpm.fetch_page_mut(page_id).await.unwrap();
+                            guard[64..128].fill(i as u8);
+                        }
+                    }
+                    (t < readers).then(|| start.elapsed())
+                })
+            })
+        })
+        .collect();
+
+    let elapsed = handles
+        .into_iter()
+        .filter_map(|h| h.join().unwrap())
+        .max()
+        .unwrap_or_default();
+    let _ = std::fs::remove_file(&file);
+    elapsed
+}
+
+fn concurrent_disjoint_pages(c: &mut Criterion) {
+    let mut group = c.benchmark_group("concurrent_disjoint_pages");
+    group.sample_size(10);
+    group.bench_function("4_readers", |b| b.iter_custom(|iters| run_disjoint(4, 0, iters)));
+    group.bench_function("4_readers_4_writers", |b| {
+        b.iter_custom(|iters| run_disjoint(4, 4, iters))
+    });
+    group.finish();
+}
+
+const MIXED_POOL_SIZE: usize = 64;
+const HOT_PAGES: PageId = 32;
+const TABLE_PAGES: PageId = 1024;
+const SCAN_PAGES: PageId = 256;
+const LOOKUPS_PER_ROUND: u32 = 2_000;
+const LOOKUPS_PER_SCAN: u32 = 500;
+
+/// One round: point lookups (90% on the hot set) with a large scan every
+/// `LOOKUPS_PER_SCAN` lookups.
+async fn mixed_round(bpm: &Arc<BufferPoolManager>, seed: &mut u64) {
+    for i in 0..LOOKUPS_PER_ROUND {
+        if i % LOOKUPS_PER_SCAN == 0 {
+            let start = HOT_PAGES + (*seed % (TABLE_PAGES - HOT_PAGES - SCAN_PAGES) as u64) as PageId;
+            for page_id in start..start + SCAN_PAGES {
+                drop(bpm.fetch_page(page_id).await.unwrap());
+            }
+        }
+        *seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        let r = (*seed >> 33) as PageId;
+        let page_id = if r % 10 < 9 { r % HOT_PAGES } else { r % TABLE_PAGES };
+        drop(bpm.fetch_page(page_id).await.unwrap());
+    }
+}
+
+fn mixed_workload(c: &mut Criterion) {
+    let mut group = c.benchmark_group("mixed_workload");
+    group.sample_size(10);
+    let mut rt = monoio::RuntimeBuilder::<monoio::FusionDriver>::new()
+        .build()
+        .unwrap();
+    let file = bench_file("mixed");
+
+    for kind in [ReplacerKind::Clock, ReplacerKind::LruK(2), ReplacerKind::Arc] {
+        let disk_manager = rt.block_on(async { Arc::new(DiskManager::new(&file).await.unwrap()) });
+        let bpm = BufferPoolManager::with_replacer(MIXED_POOL_SIZE, kind, disk_manager);
+        let mut seed = 42;
+
+        group.bench_function(format!("{:?}", kind), |b| {
+            b.iter(|| rt.block_on(mixed_round(&bpm, &mut seed)))
+        });
+
+        let stats = bpm.stats();
+        println!(
+            "mixed_workload/{:?}: hit ratio {:.1}% ({} hits, {} misses)",
+            kind,
+            stats.hit_ratio() * 100.0,
+            stats.hits,
+            stats.misses
+        );
+    }
+    group.finish();
+    let _ = std::fs::remove_file(&file);
+}
+
+const BATCH_PAGES: PageId = 64;
+
+fn batched_io(c: &mut Criterion) {
+    let mut group = c.benchmark_group("batched_io");
+    group.sample_size(10);
+    group.throughput(criterion::Throughput::Bytes(BATCH_PAGES as u64 * PAGE_SIZE as u64));
+    let mut rt = monoio::RuntimeBuilder::<monoio::FusionDriver>::new()
+        .build()
+        .unwrap();
+    let file = bench_file("batched");
+    let disk_manager = rt.block_on(async { Arc::new(DiskManager::new(&file).await.unwrap()) });
+    let pages = || (0..BATCH_PAGES).map(|page_id| (page_id, vec![page_id as u8; PAGE_SIZE])).collect::<Vec<_>>();
+    rt.block_on(async {
+        let (res, _) = disk_manager.write_pages(pages()).await;
+        res.unwrap();
+    });
+
+    group.bench_function("write_page_x64", |b| {
+        b.iter(|| {
+            rt.block_on(async {
+                for (page_id, buf) in pages() {
+                    let (res, _) = disk_manager.write_page(page_id, buf).await;
+                    res.unwrap();
+                }
+            })
+        })
+    });
+    group.bench_function("write_pages_x64", |b| {
+        b.iter(|| {
+            rt.block_on(async {
+                let (res, _) = disk_manager.write_pages(pages()).await;
+                res.unwrap();
+            })
+        })
+    });
+    group.bench_function("read_page_x64", |b| {
+        b.iter(|| {
+            rt.block_on(async {
+                for page_id in 0..BATCH_PAGES {
+                    let (res, buf) = disk_manager.read_page(page_id, vec![0; PAGE_SIZE]).await;
+                    res.unwrap();
+                    std::hint::black_box(buf);
+                }
+            })
+        })
+    });
+    group.bench_function("read_pages_x64", |b| {
+        b.iter(|| {
+            rt.block_on(async {
+                let requests = (0..BATCH_PAGES).map(|page_id| (page_id, vec![0; PAGE_SIZE])).collect();
+                let (res, bufs) = disk_manager.read_pages(requests).await;
+                res.unwrap();
+                std::hint::black_box(bufs);
+            })
+        })
+    });
+    group.finish();
+    let _ = std::fs::remove_file(&file);
+}
+
+criterion_group!(benches, concurrent_disjoint_pages, mixed_workload, batched_io);
+criterion_main!(benches);
@@ -0,0 +1,124 @@
+//! 多客户端插入基准：启动 `bin/server`，用若干个并发连接各自执行 INSERT，
+//! 比较不同 synchronous_commit / commit_delay 下的提交吞吐量，衡量组提交的效果。
+//!
+//! 每种配置都启动一个使用新数据目录的服务端，客户端通过 PostgreSQL 协议的简单查询发送语句。
+use std::{
+    net::TcpStream,
+    path::PathBuf,
+    process::{Child, Command, Stdio},
+    sync::{Arc, Barrier},
+    time::{Duration, Instant},
+};
+
+use postgres::{Client, NoTls};
+
+const ADDR: &str = "127.0.0.1:54329";
+const CONNECTION: &str = "host=127.0.0.1 port=54329 user=bench";
+/// 每种配置的总插入行数
+const TOTAL_INSERTS: usize = 960;
+
+struct Server {
+    child: Child,
+    dir: PathBuf,
+}
+
+impl Server {
+    fn start(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("ringdb_server_insert_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let child = Command::new(env!("CARGO_BIN_EXE_server"))
+            .args(["--listen-addr", ADDR, "--auth-method", "trust"])
+            .current_dir(&dir)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("failed to start server");
+
+        let started = Instant::now();
+        while TcpStream::connect(ADDR).is_err() {
+            assert!(started.elapsed() < Duration::from_secs(10), "server did not start");
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        Self { child, dir }
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// 发送一条语句并等待响应，返回响应是否为成功
+fn query(client: &mut Client, sql: &str) -> bool {
+    client.batch_execute(sql).is_ok()
+}
+
+/// 返回 (总耗时, 每次提交的平均延迟)
+fn run(clients: usize, synchronous_commit: &str, commit_delay_us: u64) -> (Duration, Duration) {
+    let _server = Server::start(&format!("{}_{}_{}", clients, synchronous_commit, commit_delay_us));
+    let mut setup = Client::connect(CONNECTION, NoTls).unwrap();
+    assert!(query(&mut setup, "CREATE TABLE bench (id INT)"));
+
+    let per_client = TOTAL_INSERTS / clients;
+    let barrier = Arc::new(Barrier::new(clients + 1));
+    let handles: Vec<_> = (0..clients)
+        .map(|client| {
+            let barrier = barrier.clone();
+            let synchronous_commit = synchronous_commit.to_string();
+            std::thread::spawn(move || {
+                let mut stream = Client::connect(CONNECTION, NoTls).unwrap();
+                assert!(query(&mut stream, &format!("SET synchronous_commit = {}", synchronous_commit)));
+                assert!(query(&mut stream, &format!("SET commit_delay = {}", commit_delay_us)));
+                barrier.wait();
+                let mut latency = Duration::ZERO;
+                for i in 0..per_client {
+                    let started = Instant::now();
+                    let sql = format!("INSERT INTO bench VALUES ({})", client * per_client + i);
+                    assert!(query(&mut stream, &sql), "insert failed: {}", sql);
+                    latency += started.elapsed();
+                }
+                latency
+            })
+        })
+        .collect();
+
+    barrier.wait();
+    let started = Instant::now();
+    let latency: Duration = handles.into_iter().map(|h| h.join().unwrap()).sum();
+    let elapsed = started.elapsed();
+    (elapsed, latency / (per_client * clients) as u32)
+}
+
+fn main() {
+    // `cargo test` runs benches in test mode; a quick smoke run is enough there.
+    let quick = std::env::args().any(|arg| arg == "--test");
+    let client_counts: &[usize] = if quick { &[4] } else { &[1, 8, 32] };
+    let modes: &[(&str, u64)] = if quick {
+        &[("normal", 0)]
+    } else {
+        &[("off", 0), ("normal", 0), ("normal", 200), ("normal", 1000)]
+    };
+
+    println!(
+        "{:>8} {:>20} {:>16} {:>14} {:>16}",
+        "clients", "synchronous_commit", "commit_delay_us", "commits/s", "avg latency"
+    );
+    for &clients in client_counts {
+        for &(synchronous_commit, commit_delay_us) in modes {
+            let (elapsed, latency) = run(clients, synchronous_commit, commit_delay_us);
+            let commits = (TOTAL_INSERTS / clients * clients) as f64;
+            println!(
+                "{:>8} {:>20} {:>16} {:>14.0} {:>16?}",
+                clients,
+                synchronous_commit,
+                commit_delay_us,
+                commits / elapsed.as_secs_f64(),
+                latency
+            );
+        }
+    }
+}
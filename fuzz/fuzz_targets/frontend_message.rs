@@ -0,0 +1,12 @@
+//! `FrontendMessage::decode` 的模糊测试：第一个字节是消息类型，其余是消息体。
+//! 任何输入都只能解码成功或返回错误，不能 panic
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use ringdb::pgwire::message::FrontendMessage;
+
+fuzz_target!(|data: &[u8]| {
+    if let Some((&tag, body)) = data.split_first() {
+        let _ = FrontendMessage::decode(tag, body);
+    }
+});
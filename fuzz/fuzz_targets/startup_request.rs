@@ -0,0 +1,10 @@
+//! `StartupRequest::decode` 的模糊测试：输入是不含长度字段的启动包。
+//! 任何输入都只能解码成功或返回错误，不能 panic
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use ringdb::pgwire::message::StartupRequest;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = StartupRequest::decode(data);
+});
@@ -0,0 +1,344 @@
+//! SCRAM-SHA-256 口令认证（RFC 5802、RFC 7677），与 PostgreSQL 的做法相同，不支持通道绑定。
+//!
+//! 目录中只保存加盐迭代得到的 StoredKey 和 ServerKey，不保存口令本身。服务端用 StoredKey
+//! 验证客户端的证明，再用 ServerKey 签名，客户端由此确认服务端也持有该用户的校验信息。
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use bincode::{Decode, Encode};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+
+/// SASL 机制名
+pub const MECHANISM: &str = "SCRAM-SHA-256";
+/// 口令加盐迭代的次数，与 PostgreSQL 的默认值相同
+const ITERATIONS: u32 = 4096;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 18;
+/// 不使用通道绑定时 client-first-message 的 GS2 头。`y` 表示客户端支持通道绑定但认为服务端不支持
+const GS2_HEADERS: [&str; 2] = ["n,,", "y,,"];
+
+type Key = [u8; 32];
+
+fn hmac(key: &[u8], data: &[u8]) -> Key {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().into()
+}
+
+/// RFC 5802 中的 Hi()，即以 HMAC-SHA-256 为伪随机函数、只取一块的 PBKDF2
+fn salted_password(password: &str, salt: &[u8], iterations: u32) -> Key {
+    let mut block = salt.to_vec();
+    block.extend_from_slice(&1u32.to_be_bytes());
+    let mut u = hmac(password.as_bytes(), &block);
+    let mut result = u;
+    for _ in 1..iterations {
+        u = hmac(password.as_bytes(), &u);
+        result.iter_mut().zip(u).for_each(|(r, u)| *r ^= u);
+    }
+    result
+}
+
+fn random_bytes(len: usize) -> Vec<u8> {
+    let mut bytes = vec![0u8; len];
+    getrandom::fill(&mut bytes).expect("the operating system random number generator failed");
+    bytes
+}
+
+/// 逐字节比较全部内容，耗时与第一个不同字节的位置无关
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// 按 `key=value,...` 解析 SCRAM 消息，要求属性依次为 `keys`，多出的属性留在返回值的最后
+fn attributes<'a>(message: &'a str, keys: &[char]) -> Result<Vec<&'a str>, ScramError> {
+    let mut parts = message.splitn(keys.len() + 1, ',');
+    let mut values = Vec::new();
+    for &key in keys {
+        let part = parts.next().unwrap_or("");
+        let value = part
+            .strip_prefix(key)
+            .and_then(|rest| rest.strip_prefix('='))
+            .ok_or_else(|| ScramError::InvalidMessage(format!("expected attribute \"{}\"", key)))?;
+        values.push(value);
+    }
+    values.extend(parts);
+    Ok(values)
+}
+
+fn decode_base64(value: &str, what: &str) -> Result<Vec<u8>, ScramError> {
+    BASE64
+        .decode(value)
+        .map_err(|_| ScramError::InvalidMessage(format!("malformed {}", what)))
+}
+
+fn utf8(message: &[u8]) -> Result<&str, ScramError> {
+    std::str::from_utf8(message).map_err(|_| ScramError::InvalidMessage("message is not valid UTF-8".to_string()))
+}
+
+/// SCRAM 交换失败的原因
+#[derive(Debug, PartialEq, Eq)]
+pub enum ScramError {
+    /// 对方发来的消息不符合协议
+    InvalidMessage(String),
+    /// 客户端的证明不对，即口令错误或用户不存在
+    WrongPassword,
+}
+
+impl std::fmt::Display for ScramError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScramError::InvalidMessage(message) => write!(f, "malformed SCRAM message: {}", message),
+            ScramError::WrongPassword => write!(f, "password authentication failed"),
+        }
+    }
+}
+
+/// 目录中保存的口令校验信息
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct ScramVerifier {
+    iterations: u32,
+    salt: Vec<u8>,
+    stored_key: Key,
+    server_key: Key,
+}
+
+impl ScramVerifier {
+    /// 用随机的盐计算口令的校验信息
+    pub fn new(password: &str) -> Self {
+        Self::with_salt(password, random_bytes(SALT_LEN), ITERATIONS)
+    }
+
+    fn with_salt(password: &str, salt: Vec<u8>, iterations: u32) -> Self {
+        let salted = salted_password(password, &salt, iterations);
+        let client_key = hmac(&salted, b"Client Key");
+        Self {
+            iterations,
+            salt,
+            stored_key: Sha256::digest(client_key).into(),
+            server_key: hmac(&salted, b"Server Key"),
+        }
+    }
+
+    /// `password` 是否就是计算出这份校验信息的口令
+    pub fn matches(&self, password: &str) -> bool {
+        let other = Self::with_salt(password, self.salt.clone(), self.iterations);
+        constant_time_eq(&other.stored_key, &self.stored_key)
+    }
+
+    /// 不对应任何口令的校验信息。用户不存在时照常走完交换再失败，客户端无法据此分辨用户是否存在。
+    /// 盐和迭代次数由服务端的 `nonce` 和用户名决定，同一个用户名每次得到同样的盐，
+    /// 和真实用户的盐一样保持不变
+    pub fn mock(nonce: &[u8], user: &str) -> Self {
+        let derive = |label: &str| {
+            let mut data = label.as_bytes().to_vec();
+            data.extend_from_slice(user.as_bytes());
+            hmac(nonce, &data)
+        };
+        Self {
+            iterations: ITERATIONS,
+            salt: derive("salt")[..SALT_LEN].to_vec(),
+            stored_key: derive("stored key"),
+            server_key: derive("server key"),
+        }
+    }
+}
+
+/// 生成服务端的 mock 认证密钥，见 [`ScramVerifier::mock`]。它随目录持久化，重启之后不变
+pub fn new_mock_auth_nonce() -> Vec<u8> {
+    random_bytes(32)
+}
+
+/// 服务端一侧的一次 SCRAM 交换
+#[derive(Debug)]
+pub struct ScramServer {
+    verifier: ScramVerifier,
+    gs2_header: String,
+    nonce: String,
+    client_first_bare: String,
+    server_first: String,
+}
+
+impl ScramServer {
+    /// 处理 client-first-message，返回交换状态和要发给客户端的 server-first-message
+    pub fn start(verifier: ScramVerifier, client_first: &[u8]) -> Result<(Self, String), ScramError> {
+        Self::with_nonce(verifier, client_first, &BASE64.encode(random_bytes(NONCE_LEN)))
+    }
+
+    fn with_nonce(verifier: ScramVerifier, client_first: &[u8], server_nonce: &str) -> Result<(Self, String), ScramError> {
+        let client_first = utf8(client_first)?;
+        let gs2_header = GS2_HEADERS
+            .into_iter()
+            .find(|header| client_first.starts_with(header))
+            .ok_or_else(|| ScramError::InvalidMessage("channel binding is not supported".to_string()))?;
+        let client_first_bare = &client_first[gs2_header.len()..];
+        // Like PostgreSQL, the user name in the message is ignored in favour of the
+        // one from the startup packet.
+        let values = attributes(client_first_bare, &['n', 'r'])?;
+        let client_nonce = values[1];
+        if client_nonce.is_empty() || !client_nonce.chars().all(|c| c.is_ascii_graphic() && c != ',') {
+            return Err(ScramError::InvalidMessage("invalid nonce".to_string()));
+        }
+        let nonce = format!("{}{}", client_nonce, server_nonce);
+        let server_first = format!(
+            "r={},s={},i={}",
+            nonce,
+            BASE64.encode(&verifier.salt),
+            verifier.iterations
+        );
+        let server = Self {
+            verifier,
+            gs2_header: gs2_header.to_string(),
+            nonce,
+            client_first_bare: client_first_bare.to_string(),
+            server_first: server_first.clone(),
+        };
+        Ok((server, server_first))
+    }
+
+    /// 验证 client-final-message 中的证明，返回要发给客户端的 server-final-message
+    pub fn finish(self, client_final: &[u8]) -> Result<String, ScramError> {
+        let client_final = utf8(client_final)?;
+        let (without_proof, proof) = client_final
+            .rsplit_once(",p=")
+            .ok_or_else(|| ScramError::InvalidMessage("expected attribute \"p\"".to_string()))?;
+        let values = attributes(without_proof, &['c', 'r'])?;
+        if decode_base64(values[0], "channel binding")? != self.gs2_header.as_bytes() {
+            return Err(ScramError::InvalidMessage("unexpected channel binding".to_string()));
+        }
+        if values[1] != self.nonce {
+            return Err(ScramError::InvalidMessage("nonce does not match".to_string()));
+        }
+        let proof = decode_base64(proof, "proof")?;
+
+        let auth_message = format!("{},{},{}", self.client_first_bare, self.server_first, without_proof);
+        let client_signature = hmac(&self.verifier.stored_key, auth_message.as_bytes());
+        let client_key: Vec<u8> = proof.iter().zip(client_signature).map(|(p, s)| p ^ s).collect();
+        if proof.len() != client_signature.len()
+            || !constant_time_eq(&Sha256::digest(&client_key), &self.verifier.stored_key)
+        {
+            return Err(ScramError::WrongPassword);
+        }
+        let server_signature = hmac(&self.verifier.server_key, auth_message.as_bytes());
+        Ok(format!("v={}", BASE64.encode(server_signature)))
+    }
+}
+
+/// 客户端一侧的一次 SCRAM 交换
+#[derive(Debug)]
+pub struct ScramClient {
+    password: String,
+    client_first_bare: String,
+    /// 收到 server-first-message 之后才知道服务端的签名应该是什么
+    server_signature: Option<Key>,
+}
+
+impl ScramClient {
+    pub fn new(password: &str) -> Self {
+        // The server takes the user name from the startup packet, so libpq leaves it empty.
+        Self::with_nonce(password, "", &BASE64.encode(random_bytes(NONCE_LEN)))
+    }
+
+    fn with_nonce(password: &str, user: &str, nonce: &str) -> Self {
+        Self {
+            password: password.to_string(),
+            client_first_bare: format!("n={},r={}", user, nonce),
+            server_signature: None,
+        }
+    }
+
+    /// client-first-message
+    pub fn client_first(&self) -> String {
+        format!("{}{}", GS2_HEADERS[0], self.client_first_bare)
+    }
+
+    /// 处理 server-first-message，返回带证明的 client-final-message
+    pub fn client_final(&mut self, server_first: &[u8]) -> Result<String, ScramError> {
+        let server_first = utf8(server_first)?;
+        let values = attributes(server_first, &['r', 's', 'i'])?;
+        let client_nonce = &self.client_first_bare[self.client_first_bare.find(",r=").unwrap() + 3..];
+        if !values[0].starts_with(client_nonce) || values[0].len() == client_nonce.len() {
+            return Err(ScramError::InvalidMessage("nonce does not match".to_string()));
+        }
+        let salt = decode_base64(values[1], "salt")?;
+        let iterations: u32 = values[2]
+            .parse()
+            .ok()
+            .filter(|&i| i > 0)
+            .ok_or_else(|| ScramError::InvalidMessage("invalid iteration count".to_string()))?;
+
+        let salted = salted_password(&self.password, &salt, iterations);
+        let client_key = hmac(&salted, b"Client Key");
+        let stored_key = Sha256::digest(client_key);
+        let without_proof = format!("c={},r={}", BASE64.encode(GS2_HEADERS[0]), values[0]);
+        let auth_message = format!("{},{},{}", self.client_first_bare, server_first, without_proof);
+        let client_signature = hmac(&stored_key, auth_message.as_bytes());
+        let proof: Vec<u8> = client_key.iter().zip(client_signature).map(|(k, s)| k ^ s).collect();
+        self.server_signature = Some(hmac(&hmac(&salted, b"Server Key"), auth_message.as_bytes()));
+        Ok(format!("{},p={}", without_proof, BASE64.encode(proof)))
+    }
+
+    /// 检查 server-final-message 中服务端的签名
+    pub fn verify_server(&self, server_final: &[u8]) -> Result<(), ScramError> {
+        let server_final = utf8(server_final)?;
+        let signature = decode_base64(attributes(server_final, &['v'])?[0], "server signature")?;
+        match self.server_signature {
+            Some(expected) if constant_time_eq(&signature, &expected) => Ok(()),
+            _ => Err(ScramError::InvalidMessage("server signature does not match".to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scram_exchange() {
+        // The example exchange from RFC 7677.
+        let salt = BASE64.decode("W22ZaJ0SNY7soEsUEjb6gQ==").unwrap();
+        let verifier = ScramVerifier::with_salt("pencil", salt, 4096);
+        let mut client = ScramClient::with_nonce("pencil", "user", "rOprNGfwEbeRWgbNEkqO");
+        assert_eq!(client.client_first(), "n,,n=user,r=rOprNGfwEbeRWgbNEkqO");
+        let (server, server_first) = ScramServer::with_nonce(
+            verifier.clone(),
+            client.client_first().as_bytes(),
+            "%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0",
+        )
+        .unwrap();
+        assert_eq!(
+            server_first,
+            "r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,s=W22ZaJ0SNY7soEsUEjb6gQ==,i=4096"
+        );
+        let client_final = client.client_final(server_first.as_bytes()).unwrap();
+        assert_eq!(
+            client_final,
+            "c=biws,r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,p=dHzbZapWIk4jUhN+Ute9ytag9zjfMHgsqmmiz7AndVQ="
+        );
+        let server_final = server.finish(client_final.as_bytes()).unwrap();
+        assert_eq!(server_final, "v=6rriTRBi23WpRR/wtup+mMhUZUn/dB5nLTJRsjl95G4=");
+        client.verify_server(server_final.as_bytes()).unwrap();
+
+        // A wrong password, a mock verifier and a replayed nonce are all rejected.
+        let exchange = |verifier: ScramVerifier, password: &str| {
+            let mut client = ScramClient::new(password);
+            let (server, server_first) = ScramServer::start(verifier, client.client_first().as_bytes())?;
+            let client_final = client.client_final(server_first.as_bytes())?;
+            let server_final = server.finish(client_final.as_bytes())?;
+            client.verify_server(server_final.as_bytes())
+        };
+        let verifier = ScramVerifier::new("secret");
+        assert_ne!(verifier, ScramVerifier::new("secret"));
+        exchange(verifier.clone(), "secret").unwrap();
+        assert_eq!(exchange(verifier.clone(), "Secret"), Err(ScramError::WrongPassword));
+        let nonce = new_mock_auth_nonce();
+        assert_eq!(exchange(ScramVerifier::mock(&nonce, "nobody"), "secret"), Err(ScramError::WrongPassword));
+        // A mock verifier offers the same salt on every attempt, so retrying does not expose it.
+        assert_eq!(ScramVerifier::mock(&nonce, "nobody"), ScramVerifier::mock(&nonce, "nobody"));
+        assert_ne!(ScramVerifier::mock(&nonce, "nobody").salt, ScramVerifier::mock(&nonce, "somebody").salt);
+        let (server, _) = ScramServer::start(verifier.clone(), b"n,,n=,r=abc").unwrap();
+        assert!(matches!(
+            server.finish(b"c=biws,r=abcdef,p=AAAA"),
+            Err(ScramError::InvalidMessage(_))
+        ));
+        assert!(ScramServer::start(verifier, b"p=tls-server-end-point,,n=,r=abc").is_err());
+    }
+}
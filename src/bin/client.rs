@@ -1,21 +1,337 @@
 //! client.rs - A CLI client to connect to the DB server, send SQL commands, and display results.
-use std::io::prelude::*;
+//!
+//! 使用 PostgreSQL 协议的简单查询，也可以直接用 psql 连接服务端。
+//! 和 psql 一样，`INSERT INTO t VALUES ($1, $2) \bind 1 'a b'` 用扩展查询绑定文本参数执行，
+//! 等待结果时按 Ctrl+C 取消正在执行的语句。
+//! 连接的地址取自配置中的 `listen_addr`，例如 `client --listen-addr 127.0.0.1:5432`。
+//! 以配置中的 `user` 登录，服务端要求口令时使用 `password`（例如 `RINGDB_PASSWORD`），没有配置时在终端上输入。
 use bytes::{BufMut, BytesMut};
+use ringdb::{
+    auth::{self, ScramClient},
+    config::Config,
+    pgwire::message::body_len,
+};
 use rustyline::DefaultEditor;
 use rustyline::error::ReadlineError;
-use std::io::{self, Write};
-use std::net::TcpStream;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, Ordering};
 
-use ringdb::executor::ExecutionResult;
+const PROTOCOL_VERSION: i32 = 196608;
+const CANCEL_REQUEST_CODE: i32 = 80877102;
 
+/// 服务端在 BackendKeyData 中给出的会话号和取消密钥
+static BACKEND_KEY: OnceLock<(i32, i32)> = OnceLock::new();
+/// 是否正在等待语句的结果
+static BUSY: AtomicBool = AtomicBool::new(false);
 
-const LEN_BYTES: usize = 4;
+fn invalid_data(message: impl ToString) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// 读取一条后端消息，返回类型和内容
+fn read_message(stream: &mut TcpStream, max_len: usize) -> io::Result<(u8, Vec<u8>)> {
+    let mut header = [0u8; 5];
+    stream.read_exact(&mut header)?;
+    let len = body_len(header[1..5].try_into().unwrap(), max_len).map_err(invalid_data)?;
+    let mut body = vec![0u8; len];
+    stream.read_exact(&mut body)?;
+    Ok((header[0], body))
+}
+
+/// 从消息内容的开头取出 `len` 字节，内容不够时报错而不是 panic
+fn take<'a>(data: &mut &'a [u8], len: usize) -> io::Result<&'a [u8]> {
+    if data.len() < len {
+        return Err(invalid_data("truncated message from server"));
+    }
+    let (head, tail) = data.split_at(len);
+    *data = tail;
+    Ok(head)
+}
+
+fn take_i16(data: &mut &[u8]) -> io::Result<i16> {
+    Ok(i16::from_be_bytes(take(data, 2)?.try_into().unwrap()))
+}
+
+fn take_i32(data: &mut &[u8]) -> io::Result<i32> {
+    Ok(i32::from_be_bytes(take(data, 4)?.try_into().unwrap()))
+}
+
+fn cstr(data: &[u8]) -> (String, &[u8]) {
+    let end = data.iter().position(|&b| b == 0).unwrap_or(data.len());
+    let rest = data.get(end + 1..).unwrap_or(&[]);
+    (String::from_utf8_lossy(&data[..end]).into_owned(), rest)
+}
+
+/// ErrorResponse / NoticeResponse 中的字段
+fn error_fields(mut data: &[u8]) -> Vec<(u8, String)> {
+    let mut fields = Vec::new();
+    while let Some((&field, rest)) = data.split_first() {
+        if field == 0 {
+            break;
+        }
+        let (value, rest) = cstr(rest);
+        fields.push((field, value));
+        data = rest;
+    }
+    fields
+}
+
+fn field(fields: &[(u8, String)], code: u8) -> &str {
+    fields.iter().find(|(f, _)| *f == code).map_or("", |(_, v)| v.as_str())
+}
+
+fn put_cstr(buf: &mut BytesMut, s: &str) {
+    buf.put_slice(s.as_bytes());
+    buf.put_u8(0);
+}
+
+/// 追加一条前端消息，长度字段由内容算出
+fn put_message(buf: &mut BytesMut, tag: u8, body: &[u8]) {
+    buf.put_u8(tag);
+    buf.put_i32(4 + body.len() as i32);
+    buf.put_slice(body);
+}
+
+/// 切分 `\bind` 之后的参数，单引号括起的参数可以包含空白
+fn bind_params(text: &str) -> Vec<String> {
+    let mut params = Vec::new();
+    let mut chars = text.trim().chars().peekable();
+    while let Some(c) = chars.next() {
+        if c.is_whitespace() {
+            continue;
+        }
+        let mut param = String::new();
+        if c == '\'' {
+            param.extend(chars.by_ref().take_while(|&c| c != '\''));
+        } else {
+            param.push(c);
+            while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                param.push(c);
+            }
+        }
+        params.push(param);
+    }
+    params
+}
+
+/// 用未命名的预备语句和门户执行一条带参数的语句：Parse、Bind、Describe、Execute、Sync
+fn extended_query(sql: &str, params: &[String]) -> BytesMut {
+    let mut request = BytesMut::new();
+    let mut body = BytesMut::new();
+    put_cstr(&mut body, "");
+    put_cstr(&mut body, sql);
+    body.put_i16(0); // let the server infer parameter types
+    put_message(&mut request, b'P', &body);
+
+    body.clear();
+    put_cstr(&mut body, "");
+    put_cstr(&mut body, "");
+    body.put_i16(0); // all parameters in text format
+    body.put_i16(params.len() as i16);
+    for param in params {
+        body.put_i32(param.len() as i32);
+        body.put_slice(param.as_bytes());
+    }
+    body.put_i16(0); // all results in text format
+    put_message(&mut request, b'B', &body);
+
+    put_message(&mut request, b'D', b"P\0");
+    put_message(&mut request, b'E', b"\0\0\0\0\0");
+    put_message(&mut request, b'S', &[]);
+    request
+}
+
+/// 从终端读一行口令，输入时不回显
+fn read_password() -> io::Result<String> {
+    print!("Password: ");
+    io::stdout().flush()?;
+    let fd = libc::STDIN_FILENO;
+    // SAFETY: termios is plain data, and tcgetattr fills it in before it is used.
+    let mut term: libc::termios = unsafe { std::mem::zeroed() };
+    let is_tty = unsafe { libc::tcgetattr(fd, &mut term) } == 0;
+    if is_tty {
+        let mut quiet = term;
+        quiet.c_lflag &= !libc::ECHO;
+        unsafe { libc::tcsetattr(fd, libc::TCSANOW, &quiet) };
+    }
+    let mut line = String::new();
+    let res = io::stdin().read_line(&mut line);
+    if is_tty {
+        unsafe { libc::tcsetattr(fd, libc::TCSANOW, &term) };
+        println!();
+    }
+    res?;
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// 完成认证，收到 AuthenticationOk 时返回。服务端要求 SCRAM-SHA-256 时用 `password`，
+/// 没有配置口令时在终端上输入
+fn authenticate(stream: &mut TcpStream, max_len: usize, password: Option<&str>) -> io::Result<()> {
+    let mut scram: Option<ScramClient> = None;
+    loop {
+        let (tag, body) = read_message(stream, max_len)?;
+        let mut rest = &body[..];
+        let mut reply = BytesMut::new();
+        match tag {
+            b'R' => match take_i32(&mut rest)? {
+                0 => return Ok(()),
+                10 => {
+                    let mut mechanisms = Vec::new();
+                    loop {
+                        let (mechanism, after) = cstr(rest);
+                        if mechanism.is_empty() {
+                            break;
+                        }
+                        mechanisms.push(mechanism);
+                        rest = after;
+                    }
+                    if !mechanisms.iter().any(|m| m == auth::MECHANISM) {
+                        return Err(invalid_data(format!(
+                            "server requires an unsupported SASL mechanism: {}",
+                            mechanisms.join(", ")
+                        )));
+                    }
+                    let password = match password {
+                        Some(password) => password.to_string(),
+                        None => read_password()?,
+                    };
+                    let client = ScramClient::new(&password);
+                    let first = client.client_first();
+                    let mut body = BytesMut::new();
+                    put_cstr(&mut body, auth::MECHANISM);
+                    body.put_i32(first.len() as i32);
+                    body.put_slice(first.as_bytes());
+                    put_message(&mut reply, b'p', &body);
+                    scram = Some(client);
+                }
+                11 => {
+                    let client = scram.as_mut().ok_or_else(|| invalid_data("unexpected SASL message"))?;
+                    let client_final = client.client_final(rest).map_err(invalid_data)?;
+                    put_message(&mut reply, b'p', client_final.as_bytes());
+                }
+                12 => {
+                    let client = scram.as_ref().ok_or_else(|| invalid_data("unexpected SASL message"))?;
+                    client.verify_server(rest).map_err(invalid_data)?;
+                }
+                code => return Err(invalid_data(format!("unsupported authentication request {}", code))),
+            },
+            b'E' => {
+                let fields = error_fields(&body);
+                return Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    format!("{} (SQLSTATE {})", field(&fields, b'M'), field(&fields, b'C')),
+                ));
+            }
+            // NegotiateProtocolVersion, ...
+            _ => {}
+        }
+        if !reply.is_empty() {
+            stream.write_all(&reply)?;
+        }
+    }
+}
+
+/// 通过一个新连接请求取消正在执行的语句，服务端不回复
+fn send_cancel(addr: SocketAddr) -> io::Result<()> {
+    let Some(&(process_id, secret_key)) = BACKEND_KEY.get() else {
+        return Ok(());
+    };
+    let mut request = BytesMut::new();
+    request.put_i32(16);
+    request.put_i32(CANCEL_REQUEST_CODE);
+    request.put_i32(process_id);
+    request.put_i32(secret_key);
+    TcpStream::connect(addr)?.write_all(&request)
+}
+
+/// 打印结果直到 ReadyForQuery
+fn print_results(stream: &mut TcpStream, max_len: usize) -> io::Result<()> {
+    loop {
+        let (tag, body) = read_message(stream, max_len)?;
+        let mut rest = &body[..];
+        match tag {
+            b'T' => {
+                let count = take_i16(&mut rest)?;
+                let mut names = Vec::new();
+                for _ in 0..count {
+                    let (name, after) = cstr(rest);
+                    names.push(name);
+                    rest = after;
+                    // table oid, attnum, type oid, typlen, typmod, format
+                    take(&mut rest, 18)?;
+                }
+                println!("{}", names.join(" | "));
+            }
+            b'D' => {
+                let count = take_i16(&mut rest)?;
+                let mut values = Vec::new();
+                for _ in 0..count {
+                    let len = take_i32(&mut rest)?;
+                    if len < 0 {
+                        values.push("NULL".to_string());
+                    } else {
+                        values.push(String::from_utf8_lossy(take(&mut rest, len as usize)?).into_owned());
+                    }
+                }
+                println!("{}", values.join(" | "));
+            }
+            b'C' => println!("{}", cstr(&body).0),
+            b'E' => {
+                let fields = error_fields(&body);
+                println!("ERROR: {} (SQLSTATE {})", field(&fields, b'M'), field(&fields, b'C'));
+            }
+            b'N' => println!("NOTICE: {}", field(&error_fields(&body), b'M')),
+            b'K' => {
+                let key = (take_i32(&mut rest)?, take_i32(&mut rest)?);
+                let _ = BACKEND_KEY.set(key);
+            }
+            b'Z' => return Ok(()),
+            // EmptyQueryResponse, ParameterStatus, ...
+            _ => {}
+        }
+    }
+}
 
 fn main() -> io::Result<()> {
+    let config = Config::load(std::env::args().skip(1), |name| std::env::var(name).ok()).unwrap_or_else(|e| {
+        eprintln!("client: {}", e);
+        std::process::exit(2);
+    });
+    let addr = config.listen_addr;
+    let max_len = config.pgwire.max_message_size;
     println!("--- ringDB CLIENT ---");
-    println!("connect to 127.0.0.1:5432...");
+    println!("connect to {}...", addr);
 
-    let mut stream = TcpStream::connect("127.0.0.1:5432")?;
+    let mut stream = TcpStream::connect(&addr)?;
+    let mut startup = BytesMut::new();
+    startup.put_i32(0);
+    startup.put_i32(PROTOCOL_VERSION);
+    for s in ["user", config.user.as_str(), "application_name", "ringdb-client", ""] {
+        startup.put_slice(s.as_bytes());
+        startup.put_u8(0);
+    }
+    let len = startup.len() as i32;
+    startup[0..4].copy_from_slice(&len.to_be_bytes());
+    stream.write_all(&startup)?;
+    if let Err(e) = authenticate(&mut stream, max_len, config.password.as_deref()) {
+        eprintln!("Connection failed: {}", e);
+        std::process::exit(1);
+    }
+    print_results(&mut stream, max_len)?;
+    let server_addr = stream.peer_addr()?;
+    ctrlc::set_handler(move || {
+        // While a line is being edited rustyline reads Ctrl+C as a key, so a signal
+        // arrives only while waiting for results or from outside.
+        if !BUSY.load(Ordering::Acquire) {
+            std::process::exit(130);
+        }
+        if let Err(e) = send_cancel(server_addr) {
+            eprintln!("Cancel request failed: {}", e);
+        }
+    })
+    .map_err(io::Error::other)?;
     println!("Connected successfully! Please enter SQL statements or .exit to quit.");
 
     let mut rl = DefaultEditor::new().unwrap();
@@ -29,42 +345,33 @@ fn main() -> io::Result<()> {
                     break;
                 }
 
-                // 1. Prepare the request (length + SQL)
-                let sql_bytes = line.as_bytes();
-                let len_bytes = (sql_bytes.len() as u32).to_be_bytes();
-                let mut request = BytesMut::new();
-                request.put_slice(&len_bytes);
-                request.put_slice(sql_bytes);
+                let request = match line.split_once("\\bind") {
+                    Some((sql, params)) => {
+                        let params = params.trim_end().trim_end_matches("\\g");
+                        extended_query(sql.trim(), &bind_params(params))
+                    }
+                    None => {
+                        // Simple query: 'Q' + length + SQL + NUL
+                        let mut request = BytesMut::new();
+                        request.put_u8(b'Q');
+                        request.put_i32(4 + line.len() as i32 + 1);
+                        request.put_slice(line.as_bytes());
+                        request.put_u8(0);
+                        request
+                    }
+                };
 
-                // 2. Send the request
+                BUSY.store(true, Ordering::Release);
                 if let Err(e) = stream.write_all(&request) {
                     eprintln!("Send request failed: {}", e);
                     break;
                 }
-
-                // 3. Read the response length and content
-                let mut len_buffer = [0u8; LEN_BYTES];
-                if let Err(e) = stream.read_exact(&mut len_buffer) {
-                    eprintln!("Read response length failed: {}", e);
+                let res = print_results(&mut stream, max_len);
+                BUSY.store(false, Ordering::Release);
+                if let Err(e) = res {
+                    eprintln!("Read response failed: {}", e);
                     break;
                 }
-                let result_len = u32::from_be_bytes(len_buffer) as usize;
-
-                let mut result_buffer = vec![0u8; result_len];
-                if let Err(e) = stream.read_exact(&mut result_buffer) {
-                    eprintln!("Read response content failed: {}", e);
-                    break;
-                }
-
-                // 4. Decode and display the result
-                let (result, _): (Result<ExecutionResult, String>, _) =
-                    bincode::decode_from_slice(&result_buffer, bincode::config::standard())
-                        .unwrap();
-
-                match result {
-                    Ok(exec_result) => println!("{:?}", exec_result),
-                    Err(e) => println!("Server error: {}", e),
-                }
             }
             Err(ReadlineError::Interrupted) | Err(ReadlineError::Eof) => {
                 break;
@@ -76,6 +383,8 @@ fn main() -> io::Result<()> {
         }
     }
 
+    // Terminate
+    let _ = stream.write_all(&[b'X', 0, 0, 0, 4]);
     println!("Disconnecting. Goodbye!");
     Ok(())
-}
\ No newline at end of file
+}
@@ -1,132 +1,257 @@
-use std::{net::TcpListener, sync::Arc};
+//! ringDB 服务端，使用 PostgreSQL 协议。参数见 `server --help` 和 `ringdb::config`。
+//!
+//! 默认每个 worker 用 `SO_REUSEPORT` 绑定自己的监听套接字，由内核在各核之间分配连接；
+//! `--accept dispatch` 保留原来的做法：一个监听线程接受连接后轮流交给各个 worker。
+//!
+//! 默认要求 SCRAM-SHA-256 口令认证：启动时用 `user` 和 `password` 创建第一个用户（已经存在时更新口令），
+//! 之后可以用 `CREATE USER` 添加其他用户；`--auth-method trust` 则不认证。
+//!
+//! 收到 SIGINT/SIGTERM 后停止 accept，等正在执行的语句结束（最多 `shutdown_timeout`），
+//! 关闭客户端连接，刷写日志和缓冲池后以状态 0 退出。再收到一次信号则立即退出。
+use std::{future::Future, net::TcpListener, sync::Arc, thread::JoinHandle, time::Duration};
 
-use bytes::{BufMut, BytesMut};
-use crossbeam::channel;
-use monoio::{
-    io::{AsyncReadRentExt, AsyncWriteRentExt},
-    net::TcpStream,
+use core_affinity::CoreId;
+use futures::{StreamExt, channel::mpsc};
+use monoio::net::{ListenerOpts, TcpStream};
+use ringdb::{
+    Database,
+    config::{self, AcceptMode, Config},
+    logging,
+    pgwire::{self, AuthMethod, PgwireConfig},
 };
-use ringdb::{Database, storage::disk::DiskManager};
-
-const LEN_BYTES: usize = 4;
 
 fn main() {
-    println!("--- ringDB Server ---");
+    if std::env::args().any(|arg| arg == "--help" || arg == "-h") {
+        println!("usage: server [OPTIONS]\n\n{}", config::USAGE);
+        return;
+    }
+    let config = Config::load(std::env::args().skip(1), |name| std::env::var(name).ok()).unwrap_or_else(|e| {
+        eprintln!("server: {}", e);
+        std::process::exit(2);
+    });
+    logging::init(config.log_level);
+    log::info!("--- ringDB Server ---");
+
+    let mut core_ids = core_affinity::get_core_ids().unwrap();
+    log::info!("Detected {} CPU cores.", core_ids.len());
+    if config.workers > core_ids.len() {
+        eprintln!(
+            "server: invalid value for workers: {} (this machine has {} cores)",
+            config.workers,
+            core_ids.len()
+        );
+        std::process::exit(2);
+    }
+    if config.workers > 0 {
+        core_ids.truncate(config.workers);
+    }
+    let num_cores = core_ids.len();
+    if config.pgwire.auth_method == AuthMethod::ScramSha256 && config.password.is_none() {
+        // Nobody could ever log in to create the first user.
+        eprintln!(
+            "server: auth_method is scram-sha-256 but no password is set for user '{}' \
+             (set RINGDB_PASSWORD, or use --auth-method trust)",
+            config.user
+        );
+        std::process::exit(2);
+    }
 
     let db = {
         let mut rt = monoio::RuntimeBuilder::<monoio::FusionDriver>::new()
             .build()
             .unwrap();
-        rt.block_on(async { Arc::new(Database::new("database.db".to_string(), 64).await.unwrap()) })
+        let db = rt.block_on(Database::open(&config)).unwrap_or_else(|e| {
+            eprintln!("server: failed to open database in {}: {}", config.data_dir.display(), e);
+            std::process::exit(1);
+        });
+        if let Some(password) = &config.password {
+            // The configured password wins over the one saved in the catalog.
+            if let Err(e) = rt.block_on(db.bootstrap_user(&config.user, password)) {
+                eprintln!("server: failed to create user '{}': {}", config.user, e);
+                std::process::exit(1);
+            }
+        }
+        Arc::new(db)
     };
+    let listen_addr = config.listen_addr.clone();
+    let shutdown_timeout = config.shutdown_timeout;
+    let pgwire_config = config.pgwire;
 
-    let core_ids = core_affinity::get_core_ids().unwrap();
-    let num_cores = core_ids.len();
-    println!("Detected {} CPU cores.", num_cores);
-
-    let (tx, rx) = channel::unbounded();
-
-    let mut worker_threads = Vec::new();
-    for (i, core_id) in core_ids.into_iter().enumerate() {
-        let rx = rx.clone();
+    let handler = {
         let db = db.clone();
-
-        let handle = std::thread::spawn(move || {
-            if !core_affinity::set_for_current(core_id) {
-                eprintln!("Failed to set core affinity for worker {}", i);
-            } else {
-                println!("Worker {} pinned to core {:?}", i, core_id.id);
+        let listen_addr = listen_addr.clone();
+        let accept = config.accept;
+        move || {
+            if db.shutdown().request() {
+                log::warn!("Received a second shutdown signal, exiting immediately");
+                std::process::exit(1);
             }
-
-            let mut rt = monoio::RuntimeBuilder::<monoio::FusionDriver>::new()
-                .enable_all()
-                .build()
-                .unwrap();
-
-            rt.block_on(async move {
-                while let Ok(stream) = rx.recv() {
-                    let stream = monoio::net::TcpStream::from_std(stream).unwrap();
-                    let db = db.clone();
-                    monoio::spawn(handle_connection(stream, db));
-                }
-            })
-        });
-        worker_threads.push(handle);
+            log::info!("Received shutdown signal, no longer accepting connections");
+            if accept == AcceptMode::Dispatch {
+                // Wake the blocking accept() so the listener thread sees the request.
+                let _ = std::net::TcpStream::connect(&listen_addr);
+            }
+        }
+    };
+    if let Err(e) = ctrlc::set_handler(handler) {
+        eprintln!("server: failed to install signal handler: {}", e);
+        std::process::exit(1);
     }
 
-    let listener_thread = std::thread::spawn(move || {
-        let listener = TcpListener::bind("127.0.0.1:5432").unwrap();
-        println!("Server is listening on: 127.0.0.1:5432");
+    match config.accept {
+        AcceptMode::ReusePort => {
+            log::info!("Server is listening on: {} (SO_REUSEPORT on {} workers)", listen_addr, num_cores);
+            let workers: Vec<_> = core_ids
+                .into_iter()
+                .enumerate()
+                .map(|(i, core_id)| {
+                    let listen_addr = listen_addr.clone();
+                    spawn_worker(i, core_id, num_cores, shutdown_timeout, db.clone(), move |db| {
+                        accept_loop(i, listen_addr, db, pgwire_config)
+                    })
+                })
+                .collect();
+            for handle in workers {
+                handle.join().unwrap();
+            }
+        }
+        AcceptMode::Dispatch => {
+            // One async channel per worker: a blocking receive inside `block_on` would
+            // keep the runtime from ever polling the connections it has spawned.
+            let mut senders = Vec::new();
+            let mut worker_threads = Vec::new();
+            for (i, core_id) in core_ids.into_iter().enumerate() {
+                let (tx, mut rx) = mpsc::unbounded::<std::net::TcpStream>();
+                senders.push(tx);
+                worker_threads.push(spawn_worker(i, core_id, num_cores, shutdown_timeout, db.clone(), move |db| async move {
+                    while let Some(stream) = rx.next().await {
+                        serve(TcpStream::from_std(stream).unwrap(), db.clone(), pgwire_config);
+                    }
+                }));
+            }
+
+            let listener_db = db.clone();
+            let listener_thread = std::thread::spawn(move || {
+                let listener = TcpListener::bind(&listen_addr).unwrap();
+                log::info!("Server is listening on: {}", listen_addr);
 
-        loop {
-            match listener.accept() {
-                Ok((stream, addr)) => {
-                    println!("Accepted new connection from {}", addr);
-                    // Send the connection to the worker thread
-                    if tx.send(stream).is_err() {
-                        eprintln!(
-                            "Failed to distribute connection to worker thread, channel closed."
-                        );
+                for next_worker in (0..senders.len()).cycle() {
+                    let accepted = listener.accept();
+                    if listener_db.shutdown().is_requested() {
                         break;
                     }
+                    match accepted {
+                        Ok((stream, addr)) => {
+                            log::debug!("Accepted new connection from {}", addr);
+                            // Hand the connections out to the workers in turn
+                            if senders[next_worker].unbounded_send(stream).is_err() {
+                                log::error!(
+                                    "Failed to distribute connection to worker thread, channel closed."
+                                );
+                                break;
+                            }
+                        }
+                        Err(e) => {
+                            log::warn!("Failed to accept connection: {}", e);
+                        }
+                    }
                 }
-                Err(e) => {
-                    eprintln!("Failed to accept connection: {}", e);
-                }
+            });
+
+            // The listener stops on shutdown or when it can no longer hand out
+            // connections; the workers drain once their channel is closed.
+            listener_thread.join().unwrap();
+            for handle in worker_threads {
+                handle.join().unwrap();
             }
         }
-    });
+    }
 
-    listener_thread.join().unwrap();
-    for handle in worker_threads {
-        handle.join().unwrap();
+    // Every worker has stopped, so nothing can dirty a page any more.
+    let mut rt = monoio::RuntimeBuilder::<monoio::FusionDriver>::new()
+        .enable_timer()
+        .build()
+        .unwrap();
+    if let Err(e) = rt.block_on(db.close()) {
+        log::error!("Failed to flush database: {}", e);
+        std::process::exit(1);
     }
+    log::info!("Database shut down cleanly");
 }
 
-async fn handle_connection(mut stream: TcpStream, db: Arc<Database>) {
-    let disk_manager = Arc::new(DiskManager::new("database.db").await.unwrap());
-    let mut buffer = BytesMut::with_capacity(1024);
-    loop {
-        let disk_manager = disk_manager.clone();
-        let len_buffer = vec![0u8; LEN_BYTES];
-        let (res, len_buffer) = stream.read_exact(len_buffer).await;
-        if res.is_err() {
-            eprintln!("Failed to read length: {}", res.err().unwrap());
-            break;
+/// 启动一个绑定到 `core_id` 的 worker 线程，在它自己的 runtime 上运行后台任务和 `run`。
+/// `run` 返回后等待连接结束，超过 `shutdown_timeout` 的连接随 runtime 一起被丢弃。
+fn spawn_worker<F, Fut>(
+    i: usize,
+    core_id: CoreId,
+    num_cores: usize,
+    shutdown_timeout: Duration,
+    db: Arc<Database>,
+    run: F,
+) -> JoinHandle<()>
+where
+    F: FnOnce(Arc<Database>) -> Fut + Send + 'static,
+    Fut: Future<Output = ()>,
+{
+    std::thread::spawn(move || {
+        if !core_affinity::set_for_current(core_id) {
+            log::warn!("Failed to set core affinity for worker {}", i);
+        } else {
+            log::info!("Worker {} pinned to core {:?}", i, core_id.id);
         }
 
-        let len = u32::from_be_bytes(len_buffer.try_into().unwrap()) as usize;
-        buffer.resize(len, 0);
-
-        let (res, res_buffer) = stream.read_exact(buffer).await;
-        if res.is_err() {
-            eprintln!("Failed to read SQL: {}", res.err().unwrap());
-            break;
-        }
+        let mut rt = monoio::RuntimeBuilder::<monoio::FusionDriver>::new()
+            .enable_all()
+            .build()
+            .unwrap();
 
-        let sql = match String::from_utf8(res_buffer.to_vec()) {
-            Ok(s) => s,
-            Err(e) => {
-                eprintln!("Failed to read SQL, invalid UTF-8 sequence: {}", e);
-                break;
+        rt.block_on(async move {
+            db.start_background_tasks(i, num_cores);
+            run(db.clone()).await;
+            let remaining = db.shutdown().drain(shutdown_timeout).await;
+            if remaining > 0 && i == 0 {
+                log::warn!(
+                    "{} connections still busy after {:?}, closing them",
+                    remaining,
+                    shutdown_timeout
+                );
             }
-        };
-        println!("Received SQL: {}", sql);
-
-        let result = db.run_statement(&sql, disk_manager).await;
-
-        let encoded_result = bincode::encode_to_vec(result, bincode::config::standard()).unwrap();
-        let result_len = (encoded_result.len() as u32).to_be_bytes();
-
-        let mut response = BytesMut::new();
-        response.put_slice(&result_len);
-        response.put_slice(&encoded_result);
+        })
+    })
+}
 
-        if let (Err(e), _) = stream.write_all(response).await {
-            eprintln!("Failed to write response: {}", e);
-            return;
+/// 在本 worker 自己的 `SO_REUSEPORT` 套接字上接受连接
+async fn accept_loop(worker: usize, listen_addr: String, db: Arc<Database>, pgwire_config: PgwireConfig) {
+    let opts = ListenerOpts::new().reuse_port(true);
+    let listener = match monoio::net::TcpListener::bind_with_config(&listen_addr, &opts) {
+        Ok(listener) => listener,
+        Err(e) => {
+            // A worker without a listener would silently shrink the server; fail loudly
+            // so the operator can fix the address or fall back to --accept=dispatch.
+            log::error!("Worker {} failed to listen on {}: {}", worker, listen_addr, e);
+            std::process::exit(1);
+        }
+    };
+    loop {
+        let accepted = monoio::select! {
+            accepted = listener.accept() => accepted,
+            _ = db.shutdown().requested() => return,
+        };
+        match accepted {
+            Ok((stream, addr)) => {
+                log::debug!("Worker {} accepted new connection from {}", worker, addr);
+                serve(stream, db.clone(), pgwire_config);
+            }
+            Err(e) => log::warn!("Failed to accept connection: {}", e),
         }
-
-        buffer = res_buffer;
     }
 }
+
+/// 在当前 runtime 上处理一个连接
+fn serve(stream: TcpStream, db: Arc<Database>, config: PgwireConfig) {
+    monoio::spawn(async move {
+        if let Err(e) = pgwire::serve_connection(stream, db, config).await {
+            log::warn!("Connection closed with error: {}", e);
+        }
+    });
+}
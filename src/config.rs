@@ -0,0 +1,363 @@
+//! 服务端、交互式终端和客户端共用的配置。
+//!
+//! 优先级从低到高：默认值、TOML 配置文件、`RINGDB_<参数名>` 环境变量、命令行参数。
+//! 配置文件由 `--config FILE` 或 `RINGDB_CONFIG` 指定，其中的键与参数名相同：
+//!
+//! ```toml
+//! listen_addr = "0.0.0.0:5432"
+//! data_dir = "/var/lib/ringdb"
+//! pool_size = 1024
+//! synchronous_commit = "normal"
+//! ```
+//!
+//! 命令行参数写作 `--pool-size 1024` 或 `--pool-size=1024`。无效的设置在启动时直接报错。
+use std::{
+    net::ToSocketAddrs,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+use log::LevelFilter;
+
+use crate::{
+    executor::autovacuum::AutovacuumConfig,
+    pgwire::PgwireConfig,
+    storage::{bgwriter::BgWriterConfig, wal::WalConfig},
+};
+
+/// 数据目录中的数据文件名，日志和控制文件以它为前缀
+const DB_FILE_NAME: &str = "database.db";
+/// 缓冲池至少需要的帧数，插入时会同时固定数据页和 FSM 页
+const MIN_POOL_SIZE: usize = 8;
+/// 消息长度的下限，太小时连普通的查询都发不过来
+const MIN_MESSAGE_SIZE: usize = 1024;
+
+/// 命令行帮助中的参数说明
+pub const USAGE: &str = "\
+Options (also settable in the config file or as RINGDB_<NAME> environment variables):
+  --config FILE              TOML config file
+  --listen-addr ADDR         address the server listens on / the client connects to [127.0.0.1:5432]
+  --data-dir DIR             directory holding the data, WAL and control files [.]
+  --pool-size N              buffer pool size in pages [64]
+  --workers N                worker cores for the server, 0 = one per core [0]
+  --accept MODE              reuseport | dispatch [reuseport]
+  --synchronous-commit MODE  off | normal | full [full]
+  --commit-delay MICROS      group commit delay in microseconds [0]
+  --checkpoint-interval SECS seconds between checkpoints [300]
+  --bgwriter-delay MILLIS    milliseconds between background writer rounds [200]
+  --autovacuum BOOL          run VACUUM periodically in the background [false]
+  --autovacuum-naptime SECS  seconds between autovacuum runs [60]
+  --max-message-size BYTES   largest protocol message a client may send [67108864]
+  --auth-method METHOD       trust | scram-sha-256 [scram-sha-256]
+  --user NAME                user the server creates at startup / the client logs in as [ringdb]
+  --password PASSWORD        password of that user, better passed as RINGDB_PASSWORD
+  --shutdown-timeout SECS    seconds to let running statements finish on shutdown [30]
+  --log-level LEVEL          off | error | warn | info | debug | trace [info]";
+
+/// 服务端把连接分配给 worker 的方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AcceptMode {
+    /// 每个 worker 用 `SO_REUSEPORT` 各自 accept，内核做负载均衡
+    #[default]
+    ReusePort,
+    /// 单独的监听线程 accept，再通过 channel 分发给 worker
+    Dispatch,
+}
+
+impl FromStr for AcceptMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "reuseport" => Ok(AcceptMode::ReusePort),
+            "dispatch" => Ok(AcceptMode::Dispatch),
+            _ => Err(format!("invalid value for accept: '{}' (expected reuseport or dispatch)", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub listen_addr: String,
+    pub data_dir: PathBuf,
+    /// 缓冲池的帧数
+    pub pool_size: usize,
+    /// 服务端使用的核数，0 表示每个核一个 worker
+    pub workers: usize,
+    pub accept: AcceptMode,
+    pub wal: WalConfig,
+    pub bgwriter: BgWriterConfig,
+    pub autovacuum: AutovacuumConfig,
+    pub pgwire: PgwireConfig,
+    /// 服务端启动时创建的用户，也是客户端登录使用的用户
+    pub user: String,
+    /// `user` 的口令。服务端没有设置口令时不创建用户
+    pub password: Option<String>,
+    /// 关闭时等待连接结束的时间，超时后直接断开
+    pub shutdown_timeout: Duration,
+    pub log_level: LevelFilter,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            listen_addr: "127.0.0.1:5432".to_string(),
+            data_dir: PathBuf::from("."),
+            pool_size: 64,
+            workers: 0,
+            accept: AcceptMode::default(),
+            wal: WalConfig::default(),
+            bgwriter: BgWriterConfig::default(),
+            autovacuum: AutovacuumConfig::default(),
+            pgwire: PgwireConfig::default(),
+            user: "ringdb".to_string(),
+            password: None,
+            shutdown_timeout: Duration::from_secs(30),
+            log_level: LevelFilter::Info,
+        }
+    }
+}
+
+/// 可以设置的参数名
+const KEYS: &[&str] = &[
+    "listen_addr",
+    "data_dir",
+    "pool_size",
+    "workers",
+    "accept",
+    "synchronous_commit",
+    "commit_delay",
+    "checkpoint_interval",
+    "bgwriter_delay",
+    "autovacuum",
+    "autovacuum_naptime",
+    "max_message_size",
+    "auth_method",
+    "user",
+    "password",
+    "shutdown_timeout",
+    "log_level",
+];
+
+fn parse_number<T: FromStr>(key: &str, value: &str) -> Result<T, String> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid value for {}: '{}' (expected a non-negative integer)", key, value))
+}
+
+impl Config {
+    /// 数据文件的路径
+    pub fn db_file(&self) -> String {
+        self.data_dir.join(DB_FILE_NAME).to_string_lossy().into_owned()
+    }
+
+    /// 按优先级合并配置文件、环境变量和命令行参数（不含程序名）并检查结果
+    pub fn load(
+        args: impl IntoIterator<Item = String>,
+        env: impl Fn(&str) -> Option<String>,
+    ) -> Result<Self, String> {
+        let flags = parse_flags(args)?;
+        let mut config = Config::default();
+
+        let config_file = flags
+            .iter()
+            .rev()
+            .find(|(key, _)| key == "config")
+            .map(|(_, value)| value.clone())
+            .or_else(|| env("RINGDB_CONFIG"));
+        if let Some(path) = config_file {
+            config.apply_file(Path::new(&path))?;
+        }
+
+        for key in KEYS {
+            let name = format!("RINGDB_{}", key.to_uppercase());
+            if let Some(value) = env(&name) {
+                config.set(key, &value).map_err(|e| format!("{} (from {})", e, name))?;
+            }
+        }
+
+        for (key, value) in flags.iter().filter(|(key, _)| key != "config") {
+            config.set(key, value).map_err(|e| format!("{} (from --{})", e, key.replace('_', "-")))?;
+        }
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn apply_file(&mut self, path: &Path) -> Result<(), String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read config file {}: {}", path.display(), e))?;
+        let table: toml::Table =
+            toml::from_str(&text).map_err(|e| format!("Invalid config file {}: {}", path.display(), e))?;
+        for (key, value) in table {
+            let value = match value {
+                toml::Value::String(s) => s,
+                toml::Value::Integer(i) => i.to_string(),
+                toml::Value::Boolean(b) => b.to_string(),
+                _ => {
+                    return Err(format!(
+                        "{}: invalid value for {}: expected a string, integer or boolean",
+                        path.display(),
+                        key
+                    ));
+                }
+            };
+            self.set(&key, &value).map_err(|e| format!("{}: {}", path.display(), e))?;
+        }
+        Ok(())
+    }
+
+    /// 设置一个参数，参数名中的 `-` 与 `_` 等价
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key.replace('-', "_").as_str() {
+            "listen_addr" => self.listen_addr = value.to_string(),
+            "data_dir" => self.data_dir = PathBuf::from(value),
+            "pool_size" => self.pool_size = parse_number(key, value)?,
+            "workers" => self.workers = parse_number(key, value)?,
+            "accept" => self.accept = value.parse()?,
+            "synchronous_commit" => self.wal.synchronous_commit = value.parse()?,
+            "commit_delay" => self.wal.commit_delay = Duration::from_micros(parse_number(key, value)?),
+            "checkpoint_interval" => {
+                self.bgwriter.checkpoint_interval = Duration::from_secs(parse_number(key, value)?)
+            }
+            "bgwriter_delay" => self.bgwriter.delay = Duration::from_millis(parse_number(key, value)?),
+            "autovacuum" => {
+                self.autovacuum.enabled = match value.to_lowercase().as_str() {
+                    "true" | "on" | "1" => true,
+                    "false" | "off" | "0" => false,
+                    _ => return Err(format!("invalid value for autovacuum: '{}' (expected on or off)", value)),
+                }
+            }
+            "autovacuum_naptime" => self.autovacuum.naptime = Duration::from_secs(parse_number(key, value)?),
+            "max_message_size" => self.pgwire.max_message_size = parse_number(key, value)?,
+            "auth_method" => self.pgwire.auth_method = value.parse()?,
+            "user" => self.user = value.to_string(),
+            "password" => self.password = Some(value.to_string()),
+            "shutdown_timeout" => self.shutdown_timeout = Duration::from_secs(parse_number(key, value)?),
+            "log_level" => {
+                self.log_level = value.parse().map_err(|_| {
+                    format!(
+                        "invalid value for log_level: '{}' (expected off, error, warn, info, debug or trace)",
+                        value
+                    )
+                })?
+            }
+            _ => return Err(format!("unrecognized configuration parameter '{}'", key)),
+        }
+        Ok(())
+    }
+
+    /// 检查参数之间和参数取值范围的约束
+    fn validate(&self) -> Result<(), String> {
+        if self.listen_addr.to_socket_addrs().is_err() {
+            return Err(format!(
+                "invalid value for listen_addr: '{}' (expected HOST:PORT)",
+                self.listen_addr
+            ));
+        }
+        if self.pool_size < MIN_POOL_SIZE {
+            return Err(format!(
+                "invalid value for pool_size: {} (must be at least {})",
+                self.pool_size, MIN_POOL_SIZE
+            ));
+        }
+        if self.pgwire.max_message_size < MIN_MESSAGE_SIZE {
+            return Err(format!(
+                "invalid value for max_message_size: {} (must be at least {})",
+                self.pgwire.max_message_size, MIN_MESSAGE_SIZE
+            ));
+        }
+        if self.user.is_empty() {
+            return Err("invalid value for user: must not be empty".to_string());
+        }
+        if self.password.as_deref() == Some("") {
+            return Err("invalid value for password: must not be empty".to_string());
+        }
+        if self.bgwriter.checkpoint_interval.is_zero() {
+            return Err("invalid value for checkpoint_interval: must be at least 1 second".to_string());
+        }
+        if self.autovacuum.naptime.is_zero() {
+            return Err("invalid value for autovacuum_naptime: must be at least 1 second".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// 把 `--name value` 和 `--name=value` 解析成 (参数名, 值)，参数名中的 `-` 换成 `_`
+fn parse_flags(args: impl IntoIterator<Item = String>) -> Result<Vec<(String, String)>, String> {
+    let mut flags = Vec::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let Some(flag) = arg.strip_prefix("--") else {
+            return Err(format!("unexpected argument '{}'", arg));
+        };
+        let (name, value) = match flag.split_once('=') {
+            Some((name, value)) => (name.to_string(), value.to_string()),
+            None => {
+                let value = args.next().ok_or_else(|| format!("missing value for --{}", flag))?;
+                (flag.to_string(), value)
+            }
+        };
+        flags.push((name.replace('-', "_"), value));
+    }
+    Ok(flags)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pgwire::AuthMethod;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_file_env_and_flags_in_order() {
+        let path = std::env::temp_dir().join(format!("ringdb_config_{}.toml", std::process::id()));
+        std::fs::write(
+            &path,
+            "pool_size = 256\nworkers = 2\nsynchronous_commit = \"normal\"\nautovacuum = true\n",
+        )
+        .unwrap();
+        let file = path.to_string_lossy().into_owned();
+        let env = |name: &str| match name {
+            "RINGDB_CONFIG" => Some(file.clone()),
+            "RINGDB_WORKERS" => Some("3".to_string()),
+            "RINGDB_DATA_DIR" => Some("/tmp/ringdb".to_string()),
+            "RINGDB_PASSWORD" => Some("secret".to_string()),
+            _ => None,
+        };
+
+        let config = Config::load(args(&["--workers", "4", "--commit-delay=200"]), env).unwrap();
+        assert_eq!(config.pool_size, 256);
+        assert_eq!(config.workers, 4);
+        assert_eq!(config.data_dir, PathBuf::from("/tmp/ringdb"));
+        assert_eq!(config.db_file(), "/tmp/ringdb/database.db");
+        assert_eq!(config.wal.commit_delay, Duration::from_micros(200));
+        assert!(config.autovacuum.enabled);
+        assert_eq!(config.user, "ringdb");
+        assert_eq!(config.password.as_deref(), Some("secret"));
+        assert_eq!(config.pgwire.auth_method, AuthMethod::ScramSha256);
+        let config = Config::load(args(&["--auth-method=trust", "--user=alice"]), env).unwrap();
+        assert_eq!(config.pgwire.auth_method, AuthMethod::Trust);
+        assert_eq!(config.user, "alice");
+        assert!(Config::load(args(&["--auth-method=md5"]), env).is_err());
+
+        let error = Config::load(args(&["--pool-size", "many"]), env).unwrap_err();
+        assert_eq!(
+            error,
+            "invalid value for pool_size: 'many' (expected a non-negative integer) (from --pool-size)"
+        );
+        assert!(Config::load(args(&["--pool-size=2"]), env).unwrap_err().contains("at least"));
+        assert!(Config::load(args(&["--listen-addr=nowhere"]), env).is_err());
+        assert!(Config::load(args(&["--shared-buffers=1"]), env).unwrap_err().contains("unrecognized"));
+        assert!(Config::load(args(&["--log-level"]), env).unwrap_err().contains("missing value"));
+
+        std::fs::write(&path, "pool_size = [1]\n").unwrap();
+        assert!(Config::load(args(&[]), env).is_err());
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,44 @@
+//! 自动清理：后台周期性地对所有表执行 VACUUM。
+//!
+//! 没有已删除元组的页只被读取一次，所以一轮清理的主要开销是扫描。
+use std::{sync::Arc, time::Duration};
+
+use crate::{
+    executor::catalog::{CatalogRef, TableInfo},
+    storage::{buffer_pool::BufferPoolManager, vacuum::vacuum_table},
+};
+
+/// 自动清理的配置
+#[derive(Debug, Clone, Copy)]
+pub struct AutovacuumConfig {
+    /// 是否启动自动清理任务
+    pub enabled: bool,
+    /// 两轮清理之间的间隔
+    pub naptime: Duration,
+}
+
+impl Default for AutovacuumConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            naptime: Duration::from_secs(60),
+        }
+    }
+}
+
+/// 自动清理任务，只应在一个 runtime 上运行
+pub async fn run_autovacuum(bpm: Arc<BufferPoolManager>, catalog: CatalogRef, config: AutovacuumConfig) {
+    loop {
+        monoio::time::sleep(config.naptime).await;
+        let tables: Vec<TableInfo> = catalog.lock().unwrap().tables().into_iter().cloned().collect();
+        for table in tables {
+            match vacuum_table(&bpm, table.fsm_page_id, &table.fsm_index, &table.truncate_lock).await {
+                Ok(stats) if stats.tuples_removed > 0 || stats.pages_freed > 0 => {
+                    log::info!("Autovacuum of '{}': {}", table.name, stats)
+                }
+                Ok(_) => {}
+                Err(e) => log::error!("Autovacuum of '{}' failed: {}", table.name, e),
+            }
+        }
+    }
+}
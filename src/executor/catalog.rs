@@ -1,9 +1,28 @@
-use crate::sql::ast::Column;
+//! 系统目录：表和用户。
+//!
+//! 目录保存在系统页 `CATALOG_PAGE_ID` 中：这一页从不分配给表，它唯一的元组是指向一条溢出页链的指针，
+//! 链中是 bincode 编码的目录内容。每次修改目录之后 `save` 写一条新链、改指针、释放旧链，
+//! 这些页的修改和其它页一样写入 WAL，所以目录随提交一起持久化，重启时由 `load` 读回。
+use crate::{
+    auth::{self, ScramVerifier},
+    sql::ast::Column,
+    storage::{
+        buffer_pool::BufferPoolManager,
+        fsm::FsmIndex,
+        overflow::{free_overflow_chain, read_overflow_chain, write_overflow_chain},
+        page::{PAGE_SIZE, Page, PageId, TupleData},
+    },
+};
+use async_lock::RwLock;
+use bincode::{Decode, Encode};
 use std::{
     collections::HashMap,
     sync::{Arc, Mutex},
 };
 
+/// 保存目录的系统页。`DiskManager` 从不分配页 0
+pub const CATALOG_PAGE_ID: PageId = 0;
+
 // #[derive(Debug, Clone)]
 // pub struct Column {
 //     name: String,
@@ -25,17 +44,96 @@ impl Schema {
 pub struct TableInfo {
     pub name: String,
     pub schema: Schema,
+    /// 表的 FSM 页链首页，也是找到表中所有数据页的入口
+    pub fsm_page_id: PageId,
+    /// FSM 在内存中的索引，所有会话共享
+    pub fsm_index: Arc<FsmIndex>,
+    /// 插入和扫描持有共享锁；VACUUM 释放页时持有排他锁
+    pub truncate_lock: Arc<RwLock<()>>,
+}
+
+/// 可以登录的用户
+#[derive(Debug, Clone, Encode, Decode)]
+pub struct UserInfo {
+    pub name: String,
+    /// 加盐的口令校验信息，不保存口令本身
+    pub verifier: ScramVerifier,
+    /// 只有超级用户（启动时配置的用户）可以创建用户
+    pub superuser: bool,
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug)]
 pub struct Catalog {
     tables: HashMap<String, TableInfo>,
+    users: HashMap<String, UserInfo>,
     next_table_id: usize,
+    /// 为不存在的用户派生 mock 校验信息的密钥，见 [`ScramVerifier::mock`]
+    mock_auth_nonce: Vec<u8>,
+}
+
+impl Default for Catalog {
+    fn default() -> Self {
+        Self {
+            tables: HashMap::new(),
+            users: HashMap::new(),
+            next_table_id: 0,
+            mock_auth_nonce: auth::new_mock_auth_nonce(),
+        }
+    }
+}
+
+/// 写入系统页的目录内容
+#[derive(Encode, Decode)]
+struct CatalogImage {
+    next_table_id: u64,
+    tables: Vec<TableImage>,
+    users: Vec<UserInfo>,
+    mock_auth_nonce: Vec<u8>,
+}
+
+#[derive(Encode, Decode)]
+struct TableImage {
+    name: String,
+    columns: Vec<Column>,
+    fsm_page_id: PageId,
 }
 
 impl Catalog {
+    fn image(&self) -> CatalogImage {
+        CatalogImage {
+            next_table_id: self.next_table_id as u64,
+            tables: self
+                .tables()
+                .into_iter()
+                .map(|table| TableImage {
+                    name: table.name.clone(),
+                    columns: table.schema.columns.clone(),
+                    fsm_page_id: table.fsm_page_id,
+                })
+                .collect(),
+            users: self.users.values().cloned().collect(),
+            mock_auth_nonce: self.mock_auth_nonce.clone(),
+        }
+    }
 
-    pub fn create_table(&mut self, name: String, columns: Vec<Column>) -> Result<(), String> {
+    fn from_image(image: CatalogImage) -> Self {
+        let mut catalog = Catalog::default();
+        for table in image.tables {
+            // Names in a saved catalog are unique.
+            let _ = catalog.create_table(table.name, table.columns, table.fsm_page_id);
+        }
+        catalog.next_table_id = image.next_table_id as usize;
+        catalog.users = image.users.into_iter().map(|user| (user.name.clone(), user)).collect();
+        catalog.mock_auth_nonce = image.mock_auth_nonce;
+        catalog
+    }
+
+    pub fn create_table(
+        &mut self,
+        name: String,
+        columns: Vec<Column>,
+        fsm_page_id: PageId,
+    ) -> Result<(), String> {
         if self.tables.contains_key(&name) {
             return Err(format!("Table '{}' already exists", name));
         }
@@ -43,6 +141,9 @@ impl Catalog {
         let table_info = TableInfo {
             name: name.clone(),
             schema,
+            fsm_page_id,
+            fsm_index: Arc::default(),
+            truncate_lock: Arc::new(RwLock::new(())),
         };
         self.tables.insert(name, table_info);
         self.next_table_id += 1;
@@ -52,6 +153,99 @@ impl Catalog {
     pub fn get_table(&self, name: &str) -> Option<&TableInfo> {
         self.tables.get(name)
     }
+
+    pub fn create_user(&mut self, name: String, verifier: ScramVerifier) -> Result<(), String> {
+        if self.users.contains_key(&name) {
+            return Err(format!("User '{}' already exists.", name));
+        }
+        self.users.insert(
+            name.clone(),
+            UserInfo {
+                name,
+                verifier,
+                superuser: false,
+            },
+        );
+        Ok(())
+    }
+
+    /// 创建用户，用户已经存在时替换它的口令和权限
+    pub fn set_user(&mut self, name: String, verifier: ScramVerifier, superuser: bool) {
+        self.users.insert(
+            name.clone(),
+            UserInfo {
+                name,
+                verifier,
+                superuser,
+            },
+        );
+    }
+
+    pub fn get_user(&self, name: &str) -> Option<&UserInfo> {
+        self.users.get(name)
+    }
+
+    /// 不存在的用户 `name` 在认证时使用的校验信息
+    pub fn mock_verifier(&self, name: &str) -> ScramVerifier {
+        ScramVerifier::mock(&self.mock_auth_nonce, name)
+    }
+
+    /// 所有表，按表名排序
+    pub fn tables(&self) -> Vec<&TableInfo> {
+        let mut tables: Vec<_> = self.tables.values().collect();
+        tables.sort_by(|a, b| a.name.cmp(&b.name));
+        tables
+    }
 }
 
 pub type CatalogRef = Arc<Mutex<Catalog>>;
+
+fn decode_catalog_page(data: &[u8]) -> Result<Page, String> {
+    Page::from_bytes(data[..PAGE_SIZE].try_into().unwrap())
+        .map_err(|e| format!("Catalog page {} is corrupted: {}", CATALOG_PAGE_ID, e))
+}
+
+/// 从系统页读取目录。从未保存过目录时写入并返回一个空目录，调用者需要提交 WAL
+pub async fn load(bpm: &Arc<BufferPoolManager>) -> Result<CatalogRef, String> {
+    let page = decode_catalog_page(&bpm.fetch_page(CATALOG_PAGE_ID).await?)?;
+    let Some(TupleData::Overflow { first_page_id, len }) = page.get_tuple(0) else {
+        // Save the new catalog right away so its mock authentication nonce
+        // stays the same across restarts.
+        let catalog = CatalogRef::default();
+        save(&catalog, bpm).await?;
+        return Ok(catalog);
+    };
+    let data = read_overflow_chain(bpm, first_page_id, len).await?;
+    let (image, _) = bincode::decode_from_slice(&data, bincode::config::standard())
+        .map_err(|e| format!("Failed to decode catalog: {}", e))?;
+    Ok(Arc::new(Mutex::new(Catalog::from_image(image))))
+}
+
+/// 把目录的当前内容写入系统页，在修改目录之后调用。
+/// 调用者需要像其它写操作一样提交 WAL，目录才在崩溃之后仍然存在。
+///
+/// 每次都重写整条链，所以一次 DDL 的开销与整个目录的大小成正比（大约每 8KB 一页）。
+/// 只覆盖变化的页做不到原子：日志可能在这些页的镜像之间被截断，重放出半新半旧的目录。
+/// 写完新链再改系统页中的指针，重放的结果要么是旧目录、要么是新目录。
+/// 目录大到这成为问题时，应改为按行存储的系统表（见 README 中的路线图）。
+pub async fn save(catalog: &CatalogRef, bpm: &Arc<BufferPoolManager>) -> Result<(), String> {
+    // The latch on the catalog page orders concurrent saves, so the last one
+    // to take it writes the newest snapshot.
+    let mut page_write_guard = bpm.fetch_page_mut(CATALOG_PAGE_ID).await?;
+    let old_chain = match decode_catalog_page(&page_write_guard)?.get_tuple(0) {
+        Some(TupleData::Overflow { first_page_id, .. }) => Some(first_page_id),
+        _ => None,
+    };
+    let data = bincode::encode_to_vec(catalog.lock().unwrap().image(), bincode::config::standard())
+        .map_err(|e| format!("Failed to encode catalog: {}", e))?;
+    let first_page_id = write_overflow_chain(bpm, &data).await?;
+    let mut page = Page::from_bytes([0; PAGE_SIZE]).unwrap();
+    page.insert_overflow_pointer(first_page_id, data.len() as u32);
+    page_write_guard.copy_from_slice(&page.to_bytes());
+    drop(page_write_guard);
+
+    if let Some(old_chain) = old_chain {
+        free_overflow_chain(bpm, old_chain).await?;
+    }
+    Ok(())
+}
@@ -1,83 +1,165 @@
 use crate::{
-    executor::{catalog::CatalogRef, ExecutionResult, Executor, Tuple},
+    auth::ScramVerifier,
+    executor::{
+        catalog::{self, CatalogRef, Schema, TableInfo},
+        row_format::{decode_column, decode_columns, encode_row},
+        ExecutionResult, Executor, RowSource, RowStream, Tuple, create_executor, param_types,
+    },
+    session::{CancelToken, PreparedStatement, Session, SessionRegistry},
     sql::ast,
     storage::{
-        buffer_pool::BufferPoolManager, disk::DiskManager, page::{Page, PAGE_SIZE}
+        access_strategy::BufferAccessStrategy,
+        buffer_pool::BufferPoolManager,
+        disk::DiskManager,
+        fsm,
+        overflow::{read_overflow_chain, write_overflow_chain},
+        page::{Page, PageId, TupleData, OVERFLOW_POINTER_SIZE, PAGE_SIZE, SLOT_OVERHEAD, TOAST_THRESHOLD},
+        vacuum::{vacuum_table, VacuumStats},
     },
 };
 use async_trait::async_trait;
-use futures::{stream::FuturesUnordered, StreamExt};
-use std::sync::Arc;
+use std::{collections::VecDeque, sync::Arc, time::UNIX_EPOCH};
 
 pub struct CreateTableExecutor {
     pub(crate) table_name: String,
     pub(crate) columns: Vec<ast::Column>,
     pub(crate) catalog: CatalogRef,
+    pub(crate) bpm: Arc<BufferPoolManager>,
 }
 
 #[async_trait(?Send)]
 impl Executor for CreateTableExecutor {
     async fn execute(self: Box<Self>) -> Result<ExecutionResult, String> {
-        let mut catalog = self.catalog.lock().unwrap();
-        if catalog
-            .create_table(self.table_name.clone(), self.columns)
-            .is_ok()
+        let exists = || Err(format!("Table '{}' already exists.", self.table_name));
+        if self.catalog.lock().unwrap().get_table(&self.table_name).is_some() {
+            return exists();
+        }
+
+        let fsm_page_id = fsm::create(&self.bpm).await?;
+        let created = self
+            .catalog
+            .lock()
+            .unwrap()
+            .create_table(self.table_name.clone(), self.columns, fsm_page_id);
+        if created.is_err() {
+            // Lost a race with a concurrent CREATE TABLE of the same name.
+            let _ = self.bpm.delete_page(fsm_page_id).await;
+            return exists();
+        }
+        catalog::save(&self.catalog, &self.bpm).await?;
+        Ok(ExecutionResult::Message(format!(
+            "Table '{}' created.",
+            self.table_name
+        )))
+    }
+}
+
+pub struct CreateUserExecutor {
+    pub(crate) name: String,
+    pub(crate) password: String,
+    /// 执行语句的用户，没有登录的本地会话为 `None`，不受权限限制
+    pub(crate) current_user: Option<String>,
+    pub(crate) catalog: CatalogRef,
+    pub(crate) bpm: Arc<BufferPoolManager>,
+}
+
+#[async_trait(?Send)]
+impl Executor for CreateUserExecutor {
+    async fn execute(self: Box<Self>) -> Result<ExecutionResult, String> {
+        let verifier = ScramVerifier::new(&self.password);
         {
-            Ok(ExecutionResult::Message(format!(
-                "Table '{}' created.",
-                self.table_name
-            )))
-        } else {
-            Err(format!("Table '{}' already exists.", self.table_name))
+            let mut catalog = self.catalog.lock().unwrap();
+            if let Some(user) = &self.current_user
+                && !catalog.get_user(user).is_some_and(|user| user.superuser)
+            {
+                return Err("permission denied to create role".to_string());
+            }
+            catalog.create_user(self.name.clone(), verifier)?;
         }
+        catalog::save(&self.catalog, &self.bpm).await?;
+        Ok(ExecutionResult::Message(format!("User '{}' created.", self.name)))
     }
 }
 
 pub struct InsertExecutor {
     pub table_name: String,
-    pub values: Vec<ast::Value>,
+    pub values: Vec<ast::Expr>,
     pub catalog: CatalogRef,
     pub bpm: Arc<BufferPoolManager>,
-    pub disk_manager: Arc<DiskManager>,
+    pub cancel: Arc<CancelToken>,
 }
 
 #[async_trait(?Send)]
 impl Executor for InsertExecutor {
     async fn execute(self: Box<Self>) -> Result<ExecutionResult, String> {
+        // Once the row is being written it is not interrupted, so nothing is left half done.
+        self.cancel.check()?;
         let table_info = {
             let catalog = self.catalog.lock().unwrap();
             catalog.get_table(&self.table_name).cloned()
-        };
-
-        if table_info.is_none() {
-            return Err(format!("Table '{}' not found.", self.table_name));
         }
+        .ok_or_else(|| format!("Table '{}' not found.", self.table_name))?;
 
-        let tuple = Tuple {
-            values: self.values,
-        };
-        // 使用 bincode 的标准函数进行序列化
-        let tuple_data = bincode::encode_to_vec(&tuple, bincode::config::standard())
-            .map_err(|e| e.to_string())?;
+        let values = self
+            .values
+            .into_iter()
+            .map(ast::Expr::into_value)
+            .collect::<Result<Vec<_>, _>>()?;
+        // 按照表的 Schema 编码为紧凑行格式
+        let tuple_data = encode_row(&table_info.schema, &values)
+            .map_err(|e| format!("Failed to insert into '{}': {}", self.table_name, e))?;
 
-        // 极简实现：总是尝试插入到第一页
-        let page_id = 0;
+        // 过大的元组存放到溢出页链中，表页里只保留指针
+        let toasted = tuple_data.len() > TOAST_THRESHOLD;
 
-        let mut page_write_guard = self
-            .bpm
-            .fetch_page_mut(page_id, self.disk_manager.clone())
-            .await
-            .map_err(|e| format!("Failed to fetch page {}: {:?}", page_id, e))?;
-        let frame_id = page_write_guard.frame_id();
-        let mut frames_mut = page_write_guard.get_mut();
-        let mut page = Page::from_bytes(frames_mut[frame_id].data[..PAGE_SIZE].try_into().unwrap());
-
-        if page.insert_tuple(&tuple_data).is_some() {
-            // Write the modified page back into the frame
-            frames_mut[frame_id].data.copy_from_slice(&page.to_bytes());
-            Ok(ExecutionResult::Message("1 row inserted.".to_string()))
-        } else {
-            Err("Failed to insert tuple: page is full.".to_string())
+        // 通过 FSM 找一个放得下的页，没有时给表扩展一个新页
+        let _truncate_guard = table_info.truncate_lock.read().await;
+        let needed = SLOT_OVERHEAD + if toasted { OVERFLOW_POINTER_SIZE } else { tuple_data.len() };
+        let fsm_page_id = table_info.fsm_page_id;
+        loop {
+            let (page_id, fsm_slot) = match fsm::find_page(&self.bpm, fsm_page_id, &table_info.fsm_index, needed).await? {
+                Some(found) => found,
+                None => {
+                    let page_id = self
+                        .bpm
+                        .new_page()
+                        .await
+                        .map_err(|e| format!("Failed to extend '{}': {}", self.table_name, e))?
+                        .page_id();
+                    let free = Page::from_bytes([0; PAGE_SIZE]).unwrap().free_space();
+                    (page_id, fsm::add_page(&self.bpm, fsm_page_id, &table_info.fsm_index, page_id, free).await?)
+                }
+            };
+
+            let mut page_write_guard = self
+                .bpm
+                .fetch_page_mut(page_id)
+                .await
+                .map_err(|e| format!("Failed to fetch page {}: {}", page_id, e))?;
+            let mut page = Page::from_bytes(page_write_guard[..PAGE_SIZE].try_into().unwrap())
+                .map_err(|e| format!("Page {} is corrupted: {}", page_id, e))?;
+
+            // The chain is written only once a page with room is latched, so an error
+            // cannot leave a chain behind that no tuple points to.
+            let slot_id = if page.free_space() < needed {
+                None
+            } else if toasted {
+                let first_page_id = write_overflow_chain(&self.bpm, &tuple_data).await?;
+                page.insert_overflow_pointer(first_page_id, tuple_data.len() as u32)
+            } else {
+                page.insert_tuple(&tuple_data)
+            };
+            if slot_id.is_some() {
+                // Write the modified page back into the frame
+                page_write_guard.copy_from_slice(&page.to_bytes());
+            }
+            drop(page_write_guard);
+
+            fsm::update(&self.bpm, &table_info.fsm_index, fsm_slot, page.free_space()).await?;
+            if slot_id.is_some() {
+                return Ok(ExecutionResult::Message("1 row inserted.".to_string()));
+            }
+            // The page filled up after the lookup; the map is corrected now, so look again.
         }
     }
 }
@@ -87,87 +169,458 @@ pub struct SequentialScanExecutor {
     pub columns: Vec<String>,
     pub catalog: CatalogRef,
     pub bpm: Arc<BufferPoolManager>,
-    pub disk_manager: Arc<DiskManager>,
+    pub cancel: Arc<CancelToken>,
 }
 
 #[async_trait(?Send)]
 impl Executor for SequentialScanExecutor {
     async fn execute(mut self: Box<Self>) -> Result<ExecutionResult, String> {
-        let _table_info = {
+        let table_info = {
             let catalog = self.catalog.lock().unwrap();
             catalog.get_table(&self.table_name).cloned()
         }
         .ok_or_else(|| format!("Table '{}' not found", self.table_name))?;
+        let schema = &table_info.schema;
+        let projection = self
+            .columns
+            .iter()
+            .map(|col| {
+                schema
+                    .get_col_idx(col)
+                    .ok_or_else(|| format!("Column '{}' not found in '{}'", col, self.table_name))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
 
         const PREFETCH_PAGES: usize = 16;
 
-        const TOTAL_PAGES: usize = 128; //TODO: HArdcore for now
-
-
-        // 1. Start the prefetching task
-        let mut result_tuples: Vec<Tuple> = Vec::new();
-        let (tx, mut rx) = local_sync::mpsc::bounded::channel(PREFETCH_PAGES);
-        let bpm_clone = self.bpm.clone();
-
-        let prefetch_handle = monoio::spawn(async move {
-            // let mut futures = Vec::new();
-            let mut tasks = FuturesUnordered::new();
-            for page_id in 0..TOTAL_PAGES {
-                let fetch_fut = bpm_clone.fetch_page(page_id as _, self.disk_manager.clone());
-                tasks.push(fetch_fut);
-
-                if tasks.len() >= PREFETCH_PAGES {
-                    // let page_guards = futures::future::join_all(futures.drain(..)).await;
-                    // for guard_result in page_guards {
-                    if let Some(guard_result) = tasks.next().await {
-                        match guard_result {
-                            Ok(guard) => {
-                                if tx.send(guard).await.is_err() {
-                                    return;
-                                }
-                            }
-                            Err(_) => {
-                                eprintln!("Failed to fetch page");
-                                return;
-                            }
-                        }
+        let page_ids = {
+            let _shared = table_info.truncate_lock.read().await;
+            fsm::table_pages(&self.bpm, table_info.fsm_page_id).await?
+        };
+        // 大表的扫描只在一个私有的小环中复用帧，避免冲掉共享缓冲池中的热点页。
+        // 每批取的页数不超过环的大小。
+        let strategy = BufferAccessStrategy::for_table(page_ids.len(), self.bpm.pool_size());
+        let prefetch_pages = strategy
+            .as_ref()
+            .map_or(PREFETCH_PAGES, |s| s.ring_size().clamp(1, PREFETCH_PAGES));
+
+        let columns = projection.iter().map(|&idx| schema.columns[idx].clone()).collect();
+        Ok(ExecutionResult::Data {
+            columns,
+            rows: RowStream::new(SeqScanStream {
+                bpm: self.bpm,
+                cancel: self.cancel,
+                projection,
+                table: table_info,
+                page_ids: page_ids.into(),
+                strategy,
+                prefetch_pages,
+            }),
+        })
+    }
+}
+
+/// 顺序扫描的结果，每批是一次合并读取的若干页上的行。
+///
+/// 每批只在取这一批时持有截断锁的共享锁和页的 pin，行复制出来之后全部释放，
+/// 所以暂停的门户既不挡住 VACUUM，也不占用缓冲池的帧
+struct SeqScanStream {
+    bpm: Arc<BufferPoolManager>,
+    cancel: Arc<CancelToken>,
+    table: TableInfo,
+    projection: Vec<usize>,
+    /// 还没有读的页
+    page_ids: VecDeque<PageId>,
+    strategy: Option<BufferAccessStrategy>,
+    prefetch_pages: usize,
+}
+
+#[async_trait(?Send)]
+impl RowSource for SeqScanStream {
+    async fn next_batch(&mut self) -> Result<Option<Vec<Tuple>>, String> {
+        self.cancel.check()?;
+        if self.page_ids.is_empty() {
+            return Ok(None);
+        }
+        // VACUUM cannot free pages or overflow chains while the batch is read.
+        let _shared = self.table.truncate_lock.read().await;
+        let mut batch = Vec::with_capacity(self.prefetch_pages);
+        while batch.len() < self.prefetch_pages
+            && let Some(page_id) = self.page_ids.pop_front()
+        {
+            // Pages that VACUUM removed since the scan started may now belong to anything.
+            if fsm::contains_page(&self.bpm, self.table.fsm_page_id, &self.table.fsm_index, page_id).await? {
+                batch.push(page_id);
+            }
+        }
+        // Copy the pages out, releasing their latches and pins before any
+        // overflow chains are fetched.
+        let pages = self
+            .bpm
+            .fetch_pages_with(&batch, self.strategy.as_ref())
+            .await
+            .map_err(|e| format!("Failed to fetch page: {}", e))?
+            .into_iter()
+            .map(|page_guard| {
+                Page::from_bytes(page_guard[..PAGE_SIZE].try_into().unwrap())
+                    .map_err(|e| format!("Page {} is corrupted: {}", page_guard.page_id(), e))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // Rows kept in overflow chains get a placeholder and are filled in afterwards.
+        let mut rows = Vec::new();
+        let mut overflow_pointers = Vec::new();
+        for page in &pages {
+            for i in 0..page.header.tuple_count {
+                match page.get_tuple(i) {
+                    Some(TupleData::Inline(tuple_data)) => {
+                        rows.push(Some(project_row(&self.table.schema, &self.projection, tuple_data)?));
+                    }
+                    Some(TupleData::Overflow { first_page_id, len }) => {
+                        overflow_pointers.push((rows.len(), first_page_id, len));
+                        rows.push(None);
                     }
+                    None => {}
                 }
             }
+        }
+
+        for (pos, first_page_id, len) in overflow_pointers {
+            let tuple_data = read_overflow_chain(&self.bpm, first_page_id, len).await?;
+            rows[pos] = Some(project_row(&self.table.schema, &self.projection, &tuple_data)?);
+        }
+        Ok(Some(rows.into_iter().flatten().collect()))
+    }
+}
 
-            // 处理剩余的 futures
-            while let Some(guard_result) = tasks.next().await {
-                match guard_result {
-                    Ok(guard) => {
-                        if tx.send(guard).await.is_err() {
-                            return;
-                        }
+/// 删除满足条件的行。元组只被标记为已删除，占用的空间和溢出页由 VACUUM 回收
+pub struct DeleteExecutor {
+    pub table_name: String,
+    pub filter: Option<(String, ast::Expr)>,
+    pub catalog: CatalogRef,
+    pub bpm: Arc<BufferPoolManager>,
+    pub cancel: Arc<CancelToken>,
+}
+
+#[async_trait(?Send)]
+impl Executor for DeleteExecutor {
+    async fn execute(self: Box<Self>) -> Result<ExecutionResult, String> {
+        // Like INSERT, a DELETE that has started runs to the end rather than stopping halfway.
+        self.cancel.check()?;
+        let table_info = {
+            let catalog = self.catalog.lock().unwrap();
+            catalog.get_table(&self.table_name).cloned()
+        }
+        .ok_or_else(|| format!("Table '{}' not found.", self.table_name))?;
+        let schema = &table_info.schema;
+        let filter = match self.filter {
+            Some((column, value)) => {
+                let col_idx = schema
+                    .get_col_idx(&column)
+                    .ok_or_else(|| format!("Column '{}' not found in '{}'", column, self.table_name))?;
+                let value = value.into_value()?;
+                match (&schema.columns[col_idx].data_type, &value) {
+                    (_, ast::Value::Null)
+                    | (ast::DataType::Int, ast::Value::Integer(_))
+                    | (ast::DataType::Varchar, ast::Value::String(_))
+                    | (ast::DataType::Bytea, ast::Value::Bytes(_)) => {}
+                    (data_type, value) => {
+                        return Err(format!(
+                            "Column '{}' has type {:?}, got value {:?}",
+                            column, data_type, value
+                        ));
+                    }
+                }
+                Some((col_idx, value))
+            }
+            None => None,
+        };
+
+        // VACUUM compacts pages under the shared lock as well, but only while holding the
+        // page latch, so slot numbers stay put while a page is checked here.
+        let _shared = table_info.truncate_lock.read().await;
+        let mut deleted = 0;
+        for page_id in fsm::table_pages(&self.bpm, table_info.fsm_page_id).await? {
+            let mut page_write_guard = self
+                .bpm
+                .fetch_page_mut(page_id)
+                .await
+                .map_err(|e| format!("Failed to fetch page {}: {}", page_id, e))?;
+            let mut page = Page::from_bytes(page_write_guard[..PAGE_SIZE].try_into().unwrap())
+                .map_err(|e| format!("Page {} is corrupted: {}", page_id, e))?;
+
+            let mut changed = false;
+            for slot in 0..page.header.tuple_count {
+                let column = match (&filter, page.get_tuple(slot)) {
+                    (_, None) => continue,
+                    (None, Some(_)) => None,
+                    (Some((col_idx, _)), Some(TupleData::Inline(tuple_data))) => {
+                        Some(decode_column(schema, tuple_data, *col_idx)?)
                     }
-                    Err(_) => {
-                        eprintln!("Failed to fetch page");
-                        return;
+                    (Some((col_idx, _)), Some(TupleData::Overflow { first_page_id, len })) => {
+                        let tuple_data = read_overflow_chain(&self.bpm, first_page_id, len).await?;
+                        Some(decode_column(schema, &tuple_data, *col_idx)?)
                     }
+                };
+                let matches = match (column, &filter) {
+                    (Some(column), Some((_, value))) => values_equal(&column, value),
+                    _ => true,
+                };
+                if matches && page.delete_tuple(slot) {
+                    changed = true;
+                    deleted += 1;
                 }
             }
-        });
+            // Pages without matching rows are left clean, so they are not written back or logged.
+            if changed {
+                page_write_guard.copy_from_slice(&page.to_bytes());
+            }
+        }
+        Ok(ExecutionResult::Modified {
+            message: format!("{} rows deleted.", deleted),
+            rows: deleted,
+        })
+    }
+}
+
+/// WHERE 中的相等比较。和 SQL 一样，NULL 不等于任何值
+fn values_equal(a: &ast::Value, b: &ast::Value) -> bool {
+    match (a, b) {
+        (ast::Value::Integer(a), ast::Value::Integer(b)) => a == b,
+        (ast::Value::String(a), ast::Value::String(b)) => a == b,
+        (ast::Value::Bytes(a), ast::Value::Bytes(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// 逐页读取磁盘上的数据并校验，不经过缓冲池
+pub struct VerifyDatabaseExecutor {
+    pub disk_manager: Arc<DiskManager>,
+    pub cancel: Arc<CancelToken>,
+}
+
+#[async_trait(?Send)]
+impl Executor for VerifyDatabaseExecutor {
+    async fn execute(self: Box<Self>) -> Result<ExecutionResult, String> {
+        let num_pages = self.disk_manager.num_pages();
+        let mut buffer = vec![0u8; PAGE_SIZE];
+        let mut corrupted = Vec::new();
+
+        for page_id in 0..num_pages {
+            self.cancel.check()?;
+            buffer.fill(0);
+            let (res, buf) = self.disk_manager.read_page(page_id, buffer).await;
+            buffer = buf;
+            res.map_err(|e| format!("Failed to read page {}: {}", page_id, e))?;
+            if let Err(e) = Page::verify(&buffer) {
+                corrupted.push(format!("page {}: {}", page_id, e));
+            }
+        }
 
+        if corrupted.is_empty() {
+            Ok(ExecutionResult::Message(format!(
+                "{} pages verified, no corruption found.",
+                num_pages
+            )))
+        } else {
+            Err(format!(
+                "{} of {} pages corrupted: {}",
+                corrupted.len(),
+                num_pages,
+                corrupted.join("; ")
+            ))
+        }
+    }
+}
 
-        // 2. Consume the prefetched pages
-        while let Some(page_guard) = rx.recv().await {
-            let frame_id = page_guard.frame_id();
-            let frames = page_guard.get();
-            let page = Page::from_bytes(frames[frame_id].data[..PAGE_SIZE].try_into().unwrap());
+/// 清理一张表，不指定表名时清理所有表
+pub struct VacuumExecutor {
+    pub table_name: Option<String>,
+    pub catalog: CatalogRef,
+    pub bpm: Arc<BufferPoolManager>,
+    pub cancel: Arc<CancelToken>,
+}
 
-            for i in 0..page.header.tuple_count {
-                if let Some(tuple_data) = page.get_tuple(i) {
-                    let (tuple, _) = bincode::decode_from_slice(tuple_data, bincode::config::standard()).map_err(|e| e.to_string())?;
-                    result_tuples.push(tuple);
-                }
+#[async_trait(?Send)]
+impl Executor for VacuumExecutor {
+    async fn execute(self: Box<Self>) -> Result<ExecutionResult, String> {
+        let tables: Vec<TableInfo> = {
+            let catalog = self.catalog.lock().unwrap();
+            match &self.table_name {
+                Some(name) => vec![catalog
+                    .get_table(name)
+                    .cloned()
+                    .ok_or_else(|| format!("Table '{}' not found.", name))?],
+                None => catalog.tables().into_iter().cloned().collect(),
             }
+        };
+
+        let mut stats = VacuumStats::default();
+        // A table being vacuumed is finished; cancellation takes effect between tables.
+        for table in tables {
+            self.cancel.check()?;
+            stats.merge(
+                vacuum_table(&self.bpm, table.fsm_page_id, &table.fsm_index, &table.truncate_lock)
+                    .await
+                    .map_err(|e| format!("Failed to vacuum '{}': {}", table.name, e))?,
+            );
         }
+        Ok(ExecutionResult::Message(format!("VACUUM: {}", stats)))
+    }
+}
 
-        prefetch_handle.await;
+/// 修改当前会话的设置
+pub struct SetExecutor<'a> {
+    pub name: String,
+    pub value: String,
+    pub session: &'a mut Session,
+}
 
-        Ok(ExecutionResult::Data(result_tuples))
+#[async_trait(?Send)]
+impl Executor for SetExecutor<'_> {
+    async fn execute(self: Box<Self>) -> Result<ExecutionResult, String> {
+        self.session.set(&self.name, &self.value)?;
+        Ok(ExecutionResult::Message("SET".to_string()))
     }
 }
+
+pub struct ShowExecutor<'a> {
+    pub name: String,
+    pub session: &'a Session,
+}
+
+#[async_trait(?Send)]
+impl Executor for ShowExecutor<'_> {
+    async fn execute(self: Box<Self>) -> Result<ExecutionResult, String> {
+        let value = self.session.show(&self.name)?;
+        Ok(ExecutionResult::Data {
+            columns: vec![ast::Column {
+                name: self.name.to_lowercase(),
+                data_type: ast::DataType::Varchar,
+            }],
+            rows: RowStream::from_rows(vec![Tuple {
+                values: vec![ast::Value::String(value)],
+            }]),
+        })
+    }
+}
+
+/// `SHOW SESSIONS` 的结果列
+pub fn session_columns() -> Vec<ast::Column> {
+    [
+        ("id", ast::DataType::Int),
+        ("user", ast::DataType::Varchar),
+        ("application_name", ast::DataType::Varchar),
+        ("client_addr", ast::DataType::Varchar),
+        ("backend_start", ast::DataType::Int),
+        ("state", ast::DataType::Varchar),
+        ("query", ast::DataType::Varchar),
+        ("prepared_statements", ast::DataType::Int),
+    ]
+    .into_iter()
+    .map(|(name, data_type)| ast::Column {
+        name: name.to_string(),
+        data_type,
+    })
+    .collect()
+}
+
+/// 列出所有会话，`backend_start` 为连接建立时的 Unix 时间（秒）
+pub struct ShowSessionsExecutor {
+    pub registry: Arc<SessionRegistry>,
+}
+
+#[async_trait(?Send)]
+impl Executor for ShowSessionsExecutor {
+    async fn execute(self: Box<Self>) -> Result<ExecutionResult, String> {
+        let rows = self
+            .registry
+            .list()
+            .into_iter()
+            .map(|info| Tuple {
+                values: vec![
+                    ast::Value::Integer(info.id as i64),
+                    ast::Value::String(info.user),
+                    ast::Value::String(info.application_name),
+                    info.client_addr.map_or(ast::Value::Null, ast::Value::String),
+                    ast::Value::Integer(
+                        info.backend_start
+                            .duration_since(UNIX_EPOCH)
+                            .map_or(0, |d| d.as_secs() as i64),
+                    ),
+                    ast::Value::String(info.state.as_str().to_string()),
+                    ast::Value::String(info.query),
+                    ast::Value::Integer(info.prepared_statements as i64),
+                ],
+            })
+            .collect();
+        Ok(ExecutionResult::Data {
+            columns: session_columns(),
+            rows: RowStream::from_rows(rows),
+        })
+    }
+}
+
+/// 解析语句、推断参数类型之后保存到会话中
+pub struct PrepareExecutor<'a> {
+    pub name: String,
+    pub param_types: Vec<ast::DataType>,
+    pub statement: ast::Statement,
+    pub catalog: CatalogRef,
+    pub session: &'a mut Session,
+}
+
+#[async_trait(?Send)]
+impl Executor for PrepareExecutor<'_> {
+    async fn execute(self: Box<Self>) -> Result<ExecutionResult, String> {
+        let declared: Vec<_> = self.param_types.into_iter().map(Some).collect();
+        let param_types = param_types(&self.statement, &declared, &self.catalog)?;
+        self.session.prepare(
+            self.name,
+            PreparedStatement {
+                statement: Some(self.statement),
+                query: String::new(),
+                param_types,
+            },
+        )?;
+        Ok(ExecutionResult::Message("PREPARE".to_string()))
+    }
+}
+
+/// 绑定参数后执行会话中的预备语句
+pub struct ExecuteExecutor<'a> {
+    pub statement: ast::Statement,
+    pub catalog: CatalogRef,
+    pub bpm: Arc<BufferPoolManager>,
+    pub session: &'a mut Session,
+}
+
+#[async_trait(?Send)]
+impl Executor for ExecuteExecutor<'_> {
+    async fn execute(self: Box<Self>) -> Result<ExecutionResult, String> {
+        let statement = self.session.resolve(self.statement)?;
+        create_executor(statement, self.bpm, self.catalog, self.session)
+            .execute()
+            .await
+    }
+}
+
+pub struct DeallocateExecutor<'a> {
+    pub name: Option<String>,
+    pub session: &'a mut Session,
+}
+
+#[async_trait(?Send)]
+impl Executor for DeallocateExecutor<'_> {
+    async fn execute(self: Box<Self>) -> Result<ExecutionResult, String> {
+        self.session.deallocate(self.name.as_deref())?;
+        Ok(ExecutionResult::Message("DEALLOCATE".to_string()))
+    }
+}
+
+/// 只解码投影中需要的列
+fn project_row(schema: &Schema, projection: &[usize], data: &[u8]) -> Result<Tuple, String> {
+    Ok(Tuple {
+        values: decode_columns(schema, data, projection)?,
+    })
+}
@@ -2,24 +2,38 @@ use std::sync::Arc;
 
 use crate::{
     executor::catalog::CatalogRef,
-    sql::{Statement, ast::Value},
-    storage::{buffer_pool::BufferPoolManager, disk::DiskManager},
+    session::{self, Session},
+    sql::{
+        Statement,
+        ast::{Column, DataType, Expr, Value},
+    },
+    storage::buffer_pool::BufferPoolManager,
 };
 use async_trait::async_trait;
-use bincode::{Decode, Encode};
 
+pub mod autovacuum;
 pub mod catalog;
 pub mod executors;
+pub mod row_format;
 
-#[derive(Debug, Encode, Decode)]
+#[derive(Debug)]
 pub struct Tuple {
     values: Vec<Value>,
 }
 
-#[derive(Debug, Encode, Decode)]
+impl Tuple {
+    pub fn values(&self) -> &[Value] {
+        &self.values
+    }
+}
+
+#[derive(Debug)]
 pub enum ExecutionResult {
     Message(String),
-    Data(Vec<Tuple>),
+    /// 修改了若干行的语句，`rows` 是修改的行数
+    Modified { message: String, rows: usize },
+    /// 查询结果，`columns` 与每行的值一一对应。行在取出时才产生
+    Data { columns: Vec<Column>, rows: RowStream },
 }
 
 #[async_trait(?Send)]
@@ -27,12 +41,142 @@ pub trait Executor {
     async fn execute(self: Box<Self>) -> Result<ExecutionResult, String>;
 }
 
-pub fn create_executor(
+/// 按批产生查询结果的行
+#[async_trait(?Send)]
+pub trait RowSource {
+    /// 下一批行，没有更多行时返回 `None`。一批可以为空
+    async fn next_batch(&mut self) -> Result<Option<Vec<Tuple>>, String>;
+}
+
+/// 查询结果的行。调用者边取边发送，整个结果不必同时放在内存里
+pub struct RowStream {
+    source: Box<dyn RowSource>,
+}
+
+impl std::fmt::Debug for RowStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("RowStream")
+    }
+}
+
+impl RowStream {
+    pub fn new(source: impl RowSource + 'static) -> Self {
+        Self {
+            source: Box::new(source),
+        }
+    }
+
+    /// 已经在内存中的行
+    pub fn from_rows(rows: Vec<Tuple>) -> Self {
+        Self::new(Some(rows))
+    }
+
+    pub async fn next_batch(&mut self) -> Result<Option<Vec<Tuple>>, String> {
+        self.source.next_batch().await
+    }
+
+    /// 取出剩下的所有行
+    pub async fn collect(mut self) -> Result<Vec<Tuple>, String> {
+        let mut rows = Vec::new();
+        while let Some(batch) = self.next_batch().await? {
+            rows.extend(batch);
+        }
+        Ok(rows)
+    }
+}
+
+#[async_trait(?Send)]
+impl RowSource for Option<Vec<Tuple>> {
+    async fn next_batch(&mut self) -> Result<Option<Vec<Tuple>>, String> {
+        Ok(self.take())
+    }
+}
+
+/// 不执行语句，只返回它产生的结果列；不返回行的语句返回 `None`
+pub fn describe(stat: &Statement, catalog: &CatalogRef) -> Result<Option<Vec<Column>>, String> {
+    let (table_name, columns) = match stat {
+        Statement::Select { table_name, columns } => (table_name, columns),
+        Statement::Show { name } => {
+            if !session::PARAMETERS.contains(&name.to_lowercase().as_str()) {
+                return Err(format!("unrecognized configuration parameter '{}'", name));
+            }
+            return Ok(Some(vec![Column {
+                name: name.to_lowercase(),
+                data_type: DataType::Varchar,
+            }]));
+        }
+        Statement::ShowSessions => return Ok(Some(executors::session_columns())),
+        _ => return Ok(None),
+    };
+    let catalog = catalog.lock().unwrap();
+    let table_info = catalog
+        .get_table(table_name)
+        .ok_or_else(|| format!("Table '{}' not found", table_name))?;
+    columns
+        .iter()
+        .map(|col| {
+            table_info
+                .schema
+                .get_col_idx(col)
+                .map(|idx| table_info.schema.columns[idx].clone())
+                .ok_or_else(|| format!("Column '{}' not found in '{}'", col, table_name))
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map(Some)
+}
+
+/// 预备语句每个参数的类型。`declared` 是声明的类型（`None` 表示未声明），
+/// 未声明的参数取 INSERT 中对应列或 DELETE 条件中的列的类型
+pub fn param_types(
+    stat: &Statement,
+    declared: &[Option<DataType>],
+    catalog: &CatalogRef,
+) -> Result<Vec<DataType>, String> {
+    let mut types = declared.to_vec();
+    types.resize(stat.param_count().max(declared.len()), None);
+    if let Statement::Insert { table_name, values } = stat
+        && values.iter().any(|value| matches!(value, Expr::Param(_)))
+    {
+        let catalog = catalog.lock().unwrap();
+        let table_info = catalog
+            .get_table(table_name)
+            .ok_or_else(|| format!("Table '{}' not found", table_name))?;
+        for (column, value) in table_info.schema.columns.iter().zip(values) {
+            if let Expr::Param(n) = value {
+                types[n - 1].get_or_insert_with(|| column.data_type.clone());
+            }
+        }
+    }
+    if let Statement::Delete {
+        table_name,
+        filter: Some((column, Expr::Param(n))),
+    } = stat
+    {
+        let catalog = catalog.lock().unwrap();
+        let table_info = catalog
+            .get_table(table_name)
+            .ok_or_else(|| format!("Table '{}' not found", table_name))?;
+        let col_idx = table_info
+            .schema
+            .get_col_idx(column)
+            .ok_or_else(|| format!("Column '{}' not found in '{}'", column, table_name))?;
+        types[n - 1].get_or_insert_with(|| table_info.schema.columns[col_idx].data_type.clone());
+    }
+    types
+        .into_iter()
+        .enumerate()
+        .map(|(i, data_type)| {
+            data_type.ok_or_else(|| format!("could not determine data type of parameter ${}", i + 1))
+        })
+        .collect()
+}
+
+pub fn create_executor<'a>(
     stat: Statement,
     bpm: Arc<BufferPoolManager>,
     catalog: CatalogRef,
-    disk_manager: Arc<DiskManager>,
-) -> Box<dyn Executor> {
+    session: &'a mut Session,
+) -> Box<dyn Executor + 'a> {
     match stat {
         Statement::CreateTable {
             table_name,
@@ -41,13 +185,28 @@ pub fn create_executor(
             table_name,
             columns,
             catalog,
+            bpm,
+        }),
+        Statement::CreateUser { name, password } => Box::new(executors::CreateUserExecutor {
+            name,
+            password,
+            current_user: session.user().map(str::to_string),
+            catalog,
+            bpm,
         }),
         Statement::Insert { table_name, values } => Box::new(executors::InsertExecutor {
             table_name,
             values,
             catalog,
             bpm,
-            disk_manager,
+            cancel: session.cancel_token().clone(),
+        }),
+        Statement::Delete { table_name, filter } => Box::new(executors::DeleteExecutor {
+            table_name,
+            filter,
+            catalog,
+            bpm,
+            cancel: session.cancel_token().clone(),
         }),
         Statement::Select {
             table_name,
@@ -57,7 +216,44 @@ pub fn create_executor(
             columns,
             catalog,
             bpm,
-            disk_manager,
+            cancel: session.cancel_token().clone(),
+        }),
+        Statement::VerifyDatabase => Box::new(executors::VerifyDatabaseExecutor {
+            disk_manager: bpm.disk_manager().clone(),
+            cancel: session.cancel_token().clone(),
+        }),
+        Statement::Vacuum { table_name } => Box::new(executors::VacuumExecutor {
+            table_name,
+            catalog,
+            bpm,
+            cancel: session.cancel_token().clone(),
+        }),
+        Statement::Set { name, value } => Box::new(executors::SetExecutor {
+            name,
+            value,
+            session,
+        }),
+        Statement::Show { name } => Box::new(executors::ShowExecutor { name, session }),
+        Statement::ShowSessions => Box::new(executors::ShowSessionsExecutor {
+            registry: session.registry().clone(),
+        }),
+        Statement::Prepare {
+            name,
+            param_types,
+            statement,
+        } => Box::new(executors::PrepareExecutor {
+            name,
+            param_types,
+            statement: *statement,
+            catalog,
+            session,
+        }),
+        statement @ Statement::Execute { .. } => Box::new(executors::ExecuteExecutor {
+            statement,
+            catalog,
+            bpm,
+            session,
         }),
+        Statement::Deallocate { name } => Box::new(executors::DeallocateExecutor { name, session }),
     }
 }
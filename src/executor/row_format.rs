@@ -0,0 +1,207 @@
+//! 基于 Schema 的紧凑行格式。
+//!
+//! 布局 (version 1):
+//! ```text
+//! | 0xFF | version | null bitmap | slot 0 | slot 1 | ... | variable-length area |
+//! ```
+//! 每个列在定长区中占一个 8 字节的槽位，位置只由列序号决定：
+//! INT 列直接存放 i64，VARCHAR/BYTEA 列存放 (u32 offset, u32 len)，指向变长区。
+//! 因此读取单个列时无需解码整行。
+use crate::{
+    executor::catalog::Schema,
+    sql::ast::{DataType, Value},
+};
+
+/// 行格式标记，不以它开头的数据不是本格式的行
+const ROW_FORMAT_MARKER: u8 = 0xFF;
+/// 当前写入的行格式版本
+pub const ROW_FORMAT_VERSION: u8 = 1;
+
+const SLOT_SIZE: usize = 8;
+
+fn bitmap_len(column_count: usize) -> usize {
+    column_count.div_ceil(8)
+}
+
+fn slot_offset(column_count: usize, col_idx: usize) -> usize {
+    2 + bitmap_len(column_count) + col_idx * SLOT_SIZE
+}
+
+/// 按照表的 Schema 编码一行数据，同时检查列数与类型。
+pub fn encode_row(schema: &Schema, values: &[Value]) -> Result<Vec<u8>, String> {
+    let column_count = schema.columns.len();
+    if values.len() != column_count {
+        return Err(format!(
+            "Expected {} values, got {}",
+            column_count,
+            values.len()
+        ));
+    }
+
+    let fixed_len = slot_offset(column_count, column_count);
+    let mut row = vec![0u8; fixed_len];
+    row[0] = ROW_FORMAT_MARKER;
+    row[1] = ROW_FORMAT_VERSION;
+
+    for (i, (column, value)) in schema.columns.iter().zip(values).enumerate() {
+        let slot = slot_offset(column_count, i);
+        match (&column.data_type, value) {
+            (_, Value::Null) => {
+                row[2 + i / 8] |= 1 << (i % 8);
+            }
+            (DataType::Int, Value::Integer(v)) => {
+                row[slot..slot + SLOT_SIZE].copy_from_slice(&v.to_le_bytes());
+            }
+            (DataType::Varchar, Value::String(s)) => {
+                write_var(&mut row, slot, s.as_bytes());
+            }
+            (DataType::Bytea, Value::Bytes(b)) => {
+                write_var(&mut row, slot, b);
+            }
+            (data_type, value) => {
+                return Err(format!(
+                    "Column '{}' has type {:?}, got value {:?}",
+                    column.name, data_type, value
+                ));
+            }
+        }
+    }
+
+    Ok(row)
+}
+
+fn write_var(row: &mut Vec<u8>, slot: usize, bytes: &[u8]) {
+    let offset = row.len() as u32;
+    row[slot..slot + 4].copy_from_slice(&offset.to_le_bytes());
+    row[slot + 4..slot + 8].copy_from_slice(&(bytes.len() as u32).to_le_bytes());
+    row.extend_from_slice(bytes);
+}
+
+/// 解码整行数据。
+pub fn decode_row(schema: &Schema, data: &[u8]) -> Result<Vec<Value>, String> {
+    (0..schema.columns.len())
+        .map(|i| decode_column(schema, data, i))
+        .collect()
+}
+
+/// 按 `projection` 中的列序号解码若干列。
+pub fn decode_columns(schema: &Schema, data: &[u8], projection: &[usize]) -> Result<Vec<Value>, String> {
+    projection
+        .iter()
+        .map(|&col_idx| decode_column(schema, data, col_idx))
+        .collect()
+}
+
+/// 只解码第 `col_idx` 列，不触碰其它列的数据。
+pub fn decode_column(schema: &Schema, data: &[u8], col_idx: usize) -> Result<Value, String> {
+    if data.first() != Some(&ROW_FORMAT_MARKER) {
+        return Err("Row does not start with the row format marker".to_string());
+    }
+
+    let version = data.get(1).copied().unwrap_or(0);
+    if version != ROW_FORMAT_VERSION {
+        return Err(format!("Unsupported row format version {}", version));
+    }
+
+    let column_count = schema.columns.len();
+    let column = schema
+        .columns
+        .get(col_idx)
+        .ok_or_else(|| format!("Column index {} out of range", col_idx))?;
+    if data.len() < slot_offset(column_count, column_count) {
+        return Err("Row is shorter than its fixed-width area".to_string());
+    }
+
+    if data[2 + col_idx / 8] & (1 << (col_idx % 8)) != 0 {
+        return Ok(Value::Null);
+    }
+
+    let slot = slot_offset(column_count, col_idx);
+    let slot_bytes = &data[slot..slot + SLOT_SIZE];
+    match column.data_type {
+        DataType::Int => Ok(Value::Integer(i64::from_le_bytes(
+            slot_bytes.try_into().unwrap(),
+        ))),
+        DataType::Varchar => {
+            let bytes = read_var(data, slot_bytes)?;
+            String::from_utf8(bytes.to_vec())
+                .map(Value::String)
+                .map_err(|e| e.to_string())
+        }
+        DataType::Bytea => Ok(Value::Bytes(read_var(data, slot_bytes)?.to_vec())),
+    }
+}
+
+fn read_var<'a>(data: &'a [u8], slot_bytes: &[u8]) -> Result<&'a [u8], String> {
+    let offset = u32::from_le_bytes(slot_bytes[0..4].try_into().unwrap()) as usize;
+    let len = u32::from_le_bytes(slot_bytes[4..8].try_into().unwrap()) as usize;
+    data.get(offset..offset + len)
+        .ok_or_else(|| "Variable-length column points outside the row".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::ast::Column;
+
+    fn schema() -> Schema {
+        Schema {
+            columns: vec![
+                Column {
+                    name: "id".to_string(),
+                    data_type: DataType::Int,
+                },
+                Column {
+                    name: "name".to_string(),
+                    data_type: DataType::Varchar,
+                },
+                Column {
+                    name: "body".to_string(),
+                    data_type: DataType::Bytea,
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_row_roundtrip_and_single_column() {
+        let schema = schema();
+        let values = vec![
+            Value::Integer(-42),
+            Value::String("Alice".to_string()),
+            Value::Null,
+        ];
+        let row = encode_row(&schema, &values).unwrap();
+
+        let decoded = decode_row(&schema, &row).unwrap();
+        assert!(matches!(decoded[0], Value::Integer(-42)));
+        assert!(matches!(&decoded[1], Value::String(s) if s == "Alice"));
+        assert!(matches!(decoded[2], Value::Null));
+
+        assert!(matches!(
+            decode_column(&schema, &row, 1).unwrap(),
+            Value::String(s) if s == "Alice"
+        ));
+    }
+
+    #[test]
+    fn test_type_mismatch_is_rejected() {
+        let schema = schema();
+        let values = vec![
+            Value::String("oops".to_string()),
+            Value::Null,
+            Value::Null,
+        ];
+        assert!(encode_row(&schema, &values).is_err());
+        assert!(encode_row(&schema, &values[..1]).is_err());
+    }
+
+    #[test]
+    fn test_unknown_row_format_is_rejected() {
+        let mut row = encode_row(&schema(), &[Value::Integer(1), Value::Null, Value::Null]).unwrap();
+        row[1] = ROW_FORMAT_VERSION + 1;
+        assert!(decode_row(&schema(), &row).is_err());
+        row[0] = 0x03;
+        assert!(decode_columns(&schema(), &row, &[0]).is_err());
+    }
+}
@@ -1,44 +1,363 @@
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
 
 use crate::{
+    auth::ScramVerifier,
+    config::Config,
     executor::{
         ExecutionResult,
-        catalog::{Catalog, CatalogRef},
+        autovacuum::{self, AutovacuumConfig},
+        catalog::{self, CatalogRef},
         create_executor,
     },
-    sql::parse_sql,
-    storage::{buffer_pool::BufferPoolManager, disk::DiskManager},
+    session::{Session, SessionRegistry},
+    shutdown::Shutdown,
+    sql::{
+        Statement,
+        ast::{Column, DataType},
+        parse_sql,
+    },
+    storage::{
+        bgwriter::{self, BgWriterConfig},
+        buffer_pool::BufferPoolManager,
+        disk::DiskManager,
+        replacer::ReplacerKind,
+        wal::{self, LogManager, SynchronousCommit, WalConfig},
+    },
 };
 
+pub mod auth;
+pub mod config;
 pub mod executor;
+pub mod logging;
+pub mod pgwire;
+pub mod session;
+pub mod shutdown;
 pub mod sql;
 pub mod storage;
 
 pub struct Database {
     bpm: Arc<BufferPoolManager>,
+    log_manager: Arc<LogManager>,
     catalog: CatalogRef,
+    bgwriter: BgWriterConfig,
+    wal: WalConfig,
+    autovacuum: AutovacuumConfig,
+    shutdown: Shutdown,
+    sessions: Arc<SessionRegistry>,
 }
 
 impl Database {
-    pub async fn new(db_file: String, pool_size: usize) -> Result<Self, String> {
-        let _disk_manager = Arc::new(
+    /// 按配置打开数据目录中的数据库，并从最近一次检查点开始重放日志
+    pub async fn open(config: &Config) -> Result<Self, String> {
+        std::fs::create_dir_all(&config.data_dir)
+            .map_err(|e| format!("Failed to create data directory {}: {}", config.data_dir.display(), e))?;
+        let db_file = config.db_file();
+        // 所有 runtime 共享同一个 DiskManager，各核在其上使用自己的文件句柄
+        let disk_manager = Arc::new(
             DiskManager::new(&db_file)
                 .await
                 .map_err(|e| e.to_string())?,
         );
-        let bpm = BufferPoolManager::new(pool_size);
-        let catalog = Arc::new(Mutex::new(Catalog::default()));
+        let log_manager = Arc::new(
+            LogManager::open(&format!("{}.wal", db_file))
+                .await
+                .map_err(|e| e.to_string())?,
+        );
+        // A damaged control file means replaying the whole log.
+        let checkpoint = bgwriter::last_checkpoint(&disk_manager).await.ok().flatten();
+        let mut replayed = log_manager
+            .replay(checkpoint.as_ref().map_or(0, |record| record.redo_lsn), &disk_manager)
+            .await
+            .map_err(|e| format!("Failed to replay WAL: {}", e))?;
+        let format_recorded = bgwriter::check_format_version(&disk_manager, checkpoint.as_ref()).await?;
+        // Pages freed after the checkpoint were logged, so they are among the replayed ones.
+        replayed.extend(checkpoint.iter().flat_map(|record| record.free_pages.iter().copied()));
+        bgwriter::restore_free_pages(&disk_manager, replayed).await?;
+
+        let bpm = BufferPoolManager::with_log_manager(
+            config.pool_size,
+            ReplacerKind::default(),
+            disk_manager,
+            log_manager.clone(),
+        );
+        let catalog = catalog::load(&bpm)
+            .await
+            .map_err(|e| format!("Failed to load catalog: {}", e))?;
+        if !format_recorded {
+            bgwriter::checkpoint(&bpm, &config.bgwriter).await?;
+        }
         Ok(Self {
             bpm,
+            log_manager,
             catalog,
+            bgwriter: config.bgwriter,
+            wal: config.wal,
+            autovacuum: config.autovacuum,
+            shutdown: Shutdown::default(),
+            sessions: Arc::default(),
         })
     }
 
-    // This function is now async
-    pub async fn run_statement(&self, sql: &str, disk_manager: Arc<DiskManager>) -> Result<ExecutionResult, String> {
+    /// 在当前 runtime 上启动后台写任务。每个 runtime 调用一次，
+    /// `worker` 为 0 的 runtime 同时负责周期性检查点、组提交的日志刷写以及自动清理。
+    pub fn start_background_tasks(&self, worker: usize, num_workers: usize) {
+        monoio::spawn(bgwriter::run_bgwriter(
+            self.bpm.clone(),
+            self.bgwriter,
+            worker,
+            num_workers,
+        ));
+        if worker == 0 {
+            monoio::spawn(bgwriter::run_checkpointer(self.bpm.clone(), self.bgwriter));
+            monoio::spawn(wal::run_log_flusher(self.log_manager.clone()));
+            if self.autovacuum.enabled {
+                monoio::spawn(autovacuum::run_autovacuum(
+                    self.bpm.clone(),
+                    self.catalog.clone(),
+                    self.autovacuum,
+                ));
+            }
+        }
+    }
+
+    /// 创建一个使用数据库默认设置的会话，它在 drop 之前都会出现在 `SHOW SESSIONS` 中
+    pub fn new_session(&self) -> Session {
+        Session::new(&self.wal, self.sessions.clone())
+    }
+
+    /// 在一个临时会话中执行一条语句
+    pub async fn run_statement(&self, sql: &str) -> Result<ExecutionResult, String> {
+        let mut session = self.new_session();
+        self.execute(&mut session, sql).await
+    }
+
+    /// 在 `session` 中执行一条语句。修改数据的语句按会话的 `synchronous_commit`
+    /// 等待日志落盘之后才返回。
+    pub async fn execute(&self, session: &mut Session, sql: &str) -> Result<ExecutionResult, String> {
         let ast = parse_sql(sql).map_err(|e| e.to_string())?;
-        let executor = create_executor(ast, self.bpm.clone(), self.catalog.clone(), disk_manager);
-        // Await the executor's result
-        executor.execute().await
+        self.execute_statement(session, ast).await
+    }
+
+    /// 在 `session` 中执行一条已经解析好的语句
+    pub async fn execute_statement(&self, session: &mut Session, ast: Statement) -> Result<ExecutionResult, String> {
+        // EXECUTE commits like the statement it runs.
+        let ast = session.resolve(ast)?;
+        let writes = matches!(
+            ast,
+            Statement::CreateTable { .. }
+                | Statement::CreateUser { .. }
+                | Statement::Insert { .. }
+                | Statement::Delete { .. }
+                | Statement::Vacuum { .. }
+        );
+        let executor = create_executor(ast, self.bpm.clone(), self.catalog.clone(), session);
+        let result = executor.execute().await?;
+        if writes && session.synchronous_commit != SynchronousCommit::Off {
+            self.log_manager
+                .commit(self.log_manager.insert_lsn(), session.commit_delay)
+                .await
+                .map_err(|e| format!("Failed to flush WAL at commit: {}", e))?;
+        }
+        Ok(result)
+    }
+
+    /// 语句的结果列，不返回行的语句返回 `None`
+    pub fn describe(&self, statement: &Statement) -> Result<Option<Vec<Column>>, String> {
+        executor::describe(statement, &self.catalog)
+    }
+
+    /// 预备语句的参数类型，见 [`executor::param_types`]
+    pub fn param_types(&self, statement: &Statement, declared: &[Option<DataType>]) -> Result<Vec<DataType>, String> {
+        executor::param_types(statement, declared, &self.catalog)
+    }
+
+    /// 创建启动时配置的用户，它是唯一的超级用户。用户已经存在时把口令改成配置中的口令。
+    /// 目录中只保存口令的校验信息，返回前等待它的日志落盘。
+    pub async fn bootstrap_user(&self, name: &str, password: &str) -> Result<(), String> {
+        {
+            let mut catalog = self.catalog.lock().unwrap();
+            if catalog
+                .get_user(name)
+                .is_some_and(|user| user.superuser && user.verifier.matches(password))
+            {
+                return Ok(());
+            }
+            catalog.set_user(name.to_string(), ScramVerifier::new(password), true);
+        }
+        catalog::save(&self.catalog, &self.bpm).await?;
+        self.log_manager
+            .flush(self.log_manager.insert_lsn())
+            .await
+            .map_err(|e| format!("Failed to flush WAL: {}", e))
+    }
+
+    /// 用户的口令校验信息，用户不存在时返回 `None`
+    pub fn user_verifier(&self, name: &str) -> Option<ScramVerifier> {
+        self.catalog.lock().unwrap().get_user(name).map(|user| user.verifier.clone())
+    }
+
+    /// 不存在的用户在认证时使用的校验信息，见 [`ScramVerifier::mock`]
+    pub fn mock_verifier(&self, name: &str) -> ScramVerifier {
+        self.catalog.lock().unwrap().mock_verifier(name)
+    }
+
+    /// 服务端的关闭状态
+    pub fn shutdown(&self) -> &Shutdown {
+        &self.shutdown
+    }
+
+    /// 写回所有脏页并 fsync，在正常关闭前调用
+    pub async fn flush_all(&self) -> Result<(), String> {
+        self.log_manager
+            .flush(self.log_manager.insert_lsn())
+            .await
+            .map_err(|e| e.to_string())?;
+        self.bpm.flush_all().await.map_err(|e| e.to_string())
+    }
+
+    /// 正常关闭：刷写日志和所有脏页，再写一个检查点，下次启动时不需要重放日志
+    pub async fn close(&self) -> Result<(), String> {
+        self.flush_all().await?;
+        bgwriter::checkpoint(&self.bpm, &self.bgwriter).await.map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::ast::Value;
+
+    #[monoio::test(timer_enabled = true)]
+    async fn test_catalog_survives_reopen() {
+        let config = Config {
+            data_dir: std::env::temp_dir().join(format!("ringdb_reopen_{}", std::process::id())),
+            ..Default::default()
+        };
+        let _ = std::fs::remove_dir_all(&config.data_dir);
+        let mock;
+        {
+            let db = Database::open(&config).await.unwrap();
+            db.run_statement("CREATE TABLE users (id INT, name VARCHAR)").await.unwrap();
+            db.run_statement("INSERT INTO users VALUES (1, 'Alice')").await.unwrap();
+            db.run_statement("INSERT INTO users VALUES (2, 'Bob')").await.unwrap();
+            db.run_statement("CREATE USER alice PASSWORD 'secret'").await.unwrap();
+            db.bootstrap_user("admin", "admin-pw").await.unwrap();
+            mock = db.mock_verifier("nobody");
+            // Dropped without closing: tables, rows and users come back from the WAL.
+        }
+
+        let db = Database::open(&config).await.unwrap();
+        let ExecutionResult::Data { rows, .. } = db.run_statement("SELECT id, name FROM users").await.unwrap() else {
+            panic!("SELECT returned no rows");
+        };
+        let rows = rows.collect().await.unwrap();
+        assert_eq!(rows.len(), 2);
+        assert!(matches!(rows[1].values(), [Value::Integer(2), Value::String(name)] if name == "Bob"));
+        assert!(db.run_statement("CREATE TABLE users (id INT)").await.is_err());
+        assert!(db.user_verifier("alice").unwrap().matches("secret"));
+        assert!(db.user_verifier("admin").unwrap().matches("admin-pw"));
+        assert_eq!(db.mock_verifier("nobody"), mock);
+        // A changed bootstrap password replaces the stored one.
+        db.bootstrap_user("admin", "new-pw").await.unwrap();
+        assert!(db.user_verifier("admin").unwrap().matches("new-pw"));
+
+        let _ = std::fs::remove_dir_all(&config.data_dir);
+    }
+
+    #[monoio::test(timer_enabled = true)]
+    async fn test_delete_then_vacuum() {
+        let config = Config {
+            data_dir: std::env::temp_dir().join(format!("ringdb_delete_{}", std::process::id())),
+            ..Default::default()
+        };
+        let _ = std::fs::remove_dir_all(&config.data_dir);
+        let db = Database::open(&config).await.unwrap();
+        db.run_statement("CREATE TABLE blobs (id INT, body BYTEA)").await.unwrap();
+        for id in 1..=3 {
+            db.run_statement(&format!("INSERT INTO blobs VALUES ({}, X'{}')", id, "ab".repeat(100)))
+                .await
+                .unwrap();
+        }
+        // A row stored in an overflow chain is matched like any other.
+        let big = "cd".repeat(3 * storage::page::PAGE_SIZE);
+        db.run_statement(&format!("INSERT INTO blobs VALUES (4, X'{}')", big)).await.unwrap();
+        db.run_statement("INSERT INTO blobs VALUES (5, NULL)").await.unwrap();
+        let ids = async || {
+            let ExecutionResult::Data { rows, .. } = db.run_statement("SELECT id FROM blobs").await.unwrap() else {
+                panic!("SELECT returned no rows");
+            };
+            let rows = rows.collect().await.unwrap();
+            rows.iter().map(|row| format!("{:?}", row.values())).collect::<Vec<_>>()
+        };
+        // The overflow row keeps its place between the inline ones.
+        assert_eq!(ids().await, (1..=5).map(|id| format!("[Integer({})]", id)).collect::<Vec<_>>());
+
+        let deleted = |result| match result {
+            ExecutionResult::Modified { rows, .. } => rows,
+            other => panic!("unexpected result {:?}", other),
+        };
+        assert_eq!(deleted(db.run_statement("DELETE FROM blobs WHERE id = 2").await.unwrap()), 1);
+        assert_eq!(deleted(db.run_statement("DELETE FROM blobs WHERE id = 4").await.unwrap()), 1);
+        assert_eq!(deleted(db.run_statement("DELETE FROM blobs WHERE id = 4").await.unwrap()), 0);
+        assert_eq!(deleted(db.run_statement("DELETE FROM blobs WHERE body = NULL").await.unwrap()), 0);
+        assert!(db.run_statement("DELETE FROM blobs WHERE id = 'x'").await.is_err());
+
+        let ExecutionResult::Message(message) = db.run_statement("VACUUM blobs").await.unwrap() else {
+            panic!("VACUUM returned rows");
+        };
+        assert!(message.contains("2 dead tuples removed, 131 bytes reclaimed, 4 pages"), "{}", message);
+        assert_eq!(ids().await, ["[Integer(1)]", "[Integer(3)]", "[Integer(5)]"]);
+
+        let _ = std::fs::remove_dir_all(&config.data_dir);
+    }
+
+    #[monoio::test(timer_enabled = true)]
+    async fn test_free_pages_survive_restart() {
+        let config = Config {
+            data_dir: std::env::temp_dir().join(format!("ringdb_free_pages_{}", std::process::id())),
+            ..Default::default()
+        };
+        let _ = std::fs::remove_dir_all(&config.data_dir);
+        // Each row fills a four-page overflow chain.
+        const BODY_LEN: usize = 3 * storage::page::PAGE_SIZE;
+        let insert = |id: i64| format!("INSERT INTO blobs VALUES ({}, X'{}')", id, "ef".repeat(BODY_LEN));
+        let num_pages;
+        {
+            let db = Database::open(&config).await.unwrap();
+            db.run_statement("CREATE TABLE blobs (id INT, body BYTEA)").await.unwrap();
+            db.run_statement(&insert(1)).await.unwrap();
+            db.run_statement(&insert(2)).await.unwrap();
+            db.run_statement("DELETE FROM blobs WHERE id = 1").await.unwrap();
+            db.run_statement("VACUUM blobs").await.unwrap();
+            // The checkpoint records row 1's chain as free ...
+            db.close().await.unwrap();
+            // ... but row 3 takes it over, and row 2's chain is freed after the checkpoint.
+            db.run_statement(&insert(3)).await.unwrap();
+            db.run_statement("DELETE FROM blobs WHERE id = 2").await.unwrap();
+            db.run_statement("VACUUM blobs").await.unwrap();
+            num_pages = db.bpm.disk_manager().num_pages();
+            // Dropped without closing.
+        }
+
+        let db = Database::open(&config).await.unwrap();
+        let disk_manager = db.bpm.disk_manager();
+        assert_eq!(disk_manager.free_pages().len(), 4);
+        db.run_statement(&insert(4)).await.unwrap();
+        assert_eq!(disk_manager.num_pages(), num_pages);
+        assert!(disk_manager.free_pages().is_empty());
+
+        let ExecutionResult::Data { rows, .. } = db.run_statement("SELECT id, body FROM blobs").await.unwrap() else {
+            panic!("SELECT returned no rows");
+        };
+        let rows = rows.collect().await.unwrap();
+        assert_eq!(rows.len(), 2);
+        for (row, id) in rows.iter().zip([3, 4]) {
+            assert!(matches!(
+                row.values(),
+                [Value::Integer(i), Value::Bytes(body)] if *i == id && *body == [0xef; BODY_LEN]
+            ));
+        }
+
+        let _ = std::fs::remove_dir_all(&config.data_dir);
     }
 }
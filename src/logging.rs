@@ -0,0 +1,27 @@
+//! 把 `log` 宏的输出写到标准错误，级别由配置的 `log_level` 决定
+use log::{LevelFilter, Log, Metadata, Record};
+
+struct StderrLogger;
+
+static LOGGER: StderrLogger = StderrLogger;
+
+impl Log for StderrLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            eprintln!("{:<5} {}", record.level(), record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// 安装日志输出，可以重复调用以修改级别
+pub fn init(level: LevelFilter) {
+    // Only the first call installs the logger; later calls just change the level.
+    let _ = log::set_logger(&LOGGER);
+    log::set_max_level(level);
+}
@@ -1,13 +1,17 @@
-use std::sync::Arc;
-
-use ringdb::{storage::disk::DiskManager, Database};
+use ringdb::{Database, config::Config, executor::ExecutionResult, logging};
 use rustyline::{DefaultEditor, error::ReadlineError};
 
-#[monoio::main]
+#[monoio::main(timer_enabled = true)]
 async fn main() {
+    let config = Config::load(std::env::args().skip(1), |name| std::env::var(name).ok()).unwrap_or_else(|e| {
+        eprintln!("ringdb: {}", e);
+        std::process::exit(2);
+    });
+    logging::init(config.log_level);
     let mut r1 = DefaultEditor::new().unwrap();
-    let db = Database::new("database.db".into(), 10).await.unwrap();
-    let disk_manager = Arc::new(DiskManager::new("database.db").await.unwrap());
+    let db = Database::open(&config).await.unwrap();
+    db.start_background_tasks(0, 1);
+    let mut session = db.new_session();
     loop {
         let readline = r1.readline("ringdb>> ");
         match readline {
@@ -18,10 +22,23 @@ async fn main() {
                     break;
                 }
 
-                match db.run_statement(&line, disk_manager.clone()).await {
+                session.start_query(&line);
+                match db.execute(&mut session, &line).await {
+                    Ok(ExecutionResult::Data { mut rows, .. }) => loop {
+                        // Print each page of rows as soon as the scan produces it.
+                        match rows.next_batch().await {
+                            Ok(Some(batch)) => batch.iter().for_each(|row| println!("{:?}", row.values())),
+                            Ok(None) => break,
+                            Err(e) => {
+                                println!("Error executing statement: {:?}", e);
+                                break;
+                            }
+                        }
+                    },
                     Ok(res) => println!("{:?}", res),
                     Err(e) => println!("Error executing statement: {:?}", e),
                 }
+                session.finish_query();
             }
             Err(ReadlineError::Interrupted) => {
                 println!("Exiting...");
@@ -33,4 +50,8 @@ async fn main() {
             }
         }
     }
+
+    if let Err(e) = db.close().await {
+        println!("Failed to flush database: {}", e);
+    }
 }
@@ -0,0 +1,695 @@
+//! PostgreSQL v3 协议的消息编解码。
+//!
+//! 前端消息只解码服务端需要的部分；后端消息直接编码到输出缓冲区中。
+use bytes::{BufMut, BytesMut};
+
+use crate::sql::ast::{Column, DataType, Value};
+
+/// 协议版本 3.0
+pub const PROTOCOL_VERSION: i32 = 196608;
+/// 支持的协议主版本，客户端请求更高的次版本时协商回 3.0
+pub const PROTOCOL_MAJOR: i32 = 3;
+/// 启动包的最大长度，与 PostgreSQL 相同。此时还没有认证，不能让客户端申请大块内存
+pub const MAX_STARTUP_PACKET_LENGTH: usize = 10000;
+/// 启动参数中以此为前缀的是协议扩展选项，目前一个都不支持
+pub const PROTOCOL_OPTION_PREFIX: &str = "_pq_.";
+const SSL_REQUEST_CODE: i32 = 80877103;
+const GSSENC_REQUEST_CODE: i32 = 80877104;
+const CANCEL_REQUEST_CODE: i32 = 80877102;
+
+/// 文本格式
+pub const FORMAT_TEXT: i16 = 0;
+/// 二进制格式
+pub const FORMAT_BINARY: i16 = 1;
+
+/// 服务端用到的 SQLSTATE 错误码
+pub mod sqlstate {
+    pub const FEATURE_NOT_SUPPORTED: &str = "0A000";
+    pub const PROTOCOL_VIOLATION: &str = "08P01";
+    pub const PROGRAM_LIMIT_EXCEEDED: &str = "54000";
+    pub const ADMIN_SHUTDOWN: &str = "57P01";
+    pub const CANNOT_CONNECT_NOW: &str = "57P03";
+    pub const INVALID_AUTHORIZATION_SPECIFICATION: &str = "28000";
+    pub const INVALID_PASSWORD: &str = "28P01";
+    pub const QUERY_CANCELED: &str = "57014";
+    pub const INVALID_SQL_STATEMENT_NAME: &str = "26000";
+    pub const INVALID_CURSOR_NAME: &str = "34000";
+    pub const SYNTAX_ERROR: &str = "42601";
+    pub const UNDEFINED_COLUMN: &str = "42703";
+    pub const UNDEFINED_TABLE: &str = "42P01";
+    pub const UNDEFINED_OBJECT: &str = "42704";
+    pub const DUPLICATE_TABLE: &str = "42P07";
+    pub const DUPLICATE_OBJECT: &str = "42710";
+    pub const INSUFFICIENT_PRIVILEGE: &str = "42501";
+    pub const DUPLICATE_PREPARED_STATEMENT: &str = "42P05";
+    pub const INVALID_PARAMETER_VALUE: &str = "22023";
+    pub const INVALID_TEXT_REPRESENTATION: &str = "22P02";
+    pub const INVALID_BINARY_REPRESENTATION: &str = "22P03";
+    pub const CHARACTER_NOT_IN_REPERTOIRE: &str = "22021";
+    pub const UNDEFINED_PARAMETER: &str = "42P02";
+    pub const INDETERMINATE_DATATYPE: &str = "42P18";
+    pub const DATA_CORRUPTED: &str = "XX001";
+    pub const INTERNAL_ERROR: &str = "XX000";
+}
+
+/// 列类型对应的 PostgreSQL 类型 OID 和类型长度
+pub fn type_info(data_type: &DataType) -> (u32, i16) {
+    match data_type {
+        DataType::Int => (20, 8),       // int8
+        DataType::Varchar => (1043, -1), // varchar
+        DataType::Bytea => (17, -1),     // bytea
+    }
+}
+
+/// 客户端在 Parse 中声明的参数类型。0 表示未声明，由服务端推断
+pub fn param_type(oid: u32) -> Result<Option<DataType>, String> {
+    match oid {
+        0 => Ok(None),
+        20 | 21 | 23 => Ok(Some(DataType::Int)), // int8, int2, int4
+        25 | 1043 => Ok(Some(DataType::Varchar)), // text, varchar
+        17 => Ok(Some(DataType::Bytea)),
+        oid => Err(format!("unsupported parameter type oid {}", oid)),
+    }
+}
+
+/// 按参数的类型和格式解码 Bind 中的一个参数，`None` 是 NULL。
+/// 二进制格式的整数按长度接受 int2、int4 和 int8
+pub fn decode_param(data: Option<&[u8]>, format: i16, data_type: &DataType) -> Result<Value, String> {
+    let Some(data) = data else {
+        return Ok(Value::Null);
+    };
+    let text = || {
+        std::str::from_utf8(data).map_err(|_| "invalid byte sequence for encoding \"UTF8\"".to_string())
+    };
+    match (data_type, format) {
+        (DataType::Int, FORMAT_BINARY) => match data.len() {
+            2 => Ok(Value::Integer(i16::from_be_bytes(data.try_into().unwrap()) as i64)),
+            4 => Ok(Value::Integer(i32::from_be_bytes(data.try_into().unwrap()) as i64)),
+            8 => Ok(Value::Integer(i64::from_be_bytes(data.try_into().unwrap()))),
+            _ => Err("incorrect binary data format in bind parameter".to_string()),
+        },
+        (DataType::Int, _) => {
+            let text = text()?;
+            text.trim()
+                .parse()
+                .map(Value::Integer)
+                .map_err(|_| format!("invalid input syntax for type bigint: \"{}\"", text))
+        }
+        (DataType::Varchar, _) => text().map(|s| Value::String(s.to_string())),
+        (DataType::Bytea, FORMAT_BINARY) => Ok(Value::Bytes(data.to_vec())),
+        (DataType::Bytea, _) => {
+            let text = text()?;
+            // Only the hex format is accepted, which is what PostgreSQL itself outputs.
+            let invalid = || format!("invalid input syntax for type bytea: \"{}\"", text);
+            let digits = text.strip_prefix("\\x").ok_or_else(invalid)?;
+            if digits.len() % 2 != 0 {
+                return Err(invalid());
+            }
+            (0..digits.len())
+                .step_by(2)
+                .map(|i| {
+                    digits
+                        .get(i..i + 2)
+                        .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+                        .ok_or_else(invalid)
+                })
+                .collect::<Result<_, _>>()
+                .map(Value::Bytes)
+        }
+    }
+}
+
+/// 第 `i` 列使用的格式：没有指定时为文本，只指定一个时用于所有列
+pub fn format_for(formats: &[i16], i: usize) -> i16 {
+    match formats {
+        [] => FORMAT_TEXT,
+        [format] => *format,
+        formats => formats[i],
+    }
+}
+
+/// 消息长度字段不合法
+#[derive(Debug, PartialEq, Eq)]
+pub enum FrameError {
+    /// 长度小于长度字段本身
+    InvalidLength(i32),
+    /// 超过允许的最大长度
+    TooLong { len: usize, max_len: usize },
+}
+
+impl FrameError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            FrameError::InvalidLength(_) => sqlstate::PROTOCOL_VIOLATION,
+            FrameError::TooLong { .. } => sqlstate::PROGRAM_LIMIT_EXCEEDED,
+        }
+    }
+}
+
+impl std::fmt::Display for FrameError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FrameError::InvalidLength(len) => write!(f, "invalid message length {}", len),
+            FrameError::TooLong { len, max_len } => {
+                write!(f, "message of {} bytes exceeds the maximum of {} bytes", len, max_len)
+            }
+        }
+    }
+}
+
+/// 检查 4 字节长度字段（包括自身），返回其后内容的长度。在分配缓冲区之前调用
+pub fn body_len(header: [u8; 4], max_len: usize) -> Result<usize, FrameError> {
+    let len = i32::from_be_bytes(header);
+    if len < 4 {
+        return Err(FrameError::InvalidLength(len));
+    }
+    if len as usize > max_len {
+        return Err(FrameError::TooLong {
+            len: len as usize,
+            max_len,
+        });
+    }
+    Ok(len as usize - 4)
+}
+
+/// 连接建立时的第一个包，没有类型字节
+#[derive(Debug, PartialEq, Eq)]
+pub enum StartupRequest {
+    Startup {
+        version: i32,
+        params: Vec<(String, String)>,
+    },
+    /// SSLRequest 和 GSSENCRequest，都回答不支持
+    Encryption,
+    Cancel {
+        process_id: i32,
+        secret_key: i32,
+    },
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum FrontendMessage {
+    Query(String),
+    Parse {
+        name: String,
+        query: String,
+        param_types: Vec<u32>,
+    },
+    Bind {
+        portal: String,
+        statement: String,
+        param_formats: Vec<i16>,
+        params: Vec<Option<Vec<u8>>>,
+        result_formats: Vec<i16>,
+    },
+    /// `kind` 为 b'S'（预备语句）或 b'P'（门户）
+    Describe { kind: u8, name: String },
+    Execute { portal: String, max_rows: i32 },
+    Close { kind: u8, name: String },
+    Sync,
+    Flush,
+    Terminate,
+}
+
+/// 按协议的整数和字符串格式读取消息体
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], String> {
+        if self.data.len() < len {
+            return Err("message is shorter than its contents".to_string());
+        }
+        let (head, tail) = self.data.split_at(len);
+        self.data = tail;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn i16(&mut self) -> Result<i16, String> {
+        Ok(i16::from_be_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    fn i32(&mut self) -> Result<i32, String> {
+        Ok(i32::from_be_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn cstr(&mut self) -> Result<String, String> {
+        let end = self
+            .data
+            .iter()
+            .position(|&b| b == 0)
+            .ok_or_else(|| "string is not null-terminated".to_string())?;
+        let s = std::str::from_utf8(&self.data[..end])
+            .map_err(|_| "string is not valid UTF-8".to_string())?
+            .to_string();
+        self.data = &self.data[end + 1..];
+        Ok(s)
+    }
+
+    /// 一个 i16 个数后跟若干个元素
+    fn list<T>(&mut self, mut item: impl FnMut(&mut Self) -> Result<T, String>) -> Result<Vec<T>, String> {
+        let count = self.i16()?;
+        if count < 0 {
+            return Err("negative list length".to_string());
+        }
+        (0..count).map(|_| item(self)).collect()
+    }
+}
+
+impl StartupRequest {
+    /// 解码启动包的内容（不含长度字段）
+    pub fn decode(body: &[u8]) -> Result<Self, String> {
+        let mut reader = Reader { data: body };
+        let code = reader.i32()?;
+        match code {
+            SSL_REQUEST_CODE | GSSENC_REQUEST_CODE => Ok(StartupRequest::Encryption),
+            CANCEL_REQUEST_CODE => Ok(StartupRequest::Cancel {
+                process_id: reader.i32()?,
+                secret_key: reader.i32()?,
+            }),
+            version => {
+                let mut params = Vec::new();
+                loop {
+                    let name = reader.cstr()?;
+                    if name.is_empty() {
+                        break;
+                    }
+                    params.push((name, reader.cstr()?));
+                }
+                Ok(StartupRequest::Startup { version, params })
+            }
+        }
+    }
+}
+
+impl FrontendMessage {
+    /// 解码一条类型为 `tag` 的消息的内容（不含类型和长度字段）
+    pub fn decode(tag: u8, body: &[u8]) -> Result<Self, String> {
+        let mut reader = Reader { data: body };
+        let message = match tag {
+            b'Q' => FrontendMessage::Query(reader.cstr()?),
+            b'P' => FrontendMessage::Parse {
+                name: reader.cstr()?,
+                query: reader.cstr()?,
+                param_types: reader.list(|r| r.i32().map(|oid| oid as u32))?,
+            },
+            b'B' => FrontendMessage::Bind {
+                portal: reader.cstr()?,
+                statement: reader.cstr()?,
+                param_formats: reader.list(Reader::i16)?,
+                params: reader.list(|r| match r.i32()? {
+                    -1 => Ok(None),
+                    len if len < 0 => Err("negative parameter length".to_string()),
+                    len => r.bytes(len as usize).map(|b| Some(b.to_vec())),
+                })?,
+                result_formats: reader.list(Reader::i16)?,
+            },
+            b'D' => FrontendMessage::Describe {
+                kind: reader.u8()?,
+                name: reader.cstr()?,
+            },
+            b'E' => FrontendMessage::Execute {
+                portal: reader.cstr()?,
+                max_rows: reader.i32()?,
+            },
+            b'C' => FrontendMessage::Close {
+                kind: reader.u8()?,
+                name: reader.cstr()?,
+            },
+            b'S' => FrontendMessage::Sync,
+            b'H' => FrontendMessage::Flush,
+            b'X' => FrontendMessage::Terminate,
+            tag => return Err(format!("invalid frontend message type {}", tag)),
+        };
+        if !reader.data.is_empty() {
+            return Err(format!("invalid message format: {} trailing bytes", reader.data.len()));
+        }
+        if let FrontendMessage::Describe { kind, .. } | FrontendMessage::Close { kind, .. } = &message
+            && *kind != b'S'
+            && *kind != b'P'
+        {
+            return Err(format!("invalid DESCRIBE/CLOSE message subtype {}", kind));
+        }
+        Ok(message)
+    }
+}
+
+/// 解码 SASLInitialResponse：客户端选择的机制及其第一条消息。
+/// 认证阶段的 'p' 消息没有自描述的格式，只能按所处的阶段解码，所以不在 [`FrontendMessage`] 中
+pub fn decode_sasl_initial_response(body: &[u8]) -> Result<(String, Vec<u8>), String> {
+    let mut reader = Reader { data: body };
+    let mechanism = reader.cstr()?;
+    let data = match reader.i32()? {
+        -1 => Vec::new(),
+        len if len < 0 => return Err("negative SASL response length".to_string()),
+        len => reader.bytes(len as usize)?.to_vec(),
+    };
+    if !reader.data.is_empty() {
+        return Err(format!("invalid message format: {} trailing bytes", reader.data.len()));
+    }
+    Ok((mechanism, data))
+}
+
+#[derive(Debug)]
+pub enum BackendMessage<'a> {
+    AuthenticationOk,
+    /// 要求用 SASL 认证，列出服务端支持的机制
+    AuthenticationSasl { mechanisms: &'a [&'a str] },
+    /// SASL 交换中服务端的消息
+    AuthenticationSaslContinue(&'a [u8]),
+    /// SASL 交换成功后服务端的最后一条消息，之后还要发送 AuthenticationOk
+    AuthenticationSaslFinal(&'a [u8]),
+    ParameterStatus { name: &'a str, value: &'a str },
+    BackendKeyData { process_id: i32, secret_key: i32 },
+    /// 事务状态，目前总是 b'I'（空闲）
+    ReadyForQuery(u8),
+    RowDescription { columns: &'a [Column], formats: &'a [i16] },
+    DataRow { values: &'a [Value], formats: &'a [i16] },
+    CommandComplete(&'a str),
+    EmptyQueryResponse,
+    ErrorResponse { code: &'a str, message: &'a str },
+    NoticeResponse { message: &'a str },
+    ParseComplete,
+    BindComplete,
+    CloseComplete,
+    ParameterDescription(&'a [u32]),
+    NoData,
+    /// Execute 达到最大行数，门户还有剩余的行
+    PortalSuspended,
+    /// 不能继续的会话错误，之后连接会被关闭
+    Fatal { code: &'a str, message: &'a str },
+    /// 客户端请求了更高的次版本或不支持的协议选项时，告知服务端支持的次版本和不认识的选项
+    NegotiateProtocolVersion { minor: i32, unrecognized: &'a [String] },
+}
+
+fn put_cstr(buf: &mut BytesMut, s: &str) {
+    buf.put_slice(s.as_bytes());
+    buf.put_u8(0);
+}
+
+/// 按格式编码一个值，不含长度字段。NULL 由调用者处理。
+fn put_value(buf: &mut BytesMut, value: &Value, format: i16) {
+    match (value, format) {
+        (Value::Integer(i), FORMAT_BINARY) => buf.put_i64(*i),
+        (Value::Integer(i), _) => buf.put_slice(i.to_string().as_bytes()),
+        (Value::String(s), _) => buf.put_slice(s.as_bytes()),
+        (Value::Bytes(b), FORMAT_BINARY) => buf.put_slice(b),
+        (Value::Bytes(b), _) => {
+            buf.put_slice(b"\\x");
+            for byte in b {
+                buf.put_slice(format!("{:02x}", byte).as_bytes());
+            }
+        }
+        (Value::Null, _) => {}
+    }
+}
+
+impl BackendMessage<'_> {
+    fn tag(&self) -> u8 {
+        match self {
+            BackendMessage::AuthenticationOk
+            | BackendMessage::AuthenticationSasl { .. }
+            | BackendMessage::AuthenticationSaslContinue(_)
+            | BackendMessage::AuthenticationSaslFinal(_) => b'R',
+            BackendMessage::ParameterStatus { .. } => b'S',
+            BackendMessage::BackendKeyData { .. } => b'K',
+            BackendMessage::ReadyForQuery(_) => b'Z',
+            BackendMessage::RowDescription { .. } => b'T',
+            BackendMessage::DataRow { .. } => b'D',
+            BackendMessage::CommandComplete(_) => b'C',
+            BackendMessage::EmptyQueryResponse => b'I',
+            BackendMessage::ErrorResponse { .. } | BackendMessage::Fatal { .. } => b'E',
+            BackendMessage::NoticeResponse { .. } => b'N',
+            BackendMessage::ParseComplete => b'1',
+            BackendMessage::BindComplete => b'2',
+            BackendMessage::CloseComplete => b'3',
+            BackendMessage::ParameterDescription(_) => b't',
+            BackendMessage::NoData => b'n',
+            BackendMessage::PortalSuspended => b's',
+            BackendMessage::NegotiateProtocolVersion { .. } => b'v',
+        }
+    }
+
+    /// 把消息追加到 `buf`
+    pub fn encode(&self, buf: &mut BytesMut) {
+        let start = buf.len();
+        buf.put_u8(self.tag());
+        // Length placeholder, filled in once the body is written.
+        buf.put_i32(0);
+        match self {
+            BackendMessage::AuthenticationOk => buf.put_i32(0),
+            BackendMessage::AuthenticationSasl { mechanisms } => {
+                buf.put_i32(10);
+                for mechanism in mechanisms.iter() {
+                    put_cstr(buf, mechanism);
+                }
+                buf.put_u8(0);
+            }
+            BackendMessage::AuthenticationSaslContinue(data) => {
+                buf.put_i32(11);
+                buf.put_slice(data);
+            }
+            BackendMessage::AuthenticationSaslFinal(data) => {
+                buf.put_i32(12);
+                buf.put_slice(data);
+            }
+            BackendMessage::ParameterStatus { name, value } => {
+                put_cstr(buf, name);
+                put_cstr(buf, value);
+            }
+            BackendMessage::BackendKeyData { process_id, secret_key } => {
+                buf.put_i32(*process_id);
+                buf.put_i32(*secret_key);
+            }
+            BackendMessage::ReadyForQuery(status) => buf.put_u8(*status),
+            BackendMessage::RowDescription { columns, formats } => {
+                buf.put_i16(columns.len() as i16);
+                for (i, column) in columns.iter().enumerate() {
+                    let (type_oid, type_len) = type_info(&column.data_type);
+                    put_cstr(buf, &column.name);
+                    buf.put_i32(0); // table oid
+                    buf.put_i16(0); // attribute number
+                    buf.put_u32(type_oid);
+                    buf.put_i16(type_len);
+                    buf.put_i32(-1); // type modifier
+                    buf.put_i16(format_for(formats, i));
+                }
+            }
+            BackendMessage::DataRow { values, formats } => {
+                buf.put_i16(values.len() as i16);
+                for (i, value) in values.iter().enumerate() {
+                    if let Value::Null = value {
+                        buf.put_i32(-1);
+                        continue;
+                    }
+                    let len_at = buf.len();
+                    buf.put_i32(0);
+                    put_value(buf, value, format_for(formats, i));
+                    let len = (buf.len() - len_at - 4) as i32;
+                    buf[len_at..len_at + 4].copy_from_slice(&len.to_be_bytes());
+                }
+            }
+            BackendMessage::CommandComplete(tag) => put_cstr(buf, tag),
+            BackendMessage::ErrorResponse { code, message } => put_error_fields(buf, "ERROR", code, message),
+            BackendMessage::Fatal { code, message } => put_error_fields(buf, "FATAL", code, message),
+            BackendMessage::NoticeResponse { message } => put_error_fields(buf, "NOTICE", "00000", message),
+            BackendMessage::ParameterDescription(types) => {
+                buf.put_i16(types.len() as i16);
+                for &oid in types.iter() {
+                    buf.put_u32(oid);
+                }
+            }
+            BackendMessage::NegotiateProtocolVersion { minor, unrecognized } => {
+                buf.put_i32(*minor);
+                buf.put_i32(unrecognized.len() as i32);
+                for option in unrecognized.iter() {
+                    put_cstr(buf, option);
+                }
+            }
+            BackendMessage::EmptyQueryResponse
+            | BackendMessage::ParseComplete
+            | BackendMessage::BindComplete
+            | BackendMessage::CloseComplete
+            | BackendMessage::NoData
+            | BackendMessage::PortalSuspended => {}
+        }
+        let len = (buf.len() - start - 1) as i32;
+        buf[start + 1..start + 5].copy_from_slice(&len.to_be_bytes());
+    }
+}
+
+fn put_error_fields(buf: &mut BytesMut, severity: &str, code: &str, message: &str) {
+    for (field, value) in [(b'S', severity), (b'V', severity), (b'C', code), (b'M', message)] {
+        buf.put_u8(field);
+        put_cstr(buf, value);
+    }
+    buf.put_u8(0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_bind_and_encode_data_row() {
+        let mut body = BytesMut::new();
+        put_cstr(&mut body, "portal");
+        put_cstr(&mut body, "stmt");
+        body.put_i16(1);
+        body.put_i16(FORMAT_BINARY);
+        body.put_i16(2);
+        body.put_i32(-1);
+        body.put_i32(2);
+        body.put_slice(b"hi");
+        body.put_i16(1);
+        body.put_i16(FORMAT_BINARY);
+        assert_eq!(
+            FrontendMessage::decode(b'B', &body).unwrap(),
+            FrontendMessage::Bind {
+                portal: "portal".to_string(),
+                statement: "stmt".to_string(),
+                param_formats: vec![FORMAT_BINARY],
+                params: vec![None, Some(b"hi".to_vec())],
+                result_formats: vec![FORMAT_BINARY],
+            }
+        );
+        assert!(FrontendMessage::decode(b'B', &body[..body.len() - 1]).is_err());
+        assert!(FrontendMessage::decode(b'D', b"Xname\0").is_err());
+
+        let values = [Value::Integer(-2), Value::Null, Value::Bytes(vec![0xab, 0x01])];
+        let mut buf = BytesMut::new();
+        BackendMessage::DataRow { values: &values, formats: &[] }.encode(&mut buf);
+        let mut expected = vec![b'D', 0, 0, 0, 26, 0, 3];
+        expected.extend_from_slice(&[0, 0, 0, 2]);
+        expected.extend_from_slice(b"-2");
+        expected.extend_from_slice(&[0xff, 0xff, 0xff, 0xff]);
+        expected.extend_from_slice(&[0, 0, 0, 6]);
+        expected.extend_from_slice(b"\\xab01");
+        assert_eq!(&buf[..], &expected[..]);
+    }
+
+    #[test]
+    fn test_decode_params() {
+        let decode = |data: &[u8], format, data_type| decode_param(Some(data), format, &data_type);
+        assert!(matches!(decode(b" -7", FORMAT_TEXT, DataType::Int), Ok(Value::Integer(-7))));
+        assert!(matches!(decode(&[0, 0, 1, 0], FORMAT_BINARY, DataType::Int), Ok(Value::Integer(256))));
+        assert!(decode(&[0, 1, 0], FORMAT_BINARY, DataType::Int).is_err());
+        assert!(decode(b"seven", FORMAT_TEXT, DataType::Int).is_err());
+        assert!(matches!(decode(b"\\xAB01", FORMAT_TEXT, DataType::Bytea), Ok(Value::Bytes(b)) if b == [0xab, 1]));
+        assert!(decode(b"\\xA", FORMAT_TEXT, DataType::Bytea).is_err());
+        assert!(decode(&[0xff], FORMAT_TEXT, DataType::Varchar).is_err());
+        assert!(matches!(decode_param(None, FORMAT_BINARY, &DataType::Int), Ok(Value::Null)));
+        assert_eq!(param_type(23), Ok(Some(DataType::Int)));
+        assert!(param_type(701).is_err());
+    }
+
+    /// xorshift64，固定种子使失败可以复现
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: usize) -> usize {
+            (self.next() % n as u64) as usize
+        }
+    }
+
+    fn frame(tag: u8, body: &[u8]) -> Vec<u8> {
+        let mut buf = vec![tag];
+        buf.extend_from_slice(&(body.len() as i32 + 4).to_be_bytes());
+        buf.extend_from_slice(body);
+        buf
+    }
+
+    /// 像服务端一样逐条切分并解码消息，遇到长度错误时停止
+    fn decode_stream(mut data: &[u8], max_len: usize) -> usize {
+        let mut decoded = 0;
+        while data.len() >= 5 {
+            let Ok(len) = body_len(data[1..5].try_into().unwrap(), max_len) else {
+                break;
+            };
+            let Some(body) = data.get(5..5 + len) else {
+                break;
+            };
+            if FrontendMessage::decode(data[0], body).is_ok() {
+                decoded += 1;
+            }
+            data = &data[5 + len..];
+        }
+        decoded
+    }
+
+    #[test]
+    fn test_fuzz_frame_decoder() {
+        assert_eq!(body_len([0, 0, 0, 4], 4), Ok(0));
+        assert_eq!(body_len([0, 0, 0, 3], 1024), Err(FrameError::InvalidLength(3)));
+        assert_eq!(body_len([0xff, 0xff, 0xff, 0xff], 1024), Err(FrameError::InvalidLength(-1)));
+        assert_eq!(
+            body_len([0x7f, 0xff, 0xff, 0xff], 1024),
+            Err(FrameError::TooLong {
+                len: i32::MAX as usize,
+                max_len: 1024
+            })
+        );
+        assert!(FrontendMessage::decode(b'S', b"x").is_err());
+
+        let mut parse = BytesMut::new();
+        put_cstr(&mut parse, "s1");
+        put_cstr(&mut parse, "SELECT id FROM t");
+        parse.put_i16(1);
+        parse.put_i32(20);
+        let mut bind = BytesMut::new();
+        put_cstr(&mut bind, "");
+        put_cstr(&mut bind, "s1");
+        bind.put_i16(0);
+        bind.put_i16(1);
+        bind.put_i32(1);
+        bind.put_slice(b"7");
+        bind.put_i16(0);
+        let mut stream = Vec::new();
+        for (tag, body) in [
+            (b'Q', &b"SELECT 1\0"[..]),
+            (b'P', &parse[..]),
+            (b'B', &bind[..]),
+            (b'D', &b"Pname\0"[..]),
+            (b'E', &b"\0\0\0\0\x10"[..]),
+            (b'C', &b"S\0"[..]),
+            (b'S', &b""[..]),
+        ] {
+            stream.extend(frame(tag, body));
+        }
+        assert_eq!(decode_stream(&stream, 1024), 7);
+        let mut startup = PROTOCOL_VERSION.to_be_bytes().to_vec();
+        startup.extend_from_slice(b"user\0test\0\0");
+
+        // Mutated valid input and pure noise must only ever produce errors, never panics.
+        let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+        for _ in 0..20_000 {
+            let mut data = if rng.below(4) == 0 { startup.clone() } else { stream.clone() };
+            for _ in 0..1 + rng.below(4) {
+                let at = rng.below(data.len().max(1));
+                match rng.below(4) {
+                    0 if !data.is_empty() => data[at] = rng.next() as u8,
+                    1 => data.truncate(at),
+                    2 => data.insert(at.min(data.len()), rng.next() as u8),
+                    _ => data = (0..rng.below(64)).map(|_| rng.next() as u8).collect(),
+                }
+            }
+            decode_stream(&data, 1024);
+            let _ = StartupRequest::decode(&data);
+            for tag in [b'Q', b'P', b'B', b'D', b'E', b'C', rng.next() as u8] {
+                let _ = FrontendMessage::decode(tag, &data);
+            }
+        }
+    }
+}
@@ -0,0 +1,903 @@
+//! PostgreSQL v3 前端/后端协议，使 psql 和各种 PostgreSQL 驱动可以直接连接。
+//!
+//! 支持启动握手（SCRAM-SHA-256 口令认证或不认证，不支持 SSL，请求 3.x 的更高次版本时协商回 3.0）、简单查询以及扩展查询的
+//! Parse/Bind/Describe/Execute/Close/Sync/Flush。查询结果边扫描边发送，
+//! Execute 可以指定最大行数分批取回，正在执行的语句可以用 CancelRequest 取消。预备语句与 SQL 的 PREPARE 共用会话中的缓存，
+//! 参数可以用文本或二进制格式绑定。所有语句都自动提交，所以 ReadyForQuery 的事务状态总是空闲。
+pub mod message;
+
+use std::{
+    collections::HashMap,
+    io,
+    str::FromStr,
+    sync::Arc,
+    time::Duration,
+};
+
+use bytes::BytesMut;
+use monoio::{
+    io::{AsyncReadRentExt, AsyncWriteRentExt, BufReader},
+    net::TcpStream,
+};
+
+use crate::{
+    Database,
+    auth::{self, ScramError, ScramServer},
+    executor::{ExecutionResult, RowStream, Tuple},
+    pgwire::message::{
+        BackendMessage, FORMAT_BINARY, FORMAT_TEXT, FrontendMessage, MAX_STARTUP_PACKET_LENGTH, PROTOCOL_MAJOR,
+        PROTOCOL_OPTION_PREFIX, StartupRequest, body_len, decode_param, decode_sasl_initial_response, format_for,
+        param_type, sqlstate, type_info,
+    },
+    session::{PreparedStatement, Session},
+    sql::{Statement, ast::Column, parse_sql, split_statements},
+};
+
+/// 输出缓冲区超过这个大小时先写给客户端，避免大结果集全部堆在内存里
+const FLUSH_THRESHOLD: usize = 64 * 1024;
+
+/// 客户端完成认证的时限，超时后断开连接，与 PostgreSQL 的 `authentication_timeout` 默认值相同
+const AUTHENTICATION_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// 客户端连接时的认证方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AuthMethod {
+    /// 不认证，任何能连上端口的客户端都可以执行语句
+    Trust,
+    /// 用目录中保存的口令校验信息做 SCRAM-SHA-256 认证
+    #[default]
+    ScramSha256,
+}
+
+impl FromStr for AuthMethod {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "trust" => Ok(AuthMethod::Trust),
+            "scram-sha-256" => Ok(AuthMethod::ScramSha256),
+            _ => Err(format!(
+                "invalid value for auth_method: '{}' (expected trust or scram-sha-256)",
+                s
+            )),
+        }
+    }
+}
+
+/// 协议层的配置
+#[derive(Debug, Clone, Copy)]
+pub struct PgwireConfig {
+    /// 一条前端消息（包括长度字段）的最大字节数，超过时断开连接
+    pub max_message_size: usize,
+    pub auth_method: AuthMethod,
+}
+
+impl Default for PgwireConfig {
+    fn default() -> Self {
+        Self {
+            max_message_size: 64 * 1024 * 1024,
+            auth_method: AuthMethod::default(),
+        }
+    }
+}
+
+/// 认证没有完成的原因
+enum AuthError {
+    Io(io::Error),
+    /// 发给客户端的 FATAL 错误，以及只写进服务端日志的原因
+    Failed(PgError, String),
+}
+
+impl From<io::Error> for AuthError {
+    fn from(e: io::Error) -> Self {
+        AuthError::Io(e)
+    }
+}
+
+/// 出错时发给客户端的 SQLSTATE 和消息
+struct PgError {
+    code: &'static str,
+    message: String,
+}
+
+impl PgError {
+    fn new(code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+
+    /// 执行器的错误只有文本，按消息内容归类
+    fn from_execution(message: String) -> Self {
+        let code = if message.starts_with("canceling statement") {
+            sqlstate::QUERY_CANCELED
+        } else if message.starts_with("prepared statement") && message.ends_with("already exists") {
+            sqlstate::DUPLICATE_PREPARED_STATEMENT
+        } else if message.starts_with("prepared statement") && message.ends_with("does not exist") {
+            sqlstate::INVALID_SQL_STATEMENT_NAME
+        } else if message.starts_with("wrong number of parameters") {
+            sqlstate::SYNTAX_ERROR
+        } else if message.starts_with("there is no parameter") {
+            sqlstate::UNDEFINED_PARAMETER
+        } else if message.starts_with("could not determine data type") {
+            sqlstate::INDETERMINATE_DATATYPE
+        } else if message.starts_with("invalid input syntax") {
+            sqlstate::INVALID_TEXT_REPRESENTATION
+        } else if message.starts_with("incorrect binary data format") {
+            sqlstate::INVALID_BINARY_REPRESENTATION
+        } else if message.starts_with("invalid byte sequence") {
+            sqlstate::CHARACTER_NOT_IN_REPERTOIRE
+        } else if message.starts_with("unsupported parameter type") {
+            sqlstate::FEATURE_NOT_SUPPORTED
+        } else if message.contains("not found in") {
+            sqlstate::UNDEFINED_COLUMN
+        } else if message.contains("not found") {
+            sqlstate::UNDEFINED_TABLE
+        } else if message.starts_with("permission denied") {
+            sqlstate::INSUFFICIENT_PRIVILEGE
+        } else if message.starts_with("User '") && message.ends_with("already exists.") {
+            sqlstate::DUPLICATE_OBJECT
+        } else if message.contains("already exists") {
+            sqlstate::DUPLICATE_TABLE
+        } else if message.starts_with("unrecognized configuration parameter") {
+            sqlstate::UNDEFINED_OBJECT
+        } else if message.starts_with("invalid value") {
+            sqlstate::INVALID_PARAMETER_VALUE
+        } else if message.contains("corrupted") {
+            sqlstate::DATA_CORRUPTED
+        } else {
+            sqlstate::INTERNAL_ERROR
+        };
+        Self { code, message }
+    }
+}
+
+enum PortalState {
+    Empty,
+    Ready(Statement),
+    /// Execute 取到最大行数后暂停的查询，下一次 Execute 接着发送
+    Suspended { statement: Statement, cursor: RowCursor },
+    /// 已经执行过，再次执行只返回命令标签
+    Done(String),
+}
+
+/// 正在发送的查询结果
+struct RowCursor {
+    rows: RowStream,
+    /// 已经从 `rows` 取出但还没发送的行
+    pending: std::vec::IntoIter<Tuple>,
+    /// 本次 Execute 已经发送的行数
+    sent: usize,
+}
+
+impl RowCursor {
+    fn new(rows: RowStream) -> Self {
+        Self {
+            rows,
+            pending: Vec::new().into_iter(),
+            sent: 0,
+        }
+    }
+}
+
+struct Portal {
+    state: PortalState,
+    query: String,
+    /// 结果列，不返回行的语句为 `None`
+    columns: Option<Vec<Column>>,
+    result_formats: Vec<i16>,
+}
+
+struct Connection {
+    stream: BufReader<TcpStream>,
+    out: BytesMut,
+    db: Arc<Database>,
+    config: PgwireConfig,
+    client_addr: Option<String>,
+    session: Session,
+    portals: HashMap<String, Portal>,
+    /// 扩展查询出错后丢弃消息直到 Sync
+    skip_until_sync: bool,
+}
+
+/// 处理一个客户端连接直到它断开。服务端关闭时，正在执行的语句先执行完，
+/// 之后连接收到 FATAL 消息后被关闭。
+pub async fn serve_connection(stream: TcpStream, db: Arc<Database>, config: PgwireConfig) -> io::Result<()> {
+    let db_ref = db.clone();
+    let _guard = db_ref.shutdown().track_connection();
+    // Replies are batched in `out` already; Nagle would only hold back the tail of a
+    // result until the client's delayed ACK.
+    stream.set_nodelay(true)?;
+    let client_addr = stream.peer_addr().ok().map(|addr| addr.to_string());
+    let session = db.new_session();
+    let mut conn = Connection {
+        stream: BufReader::new(stream),
+        out: BytesMut::with_capacity(8192),
+        db,
+        config,
+        client_addr,
+        session,
+        portals: HashMap::new(),
+        skip_until_sync: false,
+    };
+    if !conn.startup().await? {
+        return Ok(());
+    }
+    conn.run().await
+}
+
+/// 把读到连接末尾视为正常断开
+fn eof_to_none<T>(res: io::Result<T>) -> io::Result<Option<T>> {
+    match res {
+        Ok(value) => Ok(Some(value)),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+impl Connection {
+    async fn read_exact(&mut self, len: usize) -> io::Result<Vec<u8>> {
+        // read_exact fills the whole capacity, so the buffer must be exactly `len` long.
+        let (res, buf) = self.stream.read_exact(vec![0u8; len]).await;
+        res.map(|_| buf)
+    }
+
+    /// 读取 4 字节长度（包括自身）及其后的内容。长度不合法时先给客户端发送 FATAL，
+    /// 再返回错误关闭连接，不会按客户端给出的长度分配内存
+    async fn read_body(&mut self, max_len: usize) -> io::Result<Vec<u8>> {
+        let header = self.read_exact(4).await?;
+        match body_len(header.try_into().unwrap(), max_len) {
+            Ok(len) => self.read_exact(len).await,
+            Err(e) => {
+                let message = e.to_string();
+                self.fatal(e.code(), &message).await?;
+                Err(io::Error::new(io::ErrorKind::InvalidData, message))
+            }
+        }
+    }
+
+    async fn flush(&mut self) -> io::Result<()> {
+        if self.out.is_empty() {
+            return Ok(());
+        }
+        let (res, mut buf) = self.stream.write_all(std::mem::take(&mut self.out)).await;
+        buf.clear();
+        self.out = buf;
+        res.map(|_| ())
+    }
+
+    fn send(&mut self, message: BackendMessage<'_>) {
+        message.encode(&mut self.out);
+    }
+
+    fn send_error(&mut self, error: &PgError) {
+        self.send(BackendMessage::ErrorResponse {
+            code: error.code,
+            message: &error.message,
+        });
+    }
+
+    async fn fatal(&mut self, code: &'static str, message: &str) -> io::Result<()> {
+        self.send(BackendMessage::Fatal { code, message });
+        self.flush().await
+    }
+
+    /// 完成启动握手，客户端在握手中断开或只是发送取消请求时返回 false
+    async fn startup(&mut self) -> io::Result<bool> {
+        let params = loop {
+            let Some(body) = eof_to_none(self.read_body(MAX_STARTUP_PACKET_LENGTH).await)? else {
+                return Ok(false);
+            };
+            match StartupRequest::decode(&body) {
+                Ok(StartupRequest::Encryption) => {
+                    // Encryption is not supported; the client continues in plain text.
+                    let (res, _) = self.stream.write_all(b"N".to_vec()).await;
+                    res?;
+                }
+                Ok(StartupRequest::Cancel { process_id, secret_key }) => {
+                    // The statement stops on the worker that owns the session, at its next check.
+                    // Like PostgreSQL, the sender gets no reply either way.
+                    if !self.session.registry().cancel(process_id, secret_key) {
+                        log::warn!("Ignoring cancel request for unknown session {} or with a wrong key", process_id);
+                    }
+                    return Ok(false);
+                }
+                Ok(StartupRequest::Startup { .. }) if self.db.shutdown().is_requested() => {
+                    self.fatal(sqlstate::CANNOT_CONNECT_NOW, "the database system is shutting down")
+                        .await?;
+                    return Ok(false);
+                }
+                Ok(StartupRequest::Startup { version, params }) if version >> 16 == PROTOCOL_MAJOR => {
+                    break self.negotiate(version & 0xffff, params);
+                }
+                Ok(StartupRequest::Startup { version, .. }) => {
+                    let message = format!(
+                        "unsupported frontend protocol {}.{}: server supports 3.0 to 3.0",
+                        version >> 16,
+                        version & 0xffff
+                    );
+                    self.fatal(sqlstate::FEATURE_NOT_SUPPORTED, &message).await?;
+                    return Ok(false);
+                }
+                Err(e) => {
+                    self.fatal(sqlstate::PROTOCOL_VIOLATION, &format!("invalid startup packet: {}", e))
+                        .await?;
+                    return Ok(false);
+                }
+            }
+        };
+
+        let param = |key: &str| {
+            params
+                .iter()
+                .find(|(name, _)| name == key)
+                .map_or("", |(_, value)| value.as_str())
+        };
+        let user = param("user");
+        if user.is_empty() {
+            self.fatal(
+                sqlstate::INVALID_AUTHORIZATION_SPECIFICATION,
+                "no PostgreSQL user name specified in startup packet",
+            )
+            .await?;
+            return Ok(false);
+        }
+        if !self.authenticate(user).await? {
+            return Ok(false);
+        }
+        let application_name = param("application_name");
+        self.session.set_client(user, application_name, self.client_addr.clone());
+        self.send(BackendMessage::AuthenticationOk);
+        for (name, value) in [
+            ("server_version", "16.0"),
+            ("server_encoding", "UTF8"),
+            ("client_encoding", "UTF8"),
+            ("DateStyle", "ISO, MDY"),
+            ("integer_datetimes", "on"),
+            ("standard_conforming_strings", "on"),
+            ("application_name", application_name),
+        ] {
+            self.send(BackendMessage::ParameterStatus { name, value });
+        }
+        self.send(BackendMessage::BackendKeyData {
+            process_id: self.session.id(),
+            secret_key: self.session.secret_key(),
+        });
+        self.send(BackendMessage::ReadyForQuery(b'I'));
+        self.flush().await?;
+        Ok(true)
+    }
+
+    /// 按配置的认证方式验证用户。失败时记录日志并发送 FATAL，返回 false 后连接在执行任何语句之前关闭
+    async fn authenticate(&mut self, user: &str) -> io::Result<bool> {
+        if self.config.auth_method == AuthMethod::Trust {
+            return Ok(true);
+        }
+        let client = self.client_addr.clone().unwrap_or_default();
+        let exchange = monoio::time::timeout(AUTHENTICATION_TIMEOUT, self.scram_exchange(user)).await;
+        match exchange {
+            Ok(Ok(())) => Ok(true),
+            Err(_) => {
+                log::warn!("authentication timed out for user \"{}\" from {}", user, client);
+                Ok(false)
+            }
+            // psql hangs up after the first request to ask for the password, like PostgreSQL
+            // this is not a failed attempt.
+            Ok(Err(AuthError::Io(e))) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+            Ok(Err(AuthError::Io(e))) => Err(e),
+            Ok(Err(AuthError::Failed(error, reason))) => {
+                log::warn!(
+                    "authentication failed for user \"{}\" from {}: {}",
+                    user,
+                    client,
+                    reason
+                );
+                self.fatal(error.code, &error.message).await?;
+                Ok(false)
+            }
+        }
+    }
+
+    /// SASL 认证中客户端的一条消息。此时还没有认证，按启动包的长度上限读取
+    async fn read_sasl_response(&mut self) -> Result<Vec<u8>, AuthError> {
+        let tag = self.read_exact(1).await?[0];
+        let body = self.read_body(MAX_STARTUP_PACKET_LENGTH).await?;
+        if tag != b'p' {
+            let message = format!("expected SASL response, got message type {}", tag);
+            return Err(AuthError::Failed(
+                PgError::new(sqlstate::PROTOCOL_VIOLATION, message.clone()),
+                message,
+            ));
+        }
+        Ok(body)
+    }
+
+    /// 与客户端完成一次 SCRAM-SHA-256 交换。用户不存在时用随机的校验信息照常交换，
+    /// 客户端看到的结果与口令错误相同
+    async fn scram_exchange(&mut self, user: &str) -> Result<(), AuthError> {
+        let verifier = self.db.user_verifier(user);
+        let user_exists = verifier.is_some();
+        let failed = |e: ScramError| {
+            let reason = if user_exists {
+                e.to_string()
+            } else {
+                format!("user does not exist ({})", e)
+            };
+            let error = match e {
+                ScramError::InvalidMessage(message) => PgError::new(sqlstate::PROTOCOL_VIOLATION, message),
+                ScramError::WrongPassword => PgError::new(
+                    sqlstate::INVALID_PASSWORD,
+                    format!("password authentication failed for user \"{}\"", user),
+                ),
+            };
+            AuthError::Failed(error, reason)
+        };
+
+        self.send(BackendMessage::AuthenticationSasl {
+            mechanisms: &[auth::MECHANISM],
+        });
+        self.flush().await?;
+        let body = self.read_sasl_response().await?;
+        let (mechanism, client_first) = decode_sasl_initial_response(&body)
+            .map_err(|e| failed(ScramError::InvalidMessage(e)))?;
+        if mechanism != auth::MECHANISM {
+            return Err(failed(ScramError::InvalidMessage(
+                "client selected an invalid SASL authentication mechanism".to_string(),
+            )));
+        }
+        let verifier = verifier.unwrap_or_else(|| self.db.mock_verifier(user));
+        let (server, server_first) = ScramServer::start(verifier, &client_first).map_err(failed)?;
+
+        self.send(BackendMessage::AuthenticationSaslContinue(server_first.as_bytes()));
+        self.flush().await?;
+        let client_final = self.read_sasl_response().await?;
+        let server_final = server.finish(&client_final).map_err(failed)?;
+        self.send(BackendMessage::AuthenticationSaslFinal(server_final.as_bytes()));
+        Ok(())
+    }
+
+    /// 客户端请求 3.x 的更高次版本或协议选项时回复 NegotiateProtocolVersion，
+    /// 之后按 3.0 继续。返回去掉协议选项后的启动参数
+    fn negotiate(&mut self, minor: i32, params: Vec<(String, String)>) -> Vec<(String, String)> {
+        let (options, params): (Vec<_>, Vec<_>) =
+            params.into_iter().partition(|(name, _)| name.starts_with(PROTOCOL_OPTION_PREFIX));
+        if minor > 0 || !options.is_empty() {
+            let unrecognized: Vec<String> = options.into_iter().map(|(name, _)| name).collect();
+            self.send(BackendMessage::NegotiateProtocolVersion {
+                minor: 0,
+                unrecognized: &unrecognized,
+            });
+        }
+        params
+    }
+
+    async fn run(&mut self) -> io::Result<()> {
+        let db = self.db.clone();
+        loop {
+            // Shutdown only interrupts a connection between messages, so a running
+            // statement always completes and gets its reply.
+            let header = monoio::select! {
+                header = self.read_exact(1) => header,
+                _ = db.shutdown().requested() => {
+                    return self
+                        .fatal(sqlstate::ADMIN_SHUTDOWN, "terminating connection due to administrator command")
+                        .await;
+                }
+            };
+            let Some(header) = eof_to_none(header)? else {
+                return Ok(());
+            };
+            let body = self.read_body(self.config.max_message_size).await?;
+            let message = match FrontendMessage::decode(header[0], &body) {
+                Ok(message) => message,
+                Err(e) => return self.fatal(sqlstate::PROTOCOL_VIOLATION, &e).await,
+            };
+
+            if self.skip_until_sync && !matches!(message, FrontendMessage::Sync | FrontendMessage::Terminate) {
+                continue;
+            }
+            let res = match message {
+                FrontendMessage::Query(sql) => {
+                    self.simple_query(&sql).await?;
+                    self.send(BackendMessage::ReadyForQuery(b'I'));
+                    self.flush().await?;
+                    Ok(())
+                }
+                FrontendMessage::Parse {
+                    name,
+                    query,
+                    param_types,
+                } => self.parse(name, &query, &param_types),
+                FrontendMessage::Bind {
+                    portal,
+                    statement,
+                    param_formats,
+                    params,
+                    result_formats,
+                } => self.bind(portal, &statement, &param_formats, &params, result_formats),
+                FrontendMessage::Describe { kind, name } => self.describe(kind, &name),
+                FrontendMessage::Execute { portal, max_rows } => {
+                    // Zero (or a negative count) means no limit.
+                    self.execute_portal(&portal, max_rows.max(0) as usize).await?
+                }
+                FrontendMessage::Close { kind, name } => {
+                    if kind == b'S' {
+                        // Closing a statement that does not exist is not an error.
+                        let _ = self.session.deallocate(Some(&name));
+                    } else {
+                        self.portals.remove(&name);
+                    }
+                    self.send(BackendMessage::CloseComplete);
+                    Ok(())
+                }
+                FrontendMessage::Sync => {
+                    // Every statement commits on its own, so a Sync ends all portals.
+                    self.portals.clear();
+                    self.skip_until_sync = false;
+                    self.send(BackendMessage::ReadyForQuery(b'I'));
+                    self.flush().await?;
+                    Ok(())
+                }
+                FrontendMessage::Flush => {
+                    self.flush().await?;
+                    Ok(())
+                }
+                FrontendMessage::Terminate => return self.flush().await,
+            };
+            if let Err(e) = res {
+                self.send_error(&e);
+                self.skip_until_sync = true;
+            }
+        }
+    }
+
+    /// 简单查询：依次执行文本中的每条语句，遇到错误时停止
+    async fn simple_query(&mut self, sql: &str) -> io::Result<()> {
+        self.session.start_query(sql);
+        let res = self.run_simple_query(sql).await;
+        self.session.finish_query();
+        res
+    }
+
+    async fn run_simple_query(&mut self, sql: &str) -> io::Result<()> {
+        let _ = self.session.deallocate(Some(""));
+        self.portals.remove("");
+        let statements = split_statements(sql);
+        if statements.is_empty() {
+            self.send(BackendMessage::EmptyQueryResponse);
+        }
+        for text in statements {
+            let statement = match parse_sql(text) {
+                Ok(statement) => statement,
+                Err(e) => {
+                    self.send_error(&PgError::new(sqlstate::SYNTAX_ERROR, e.to_string()));
+                    break;
+                }
+            };
+            // EXECUTE reports the command tag of the statement it runs.
+            let statement = match self.session.resolve(statement) {
+                Ok(statement) => statement,
+                Err(e) => {
+                    self.send_error(&PgError::from_execution(e));
+                    break;
+                }
+            };
+            let result = match self.db.execute_statement(&mut self.session, statement.clone()).await {
+                Ok(result) => self.send_result(&statement, result, &[], true).await?,
+                Err(e) => Err(PgError::from_execution(e)),
+            };
+            if let Err(e) = result {
+                self.send_error(&e);
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// 解析语句并推断参数类型，保存到会话的预备语句中
+    fn parse(&mut self, name: String, query: &str, param_oids: &[u32]) -> Result<(), PgError> {
+        let statement = match split_statements(query)[..] {
+            [] => None,
+            [text] => Some(parse_sql(text).map_err(|e| PgError::new(sqlstate::SYNTAX_ERROR, e.to_string()))?),
+            _ => {
+                return Err(PgError::new(
+                    sqlstate::SYNTAX_ERROR,
+                    "cannot insert multiple commands into a prepared statement",
+                ));
+            }
+        };
+        let declared = param_oids
+            .iter()
+            .map(|&oid| param_type(oid))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(PgError::from_execution)?;
+        let param_types = match &statement {
+            Some(statement) => self.db.param_types(statement, &declared),
+            None => Ok(Vec::new()),
+        }
+        .map_err(PgError::from_execution)?;
+        self.session
+            .prepare(
+                name,
+                PreparedStatement {
+                    statement,
+                    query: query.to_string(),
+                    param_types,
+                },
+            )
+            .map_err(PgError::from_execution)?;
+        self.send(BackendMessage::ParseComplete);
+        Ok(())
+    }
+
+    fn prepared(&self, name: &str) -> Result<&PreparedStatement, PgError> {
+        self.session.prepared(name).map_err(PgError::from_execution)
+    }
+
+    fn columns_of(&self, statement: Option<&Statement>) -> Result<Option<Vec<Column>>, PgError> {
+        match statement {
+            Some(statement) => {
+                let statement = self.session.resolve(statement.clone()).map_err(PgError::from_execution)?;
+                self.db.describe(&statement).map_err(PgError::from_execution)
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// 按预备语句的参数类型解码参数并绑定到语句上，创建门户
+    fn bind(
+        &mut self,
+        portal: String,
+        statement_name: &str,
+        param_formats: &[i16],
+        params: &[Option<Vec<u8>>],
+        result_formats: Vec<i16>,
+    ) -> Result<(), PgError> {
+        let prepared = self.prepared(statement_name)?;
+        if params.len() != prepared.param_types.len() {
+            return Err(PgError::new(
+                sqlstate::PROTOCOL_VIOLATION,
+                format!(
+                    "bind message supplies {} parameters, but prepared statement \"{}\" requires {}",
+                    params.len(),
+                    statement_name,
+                    prepared.param_types.len()
+                ),
+            ));
+        }
+        if param_formats.len() > 1 && param_formats.len() != params.len() {
+            return Err(PgError::new(
+                sqlstate::PROTOCOL_VIOLATION,
+                format!(
+                    "bind message has {} parameter formats but {} parameters",
+                    param_formats.len(),
+                    params.len()
+                ),
+            ));
+        }
+        if let Some(format) = param_formats.iter().find(|&&f| f != FORMAT_TEXT && f != FORMAT_BINARY) {
+            return Err(PgError::new(
+                sqlstate::PROTOCOL_VIOLATION,
+                format!("unsupported format code: {}", format),
+            ));
+        }
+        let values = params
+            .iter()
+            .zip(&prepared.param_types)
+            .enumerate()
+            .map(|(i, (param, data_type))| decode_param(param.as_deref(), format_for(param_formats, i), data_type))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(PgError::from_execution)?;
+        let statement = prepared
+            .statement
+            .as_ref()
+            .map(|statement| statement.bind(&values).and_then(|statement| self.session.resolve(statement)))
+            .transpose()
+            .map_err(PgError::from_execution)?;
+        let query = prepared.query.clone();
+        let columns = self.columns_of(statement.as_ref())?;
+        let column_count = columns.as_ref().map_or(0, Vec::len);
+        if result_formats.len() > 1 && result_formats.len() != column_count {
+            return Err(PgError::new(
+                sqlstate::PROTOCOL_VIOLATION,
+                format!(
+                    "bind message has {} result formats but query has {} columns",
+                    result_formats.len(),
+                    column_count
+                ),
+            ));
+        }
+        if let Some(format) = result_formats.iter().find(|&&f| f != FORMAT_TEXT && f != FORMAT_BINARY) {
+            return Err(PgError::new(
+                sqlstate::PROTOCOL_VIOLATION,
+                format!("unsupported format code: {}", format),
+            ));
+        }
+
+        let state = match statement {
+            Some(statement) => PortalState::Ready(statement),
+            None => PortalState::Empty,
+        };
+        self.portals.insert(
+            portal,
+            Portal {
+                state,
+                query,
+                columns,
+                result_formats,
+            },
+        );
+        self.send(BackendMessage::BindComplete);
+        Ok(())
+    }
+
+    fn describe(&mut self, kind: u8, name: &str) -> Result<(), PgError> {
+        let (columns, formats) = if kind == b'S' {
+            let prepared = self.prepared(name)?;
+            let statement = prepared.statement.clone();
+            let param_oids: Vec<u32> = prepared.param_types.iter().map(|t| type_info(t).0).collect();
+            let columns = self.columns_of(statement.as_ref())?;
+            self.send(BackendMessage::ParameterDescription(&param_oids));
+            (columns, Vec::new())
+        } else {
+            let portal = self.portals.get(name).ok_or_else(|| {
+                PgError::new(sqlstate::INVALID_CURSOR_NAME, format!("portal \"{}\" does not exist", name))
+            })?;
+            (portal.columns.clone(), portal.result_formats.clone())
+        };
+        match columns {
+            Some(columns) => self.send(BackendMessage::RowDescription {
+                columns: &columns,
+                formats: &formats,
+            }),
+            None => self.send(BackendMessage::NoData),
+        }
+        Ok(())
+    }
+
+    /// 执行门户，最多发送 `max_rows` 行（0 表示不限）。没发完时发送 PortalSuspended 并保留门户
+    async fn execute_portal(&mut self, name: &str, max_rows: usize) -> io::Result<Result<(), PgError>> {
+        if let Some(portal) = self.portals.get(name) {
+            self.session.start_query(&portal.query);
+        }
+        let res = self.run_portal(name, max_rows).await;
+        self.session.finish_query();
+        res
+    }
+
+    async fn run_portal(&mut self, name: &str, max_rows: usize) -> io::Result<Result<(), PgError>> {
+        let Some(portal) = self.portals.get_mut(name) else {
+            return Ok(Err(PgError::new(
+                sqlstate::INVALID_CURSOR_NAME,
+                format!("portal \"{}\" does not exist", name),
+            )));
+        };
+        let formats = portal.result_formats.clone();
+        let (statement, mut cursor) = match std::mem::replace(&mut portal.state, PortalState::Empty) {
+            PortalState::Empty => {
+                self.send(BackendMessage::EmptyQueryResponse);
+                return Ok(Ok(()));
+            }
+            PortalState::Done(tag) => {
+                BackendMessage::CommandComplete(&tag).encode(&mut self.out);
+                portal.state = PortalState::Done(tag);
+                return Ok(Ok(()));
+            }
+            PortalState::Suspended { statement, cursor } => {
+                // Until this run finishes, an error leaves the portal spent.
+                portal.state = PortalState::Done(command_tag(&statement, 0));
+                (statement, cursor)
+            }
+            PortalState::Ready(statement) => {
+                portal.state = PortalState::Done(command_tag(&statement, 0));
+                match self.db.execute_statement(&mut self.session, statement.clone()).await {
+                    Ok(ExecutionResult::Data { rows, .. }) => (statement, RowCursor::new(rows)),
+                    Ok(result) => return self.send_result(&statement, result, &formats, false).await,
+                    Err(e) => return Ok(Err(PgError::from_execution(e))),
+                }
+            }
+        };
+
+        // Like PostgreSQL, the command tag counts the rows of this Execute only.
+        cursor.sent = 0;
+        match self.send_rows(&mut cursor, &formats, max_rows).await? {
+            Ok(true) => self.send(BackendMessage::CommandComplete(&command_tag(&statement, cursor.sent))),
+            Ok(false) => {
+                self.send(BackendMessage::PortalSuspended);
+                if let Some(portal) = self.portals.get_mut(name) {
+                    portal.state = PortalState::Suspended { statement, cursor };
+                }
+            }
+            Err(e) => return Ok(Err(e)),
+        }
+        Ok(Ok(()))
+    }
+
+    /// 发送一条语句的结果。简单查询在数据行之前先发送行描述，扩展查询由 Describe 发送。
+    async fn send_result(
+        &mut self,
+        statement: &Statement,
+        result: ExecutionResult,
+        formats: &[i16],
+        with_description: bool,
+    ) -> io::Result<Result<(), PgError>> {
+        match result {
+            ExecutionResult::Data { columns, rows } => {
+                if with_description {
+                    self.send(BackendMessage::RowDescription {
+                        columns: &columns,
+                        formats,
+                    });
+                }
+                let mut cursor = RowCursor::new(rows);
+                if let Err(e) = self.send_rows(&mut cursor, formats, 0).await? {
+                    return Ok(Err(e));
+                }
+                self.send(BackendMessage::CommandComplete(&command_tag(statement, cursor.sent)));
+            }
+            ExecutionResult::Message(message) => {
+                // Maintenance commands report what they did; pass that on as a notice.
+                if matches!(statement, Statement::Vacuum { .. } | Statement::VerifyDatabase) {
+                    self.send(BackendMessage::NoticeResponse { message: &message });
+                }
+                self.send(BackendMessage::CommandComplete(&command_tag(statement, 0)));
+            }
+            ExecutionResult::Modified { rows, .. } => {
+                self.send(BackendMessage::CommandComplete(&command_tag(statement, rows)));
+            }
+        }
+        Ok(Ok(()))
+    }
+
+    /// 从 `cursor` 中取行发送，最多 `limit` 行（0 表示不限），返回是否已经发完。
+    /// 输出缓冲区满了就先写给客户端，客户端读得慢时扫描也随之放慢
+    async fn send_rows(
+        &mut self,
+        cursor: &mut RowCursor,
+        formats: &[i16],
+        limit: usize,
+    ) -> io::Result<Result<bool, PgError>> {
+        while limit == 0 || cursor.sent < limit {
+            let Some(row) = cursor.pending.next() else {
+                match cursor.rows.next_batch().await {
+                    Ok(Some(batch)) => cursor.pending = batch.into_iter(),
+                    Ok(None) => return Ok(Ok(true)),
+                    Err(e) => return Ok(Err(PgError::from_execution(e))),
+                }
+                continue;
+            };
+            self.send(BackendMessage::DataRow {
+                values: row.values(),
+                formats,
+            });
+            cursor.sent += 1;
+            if self.out.len() >= FLUSH_THRESHOLD {
+                self.flush().await?;
+            }
+        }
+        // Like PostgreSQL, a portal that stops exactly at the limit reports itself as
+        // suspended; the next Execute finds it empty and completes.
+        Ok(Ok(false))
+    }
+}
+
+/// CommandComplete 中的命令标签，`rows` 为返回或修改的行数
+fn command_tag(statement: &Statement, rows: usize) -> String {
+    match statement {
+        Statement::CreateTable { .. } => "CREATE TABLE".to_string(),
+        Statement::CreateUser { .. } => "CREATE ROLE".to_string(),
+        Statement::Insert { .. } => "INSERT 0 1".to_string(),
+        Statement::Select { .. } => format!("SELECT {}", rows),
+        Statement::Delete { .. } => format!("DELETE {}", rows),
+        Statement::VerifyDatabase => "VERIFY".to_string(),
+        Statement::Set { .. } => "SET".to_string(),
+        Statement::Show { .. } | Statement::ShowSessions => "SHOW".to_string(),
+        Statement::Vacuum { .. } => "VACUUM".to_string(),
+        Statement::Prepare { .. } => "PREPARE".to_string(),
+        // Callers replace EXECUTE with the statement it runs before asking for the tag.
+        Statement::Execute { .. } => "EXECUTE".to_string(),
+        Statement::Deallocate { name: None } => "DEALLOCATE ALL".to_string(),
+        Statement::Deallocate { .. } => "DEALLOCATE".to_string(),
+    }
+}
@@ -0,0 +1,415 @@
+//! 客户端会话，以及供 `SHOW SESSIONS` 查看的会话登记表
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicI32, Ordering},
+    },
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::{
+    sql::{Statement, ast::DataType, redact_passwords},
+    storage::wal::{SynchronousCommit, WalConfig},
+};
+
+/// 可以用 `SET` 修改、用 `SHOW` 查看的会话参数
+pub const PARAMETERS: &[&str] = &["synchronous_commit", "commit_delay", "application_name", "statement_timeout"];
+
+/// 会话是否正在执行语句
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionState {
+    Idle,
+    Active,
+}
+
+impl SessionState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SessionState::Idle => "idle",
+            SessionState::Active => "active",
+        }
+    }
+}
+
+/// 其他会话也能看到的会话信息
+#[derive(Debug, Clone)]
+pub struct SessionInfo {
+    pub id: i32,
+    pub user: String,
+    pub application_name: String,
+    /// 客户端地址，交互式终端为 `None`
+    pub client_addr: Option<String>,
+    pub backend_start: SystemTime,
+    pub state: SessionState,
+    /// 正在执行的语句，空闲时为最后执行的语句
+    pub query: String,
+    pub prepared_statements: usize,
+}
+
+/// 正在执行的语句的取消请求和超时时间。执行器在处理每页数据之前检查，
+/// 取消请求可以来自其他 worker 上的连接
+#[derive(Debug, Default)]
+pub struct CancelToken {
+    canceled: AtomicBool,
+    deadline: Mutex<Option<Instant>>,
+}
+
+impl CancelToken {
+    /// 开始一条新语句，之前的取消请求作废
+    fn arm(&self, timeout: Duration) {
+        self.canceled.store(false, Ordering::Release);
+        *self.deadline.lock().unwrap() = (!timeout.is_zero()).then(|| Instant::now() + timeout);
+    }
+
+    fn disarm(&self) {
+        *self.deadline.lock().unwrap() = None;
+    }
+
+    /// 请求取消当前语句
+    pub fn cancel(&self) {
+        self.canceled.store(true, Ordering::Release);
+    }
+
+    /// 语句被取消或超时后返回错误
+    pub fn check(&self) -> Result<(), String> {
+        if self.canceled.load(Ordering::Acquire) {
+            return Err("canceling statement due to user request".to_string());
+        }
+        match *self.deadline.lock().unwrap() {
+            Some(deadline) if Instant::now() >= deadline => {
+                Err("canceling statement due to statement timeout".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Entry {
+    info: SessionInfo,
+    /// 取消请求必须带上这个密钥，防止其他客户端猜到会话号就能取消
+    secret_key: i32,
+    cancel: Arc<CancelToken>,
+}
+
+/// 数据库中所有存活的会话，各个 worker 共享
+#[derive(Debug, Default)]
+pub struct SessionRegistry {
+    next_id: AtomicI32,
+    sessions: Mutex<BTreeMap<i32, Entry>>,
+}
+
+impl SessionRegistry {
+    fn register(&self, secret_key: i32, cancel: Arc<CancelToken>) -> i32 {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let info = SessionInfo {
+            id,
+            user: String::new(),
+            application_name: String::new(),
+            client_addr: None,
+            backend_start: SystemTime::now(),
+            state: SessionState::Idle,
+            query: String::new(),
+            prepared_statements: 0,
+        };
+        let entry = Entry {
+            info,
+            secret_key,
+            cancel,
+        };
+        self.sessions.lock().unwrap().insert(id, entry);
+        id
+    }
+
+    fn update(&self, id: i32, f: impl FnOnce(&mut SessionInfo)) {
+        if let Some(entry) = self.sessions.lock().unwrap().get_mut(&id) {
+            f(&mut entry.info);
+        }
+    }
+
+    /// 按会话号排序的所有会话
+    pub fn list(&self) -> Vec<SessionInfo> {
+        self.sessions
+            .lock()
+            .unwrap()
+            .values()
+            .map(|entry| entry.info.clone())
+            .collect()
+    }
+
+    pub fn get(&self, id: i32) -> Option<SessionInfo> {
+        self.sessions.lock().unwrap().get(&id).map(|entry| entry.info.clone())
+    }
+
+    /// 取消会话 `id` 正在执行的语句，返回会话是否存在且密钥正确。
+    /// 会话空闲时请求被忽略，不影响下一条语句
+    pub fn cancel(&self, id: i32, secret_key: i32) -> bool {
+        match self.sessions.lock().unwrap().get(&id) {
+            Some(entry) if entry.secret_key == secret_key => {
+                if entry.info.state == SessionState::Active {
+                    entry.cancel.cancel();
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// 解析好的预备语句。SQL 的 PREPARE 和协议的 Parse 创建的预备语句在同一个名字空间里，
+/// 执行时只需要绑定参数，不必再次解析
+#[derive(Debug, Clone)]
+pub struct PreparedStatement {
+    /// 空查询为 `None`
+    pub statement: Option<Statement>,
+    /// 原始的查询文本，显示在 `SHOW SESSIONS` 中
+    pub query: String,
+    /// 每个参数的类型，`param_types[0]` 对应 `$1`
+    pub param_types: Vec<DataType>,
+}
+
+/// 一个客户端连接（或交互式终端）的状态，可以通过 `SET` 语句修改。
+/// 创建时登记到 [`SessionRegistry`]，drop 时注销。
+#[derive(Debug)]
+pub struct Session {
+    id: i32,
+    registry: Arc<SessionRegistry>,
+    /// 本会话中修改数据的语句提交时等待日志落盘的级别
+    pub synchronous_commit: SynchronousCommit,
+    /// 组提交时本会话的提交愿意多等的时间，`SET commit_delay` 的单位是微秒
+    pub commit_delay: Duration,
+    /// 语句执行超过这个时间就被取消，0 表示不限。`SET statement_timeout` 的单位是毫秒
+    pub statement_timeout: Duration,
+    secret_key: i32,
+    cancel: Arc<CancelToken>,
+    /// 登录的用户。交互式终端和嵌入使用的会话没有登录，为 `None`
+    user: Option<String>,
+    /// 按名字保存的预备语句，名字为空的是协议中的未命名语句
+    prepared: HashMap<String, PreparedStatement>,
+}
+
+impl Session {
+    pub fn new(config: &WalConfig, registry: Arc<SessionRegistry>) -> Self {
+        // The key is all that authorizes a CancelRequest, so it must not be guessable.
+        let mut secret_key = [0u8; 4];
+        getrandom::fill(&mut secret_key).expect("the operating system random number generator failed");
+        let secret_key = i32::from_le_bytes(secret_key);
+        let cancel = Arc::new(CancelToken::default());
+        Self {
+            id: registry.register(secret_key, cancel.clone()),
+            registry,
+            synchronous_commit: config.synchronous_commit,
+            commit_delay: config.commit_delay,
+            statement_timeout: Duration::ZERO,
+            secret_key,
+            cancel,
+            user: None,
+            prepared: HashMap::new(),
+        }
+    }
+
+    /// 会话号，在数据库中唯一，同时用作协议中的进程号
+    pub fn id(&self) -> i32 {
+        self.id
+    }
+
+    pub fn registry(&self) -> &Arc<SessionRegistry> {
+        &self.registry
+    }
+
+    /// 取消请求需要的密钥，在协议的 BackendKeyData 中发给客户端
+    pub fn secret_key(&self) -> i32 {
+        self.secret_key
+    }
+
+    /// 当前语句的取消标志，交给执行器检查
+    pub fn cancel_token(&self) -> &Arc<CancelToken> {
+        &self.cancel
+    }
+
+    /// 登录的用户，没有登录的会话为 `None`
+    pub fn user(&self) -> Option<&str> {
+        self.user.as_deref()
+    }
+
+    /// 记录连接的用户、应用名和客户端地址
+    pub fn set_client(&mut self, user: &str, application_name: &str, client_addr: Option<String>) {
+        self.user = Some(user.to_string());
+        self.registry.update(self.id, |info| {
+            info.user = user.to_string();
+            info.application_name = application_name.to_string();
+            info.client_addr = client_addr;
+        });
+    }
+
+    /// 开始执行 `query`，从现在开始计算 `statement_timeout`。
+    /// 其他会话能看到查询文本，所以记录之前先隐去其中的口令
+    pub fn start_query(&self, query: &str) {
+        self.cancel.arm(self.statement_timeout);
+        self.registry.update(self.id, |info| {
+            info.state = SessionState::Active;
+            info.query = redact_passwords(query).into_owned();
+        });
+    }
+
+    pub fn finish_query(&self) {
+        self.cancel.disarm();
+        self.registry.update(self.id, |info| info.state = SessionState::Idle);
+    }
+
+    /// 保存预备语句。未命名语句总是替换之前的，命名语句不能重名
+    pub fn prepare(&mut self, name: String, prepared: PreparedStatement) -> Result<(), String> {
+        if !name.is_empty() && self.prepared.contains_key(&name) {
+            return Err(format!("prepared statement \"{}\" already exists", name));
+        }
+        self.prepared.insert(name, prepared);
+        self.prepared_changed();
+        Ok(())
+    }
+
+    pub fn prepared(&self, name: &str) -> Result<&PreparedStatement, String> {
+        self.prepared
+            .get(name)
+            .ok_or_else(|| format!("prepared statement \"{}\" does not exist", name))
+    }
+
+    /// 释放一个预备语句，`None` 表示释放全部
+    pub fn deallocate(&mut self, name: Option<&str>) -> Result<(), String> {
+        match name {
+            Some(name) => {
+                self.prepared(name)?;
+                self.prepared.remove(name);
+            }
+            None => self.prepared.clear(),
+        }
+        self.prepared_changed();
+        Ok(())
+    }
+
+    /// 把 `EXECUTE` 换成绑定了参数的预备语句，其他语句原样返回
+    pub fn resolve(&self, statement: Statement) -> Result<Statement, String> {
+        let Statement::Execute { name, params } = statement else {
+            return Ok(statement);
+        };
+        let prepared = self.prepared(&name)?;
+        if params.len() != prepared.param_types.len() {
+            return Err(format!(
+                "wrong number of parameters for prepared statement \"{}\": expected {}, got {}",
+                name,
+                prepared.param_types.len(),
+                params.len()
+            ));
+        }
+        match &prepared.statement {
+            Some(statement) => statement.bind(&params),
+            None => Err(format!("prepared statement \"{}\" is empty", name)),
+        }
+    }
+
+    /// 命名预备语句的数量变化后更新会话信息
+    fn prepared_changed(&self) {
+        let named = self.prepared.keys().filter(|name| !name.is_empty()).count();
+        self.registry.update(self.id, |info| info.prepared_statements = named);
+    }
+
+    /// 修改一个会话参数，参数名不区分大小写
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), String> {
+        match name.to_lowercase().as_str() {
+            "synchronous_commit" => self.synchronous_commit = value.parse()?,
+            "commit_delay" => {
+                let micros = value
+                    .parse()
+                    .map_err(|_| format!("invalid value for commit_delay: '{}'", value))?;
+                self.commit_delay = Duration::from_micros(micros);
+            }
+            "application_name" => self
+                .registry
+                .update(self.id, |info| info.application_name = value.to_string()),
+            "statement_timeout" => {
+                let millis = value
+                    .parse()
+                    .map_err(|_| format!("invalid value for statement_timeout: '{}'", value))?;
+                self.statement_timeout = Duration::from_millis(millis);
+            }
+            _ => return Err(format!("unrecognized configuration parameter '{}'", name)),
+        }
+        Ok(())
+    }
+
+    /// 一个会话参数的当前值
+    pub fn show(&self, name: &str) -> Result<String, String> {
+        match name.to_lowercase().as_str() {
+            "synchronous_commit" => Ok(self.synchronous_commit.to_string()),
+            "commit_delay" => Ok(self.commit_delay.as_micros().to_string()),
+            "application_name" => Ok(self
+                .registry
+                .get(self.id)
+                .map(|info| info.application_name)
+                .unwrap_or_default()),
+            "statement_timeout" => Ok(self.statement_timeout.as_millis().to_string()),
+            _ => Err(format!("unrecognized configuration parameter '{}'", name)),
+        }
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        // Every statement commits on its own and there are no temporary tables yet, so
+        // nothing is left to roll back or drop (see the roadmap in the README).
+        self.registry.sessions.lock().unwrap().remove(&self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sessions_register_and_unregister() {
+        let registry = Arc::new(SessionRegistry::default());
+        let mut first = Session::new(&WalConfig::default(), registry.clone());
+        let second = Session::new(&WalConfig::default(), registry.clone());
+        assert_ne!(first.id(), second.id());
+
+        first.set_client("alice", "psql", Some("127.0.0.1:5000".to_string()));
+        first.set("Application_Name", "report").unwrap();
+        first.start_query("SELECT id FROM t");
+        let sessions = registry.list();
+        assert_eq!(sessions.len(), 2);
+        assert_eq!(sessions[0].user, "alice");
+        assert_eq!(sessions[0].state, SessionState::Active);
+        assert_eq!(first.show("application_name").unwrap(), "report");
+        assert_eq!(first.show("synchronous_commit").unwrap(), "full");
+        assert!(first.show("work_mem").is_err());
+
+        drop(first);
+        let sessions = registry.list();
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].id, second.id());
+    }
+
+    #[test]
+    fn test_cancel_and_statement_timeout() {
+        let registry = Arc::new(SessionRegistry::default());
+        let mut session = Session::new(&WalConfig::default(), registry.clone());
+        let token = session.cancel_token().clone();
+
+        // Requests for an idle session or with the wrong key are ignored.
+        assert!(registry.cancel(session.id(), session.secret_key()));
+        assert!(!registry.cancel(session.id(), session.secret_key().wrapping_add(1)));
+        session.start_query("SELECT id FROM t");
+        assert!(token.check().is_ok());
+        assert!(registry.cancel(session.id(), session.secret_key()));
+        assert!(token.check().unwrap_err().contains("user request"));
+        session.finish_query();
+
+        session.set("statement_timeout", "1").unwrap();
+        assert_eq!(session.show("statement_timeout").unwrap(), "1");
+        session.start_query("SELECT id FROM t");
+        std::thread::sleep(Duration::from_millis(5));
+        assert!(token.check().unwrap_err().contains("statement timeout"));
+        session.finish_query();
+        assert!(token.check().is_ok());
+    }
+}
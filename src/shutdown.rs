@@ -0,0 +1,106 @@
+//! 服务端的关闭流程：收到关闭请求后停止 accept，等正在执行的语句结束，
+//! 再通知空闲的客户端断开。各个 worker 的 runtime 都在等待同一个 [`Shutdown`]。
+use std::{
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use event_listener::Event;
+
+/// 关闭请求和仍在服务的连接数，可以跨线程共享
+#[derive(Default)]
+pub struct Shutdown {
+    requested: AtomicBool,
+    requested_event: Event,
+    active_connections: AtomicUsize,
+    drained_event: Event,
+}
+
+/// 连接存活期间持有，drop 时从活动连接数中减去
+pub struct ConnectionGuard<'a> {
+    shutdown: &'a Shutdown,
+}
+
+impl Drop for ConnectionGuard<'_> {
+    fn drop(&mut self) {
+        if self.shutdown.active_connections.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.shutdown.drained_event.notify(usize::MAX);
+        }
+    }
+}
+
+impl Shutdown {
+    /// 请求关闭，返回之前是否已经请求过
+    pub fn request(&self) -> bool {
+        let already = self.requested.swap(true, Ordering::AcqRel);
+        self.requested_event.notify(usize::MAX);
+        already
+    }
+
+    pub fn is_requested(&self) -> bool {
+        self.requested.load(Ordering::Acquire)
+    }
+
+    /// 等待关闭请求
+    pub async fn requested(&self) {
+        wait_until(&self.requested_event, || self.is_requested()).await
+    }
+
+    /// 登记一个连接
+    pub fn track_connection(&self) -> ConnectionGuard<'_> {
+        self.active_connections.fetch_add(1, Ordering::AcqRel);
+        ConnectionGuard { shutdown: self }
+    }
+
+    pub fn active_connections(&self) -> usize {
+        self.active_connections.load(Ordering::Acquire)
+    }
+
+    /// 等待所有连接关闭，最多等 `timeout`。返回时仍未关闭的连接数
+    pub async fn drain(&self, timeout: Duration) -> usize {
+        let drained = wait_until(&self.drained_event, || self.active_connections() == 0);
+        let _ = monoio::time::timeout(timeout, drained).await;
+        self.active_connections()
+    }
+}
+
+/// 等到 `done` 成立。先注册监听再检查条件，避免错过检查之后的通知
+async fn wait_until(event: &Event, done: impl Fn() -> bool) {
+    loop {
+        if done() {
+            return;
+        }
+        let listener = event.listen();
+        if done() {
+            return;
+        }
+        listener.await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    #[monoio::test(timer_enabled = true)]
+    async fn test_request_and_drain_across_threads() {
+        let shutdown = Arc::new(Shutdown::default());
+        let guard = shutdown.track_connection();
+        assert_eq!(shutdown.drain(Duration::from_millis(10)).await, 1);
+
+        let remote = shutdown.clone();
+        let thread = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            assert!(!remote.request());
+        });
+        shutdown.requested().await;
+        assert!(shutdown.is_requested());
+        thread.join().unwrap();
+
+        drop(guard);
+        assert_eq!(shutdown.drain(Duration::from_secs(5)).await, 0);
+        assert!(shutdown.request());
+    }
+}
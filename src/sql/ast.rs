@@ -1,22 +1,43 @@
 use bincode::{Decode, Encode};
 
-#[derive(Debug, Clone, Encode, Decode)]
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
 pub enum DataType {
     Int,
     Varchar,
+    Bytea,
 }
-#[derive(Debug, Clone, Encode, Decode)]
+#[derive(Debug, Clone)]
 pub enum Value {
     Integer(i64),
     String(String),
+    Bytes(Vec<u8>),
+    Null,
 }
+/// INSERT 或 WHERE 中的一个值：字面量或参数占位符
 #[derive(Debug, Clone)]
+pub enum Expr {
+    Value(Value),
+    /// `$n` 或 `?`，从 1 开始编号
+    Param(usize),
+}
+
+impl Expr {
+    /// 绑定参数之后的值，还是占位符时报错
+    pub fn into_value(self) -> Result<Value, String> {
+        match self {
+            Expr::Value(value) => Ok(value),
+            Expr::Param(n) => Err(format!("there is no parameter ${}", n)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Encode, Decode)]
 pub struct Column {
     pub name: String,
     pub data_type: DataType,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Statement {
     CreateTable {
         table_name: String,
@@ -24,10 +45,97 @@ pub enum Statement {
     },
     Insert {
         table_name: String,
-        values: Vec<Value>,
+        values: Vec<Expr>,
     },
     Select {
         table_name: String,
         columns: Vec<String>,
     },
+    /// 删除行：DELETE FROM table [WHERE column = value]，没有条件时删除所有行
+    Delete {
+        table_name: String,
+        filter: Option<(String, Expr)>,
+    },
+    VerifyDatabase,
+    /// 修改当前会话的设置：SET name = value
+    Set {
+        name: String,
+        value: String,
+    },
+    /// 查看当前会话的设置：SHOW name
+    Show {
+        name: String,
+    },
+    /// 列出所有会话：SHOW SESSIONS
+    ShowSessions,
+    /// 回收表中已删除元组占用的空间：VACUUM [table]，不指定表时清理所有表
+    Vacuum {
+        table_name: Option<String>,
+    },
+    /// 创建可以登录的用户：CREATE USER name [WITH] PASSWORD 'password'
+    CreateUser {
+        name: String,
+        password: String,
+    },
+    /// 创建预备语句：PREPARE name [(type, ...)] AS statement
+    Prepare {
+        name: String,
+        /// 声明的参数类型，没有声明的参数按使用它的位置推断
+        param_types: Vec<DataType>,
+        statement: Box<Statement>,
+    },
+    /// 执行预备语句：EXECUTE name [(value, ...)]
+    Execute {
+        name: String,
+        params: Vec<Value>,
+    },
+    /// 释放预备语句：DEALLOCATE [PREPARE] name | ALL，`None` 表示全部
+    Deallocate {
+        name: Option<String>,
+    },
+}
+
+impl Statement {
+    /// 语句中最大的参数编号，没有参数时为 0
+    pub fn param_count(&self) -> usize {
+        let exprs: Vec<&Expr> = match self {
+            Statement::Insert { values, .. } => values.iter().collect(),
+            Statement::Delete { filter, .. } => filter.iter().map(|(_, value)| value).collect(),
+            _ => Vec::new(),
+        };
+        exprs
+            .into_iter()
+            .filter_map(|value| match value {
+                Expr::Param(n) => Some(*n),
+                Expr::Value(_) => None,
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// 用 `params` 替换参数占位符，`params[0]` 对应 `$1`
+    pub fn bind(&self, params: &[Value]) -> Result<Statement, String> {
+        let bind = |value: &Expr| match value {
+            Expr::Param(n) => params
+                .get(n - 1)
+                .cloned()
+                .map(Expr::Value)
+                .ok_or_else(|| format!("there is no parameter ${}", n)),
+            value => Ok(value.clone()),
+        };
+        match self {
+            Statement::Insert { table_name, values } => Ok(Statement::Insert {
+                table_name: table_name.clone(),
+                values: values.iter().map(bind).collect::<Result<_, _>>()?,
+            }),
+            Statement::Delete { table_name, filter } => Ok(Statement::Delete {
+                table_name: table_name.clone(),
+                filter: match filter {
+                    Some((column, value)) => Some((column.clone(), bind(value)?)),
+                    None => None,
+                },
+            }),
+            statement => Ok(statement.clone()),
+        }
+    }
 }
@@ -1,6 +1,9 @@
 use std::{iter::Peekable, str::Chars};
 
-use crate::sql::token::{LexerError, Token};
+use crate::sql::{
+    parser::MAX_PARAMS,
+    token::{LexerError, Token},
+};
 
 pub struct Lexer<'a> {
     chars: Peekable<Chars<'a>>,
@@ -28,7 +31,7 @@ impl<'a> Lexer<'a> {
         ident
     }
 
-    pub fn read_number(&mut self, first: char) -> i64 {
+    fn read_digits(&mut self, first: char) -> String {
         let mut number = String::new();
         number.push(first);
 
@@ -40,13 +43,32 @@ impl<'a> Lexer<'a> {
                 break;
             }
         }
-        number.parse().unwrap_or(0)
+        number
     }
 
+    pub fn read_number(&mut self, first: char) -> Result<i64, LexerError> {
+        let digits = self.read_digits(first);
+        digits.parse().map_err(|_| LexerError::IntegerOutOfRange(digits))
+    }
+
+    /// 读取 `$n` 的编号（`$` 已被消费）
+    fn read_param(&mut self, first: char) -> Result<usize, LexerError> {
+        let digits = self.read_digits(first);
+        match digits.parse() {
+            Ok(n) if (1..=MAX_PARAMS).contains(&n) => Ok(n),
+            _ => Err(LexerError::ParamOutOfRange(digits)),
+        }
+    }
+
+    /// 读取字符串字面量（开头的引号已被消费），连续两个引号表示一个引号
     fn read_string(&mut self) -> Result<String, LexerError> {
         let mut s = String::new();
         loop {
             match self.chars.next() {
+                Some('\'') if self.chars.peek() == Some(&'\'') => {
+                    self.chars.next();
+                    s.push('\'');
+                }
                 Some('\'') => return Ok(s),
                 Some(c) => s.push(c),
                 None => return Err(LexerError::UnterminatedString),
@@ -54,6 +76,23 @@ impl<'a> Lexer<'a> {
         }
     }
 
+    /// 读取形如 X'DEADBEEF' 的十六进制字节串（开头的 X 和引号已被消费）
+    fn read_hex_string(&mut self) -> Result<Vec<u8>, LexerError> {
+        let digits = self.read_string()?;
+        if digits.len() % 2 != 0 {
+            return Err(LexerError::InvalidHexString);
+        }
+        (0..digits.len())
+            .step_by(2)
+            .map(|i| {
+                digits
+                    .get(i..i + 2)
+                    .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+                    .ok_or(LexerError::InvalidHexString)
+            })
+            .collect()
+    }
+
     fn skip_whitespace(&mut self) {
         while let Some(&c) = self.chars.peek() {
             if c.is_whitespace() {
@@ -77,8 +116,21 @@ impl<'a> Iterator for Lexer<'a> {
                     '(' => Ok(Token::LParen),
                     ')' => Ok(Token::RParen),
                     ',' => Ok(Token::Comma),
+                    '=' => Ok(Token::Eq),
                     ';' => Ok(Token::Semicolon),
+                    '?' => Ok(Token::QuestionMark),
+                    '$' => match self.chars.peek() {
+                        Some(&first) if first.is_ascii_digit() => {
+                            self.chars.next();
+                            self.read_param(first).map(Token::Param)
+                        }
+                        _ => Err(LexerError::InvalidCharacter('$')),
+                    },
                     '\'' => self.read_string().map(Token::String),
+                    'x' | 'X' if self.chars.peek() == Some(&'\'') => {
+                        self.chars.next();
+                        self.read_hex_string().map(Token::Bytes)
+                    }
                     c if c.is_alphabetic() => {
                         let ident = self.read_identifier(c);
                         // 检查是否是关键字
@@ -95,7 +147,7 @@ impl<'a> Iterator for Lexer<'a> {
                             _ => Ok(Token::Ident(ident)),
                         }
                     }
-                    c if c.is_ascii_digit() => Ok(Token::Integer(self.read_number(c))),
+                    c if c.is_ascii_digit() => self.read_number(c).map(Token::Integer),
                     _ => Err(LexerError::InvalidCharacter(c)),
                 };
                 Some(token)
@@ -3,6 +3,8 @@ pub mod lexer;
 pub mod parser;
 pub mod token;
 
+use std::borrow::Cow;
+
 pub use ast::Statement;
 pub use parser::{Parser, ParserError};
 
@@ -13,9 +15,75 @@ pub fn parse_sql(sql: &str) -> Result<Statement, ParserError> {
     parser.parse()
 }
 
+/// 按语句之间的分号切分 SQL 文本，忽略字符串字面量中的分号并跳过空语句
+pub fn split_statements(sql: &str) -> Vec<&str> {
+    let mut statements = Vec::new();
+    let mut in_string = false;
+    let mut start = 0;
+    for (i, c) in sql.char_indices() {
+        match c {
+            // An escaped quote ('') toggles twice, which leaves the state unchanged.
+            '\'' => in_string = !in_string,
+            ';' if !in_string => {
+                statements.push(&sql[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    statements.push(&sql[start..]);
+    statements.retain(|s| !s.trim().is_empty());
+    statements
+}
+
+/// 把 `PASSWORD '...'` 中的口令换成 `********`，用于在 `SHOW SESSIONS` 中显示查询文本。
+/// 和 `split_statements` 一样只跟踪引号，不做完整的词法分析
+pub fn redact_passwords(sql: &str) -> Cow<'_, str> {
+    let bytes = sql.as_bytes();
+    let is_word = |b: u8| b.is_ascii_alphanumeric() || b == b'_';
+    let mut redacted = String::new();
+    let mut copied = 0;
+    let mut in_string = false;
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'\'' {
+            in_string = !in_string;
+        } else if !in_string
+            && (i == 0 || !is_word(bytes[i - 1]))
+            && sql.get(i..i + 8).is_some_and(|word| word.eq_ignore_ascii_case("password"))
+        {
+            let mut quote = i + 8;
+            while quote < bytes.len() && bytes[quote].is_ascii_whitespace() {
+                quote += 1;
+            }
+            if quote < bytes.len() && bytes[quote] == b'\'' {
+                // Keep both quotes; an unterminated literal is hidden to the end.
+                let mut end = quote + 1;
+                while end < bytes.len() && (bytes[end] != b'\'' || bytes.get(end + 1) == Some(&b'\'')) {
+                    end += if bytes[end] == b'\'' { 2 } else { 1 };
+                }
+                redacted.push_str(&sql[copied..=quote]);
+                redacted.push_str("********");
+                copied = end;
+                i = end + 1;
+                continue;
+            }
+        }
+        i += 1;
+    }
+    if copied == 0 {
+        return Cow::Borrowed(sql);
+    }
+    redacted.push_str(&sql[copied..]);
+    Cow::Owned(redacted)
+}
+
 #[cfg(test)]
 mod tests {
-    use super::parse_sql;
+    use super::{
+        ast::{Expr, Statement, Value},
+        parse_sql, redact_passwords, split_statements,
+    };
 
     #[test]
     fn test_parse_sql() {
@@ -25,6 +93,34 @@ mod tests {
             "INSERT INTO users VALUES (1, 'Alice');",
             "SELECT id, name FROM users;",
             "SELECT name FROM users", // Test without semicolon
+            "CREATE TABLE blobs (id INT, body BYTEA);",
+            "INSERT INTO blobs VALUES (1, X'DEADbeef');",
+            "INSERT INTO blobs VALUES (2, NULL);",
+            "VERIFY DATABASE;",
+            "SET synchronous_commit = off;",
+            "SET synchronous_commit TO 'full'",
+            "SHOW synchronous_commit",
+            "SHOW SESSIONS;",
+            "VACUUM;",
+            "VACUUM users",
+            "DELETE FROM users",
+            "delete from users where name = 'Alice';",
+            "INSERT INTO users VALUES ($1, $2);",
+            "INSERT INTO users VALUES (?, NULL)",
+            "PREPARE ins (INT, VARCHAR) AS INSERT INTO users VALUES ($1, $2);",
+            "PREPARE q AS SELECT id FROM users",
+            "PREPARE d AS DELETE FROM users WHERE id = ?",
+            "EXECUTE ins (1, 'Alice');",
+            "EXECUTE q",
+            "DEALLOCATE PREPARE ins",
+            "DEALLOCATE ALL;",
+            "CREATE USER alice WITH PASSWORD 'secret';",
+            "create user bob password 'hunter2'",
+            "CREATE TABLE accounts (user VARCHAR, password BYTEA)",
+            // Only the keywords of the original grammar are reserved.
+            "CREATE TABLE settings (set VARCHAR, show INT, all BYTEA, vacuum bytea)",
+            "SELECT set, show, all, null FROM settings",
+            "INSERT INTO settings VALUES (null, 1, NULL, null)",
         ];
 
         for sql in valid_statements {
@@ -33,11 +129,94 @@ mod tests {
         }
 
         // Test invalid SQL statements
-        let invalid_statements = vec!["CREATE users (id INT);", "SELECT id, name FROM;"];
+        let invalid_statements = vec![
+            "CREATE users (id INT);",
+            "SELECT id, name FROM;",
+            "INSERT INTO blobs VALUES (1, X'ABC');",
+            "SET synchronous_commit off;",
+            "SHOW;",
+            "VACUUM users blobs;",
+            "INSERT INTO users VALUES ($0, 'a');",
+            "INSERT INTO users VALUES ($1, ?);",
+            "INSERT INTO users VALUES ($, 'a');",
+            "PREPARE p AS VACUUM;",
+            "EXECUTE ins ($1);",
+            "DEALLOCATE;",
+            "CREATE USER alice;",
+            "CREATE USER alice WITH PASSWORD secret;",
+            "SELECT id FROM select",
+            "INSERT INTO users VALUES ($99999999999, 'a');",
+            "INSERT INTO users VALUES (99999999999999999999);",
+            "settings VALUES (1)",
+            "DELETE users",
+            "DELETE FROM users WHERE id",
+            "DELETE FROM users WHERE id = 1 AND name = 'a'",
+        ];
 
         for sql in invalid_statements {
             let result = parse_sql(sql);
             assert!(result.is_err(), "Expected error for invalid SQL: {}", sql);
         }
     }
+
+    #[test]
+    fn test_bind_params() {
+        let statement = parse_sql("INSERT INTO users VALUES (?, 'x', ?)").unwrap();
+        assert_eq!(statement.param_count(), 2);
+        let Statement::Insert { values, .. } = statement.bind(&[Value::Integer(7), Value::Null]).unwrap() else {
+            panic!("expected an INSERT");
+        };
+        assert!(matches!(
+            &values[..],
+            [Expr::Value(Value::Integer(7)), Expr::Value(Value::String(_)), Expr::Value(Value::Null)]
+        ));
+        assert!(parse_sql("INSERT INTO users VALUES ($2)").unwrap().bind(&[Value::Null]).is_err());
+        let statement = parse_sql("DELETE FROM users WHERE id = $1").unwrap();
+        assert_eq!(statement.param_count(), 1);
+        let Statement::Delete { filter: Some((column, Expr::Value(Value::Integer(3)))), .. } =
+            statement.bind(&[Value::Integer(3)]).unwrap()
+        else {
+            panic!("expected a bound DELETE");
+        };
+        assert_eq!(column, "id");
+    }
+
+    #[test]
+    fn test_literals_and_placeholders() {
+        let Statement::Insert { values, .. } = parse_sql("INSERT INTO t VALUES ('it''s', '''', $65535)").unwrap() else {
+            panic!("expected an INSERT");
+        };
+        assert!(matches!(
+            &values[..],
+            [Expr::Value(Value::String(a)), Expr::Value(Value::String(b)), Expr::Param(65535)] if a == "it's" && b == "'"
+        ));
+        let error = parse_sql("INSERT INTO t VALUES ($99999999999)").unwrap_err().to_string();
+        assert!(error.contains("parameter number $99999999999 is out of range"), "{}", error);
+        let error = parse_sql("INSERT INTO t VALUES (99999999999999999999)").unwrap_err().to_string();
+        assert!(error.contains("integer 99999999999999999999 is out of range"), "{}", error);
+        // The splitter agrees with the lexer on doubled quotes.
+        assert_eq!(split_statements("INSERT INTO t VALUES ('it'';s'); SELECT b FROM t").len(), 2);
+    }
+
+    #[test]
+    fn test_split_statements() {
+        assert_eq!(
+            split_statements("INSERT INTO t VALUES ('a;b'); SELECT id FROM t;;"),
+            vec!["INSERT INTO t VALUES ('a;b')", " SELECT id FROM t"]
+        );
+        assert!(split_statements(" ; ").is_empty());
+    }
+
+    #[test]
+    fn test_redact_passwords() {
+        assert_eq!(
+            redact_passwords("CREATE USER alice WITH PASSWORD 'wonderland'; create user bob password'x"),
+            "CREATE USER alice WITH PASSWORD '********'; create user bob password'********"
+        );
+        // A doubled quote is part of the password.
+        assert_eq!(redact_passwords("CREATE USER c PASSWORD 'it''s'"), "CREATE USER c PASSWORD '********'");
+        let untouched = "INSERT INTO accounts VALUES ('password ''x''', 1)";
+        assert!(matches!(redact_passwords(untouched), std::borrow::Cow::Borrowed(s) if s == untouched));
+        assert_eq!(redact_passwords("SELECT password FROM accounts"), "SELECT password FROM accounts");
+    }
 }
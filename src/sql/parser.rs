@@ -1,7 +1,7 @@
 use std::iter::Peekable;
 
 use crate::sql::{
-    ast::{Column, DataType, Statement, Value},
+    ast::{Column, DataType, Expr, Statement, Value},
     lexer::Lexer,
     token::Token,
 };
@@ -23,14 +23,23 @@ impl std::fmt::Display for ParserError {
     }
 }
 
+/// 参数编号的上限，与 Bind 消息中参数个数字段（int16）能表示的范围一致
+pub const MAX_PARAMS: usize = u16::MAX as usize;
+
 pub struct Parser<'a> {
     tokens: Peekable<Lexer<'a>>,
+    /// 已经出现的 `?` 个数，第 n 个 `?` 就是 `$n`
+    question_marks: usize,
+    /// 是否出现过 `$n`，两种占位符不能混用
+    numbered_params: bool,
 }
 
 impl<'a> Parser<'a> {
     pub fn new(input: &'a str) -> Self {
         Self {
             tokens: Lexer::new(input).peekable(),
+            question_marks: 0,
+            numbered_params: false,
         }
     }
 
@@ -53,12 +62,28 @@ impl<'a> Parser<'a> {
             Token::Create => self.parse_create(),
             Token::Select => self.parse_select(),
             Token::Insert => self.parse_insert(),
+            // The other statements start with words that are not reserved.
+            Token::Ident(word) => match word.to_lowercase().as_str() {
+                "delete" => self.parse_delete(),
+                "verify" => self.parse_verify(),
+                "set" => self.parse_set(),
+                "show" => self.parse_show(),
+                "vacuum" => self.parse_vacuum(),
+                "prepare" => self.parse_prepare(),
+                "execute" => self.parse_execute(),
+                "deallocate" => self.parse_deallocate(),
+                _ => Err(ParserError::UnexpectedToken(self.next_token()?)),
+            },
             t => Err(ParserError::UnexpectedToken(t.clone())),
         }
     }
 
     fn parse_create(&mut self) -> Result<Statement, ParserError> {
         self.expect_token(Token::Create)?;
+        // USER, WITH and PASSWORD are not reserved, so they remain usable as column names.
+        if self.consume_word("user") {
+            return self.parse_create_user();
+        }
         self.expect_token(Token::Table)?;
         let table_name = self.expect_identifier()?;
         self.expect_token(Token::LParen)?;
@@ -67,11 +92,7 @@ impl<'a> Parser<'a> {
         if !self.check_token(Token::RParen) {
             loop {
                 let col_name = self.expect_identifier()?;
-                let data_type = match self.next_token()? {
-                    Token::Int => DataType::Int,
-                    Token::Varchar => DataType::Varchar,
-                    t => return Err(ParserError::UnexpectedToken(t)),
-                };
+                let data_type = self.parse_data_type()?;
                 columns.push(Column {
                     name: col_name,
                     data_type,
@@ -88,6 +109,19 @@ impl<'a> Parser<'a> {
         })
     }
 
+    fn parse_create_user(&mut self) -> Result<Statement, ParserError> {
+        let name = self.expect_identifier()?;
+        self.consume_word("with");
+        if !self.consume_word("password") {
+            return Err(ParserError::UnexpectedToken(self.next_token()?));
+        }
+        let password = match self.next_token()? {
+            Token::String(password) => password,
+            t => return Err(ParserError::UnexpectedToken(t)),
+        };
+        Ok(Statement::CreateUser { name, password })
+    }
+
     fn parse_select(&mut self) -> Result<Statement, ParserError> {
         self.expect_token(Token::Select)?;
         let mut columns = Vec::new();
@@ -115,12 +149,7 @@ impl<'a> Parser<'a> {
         let mut values = Vec::new();
         if !self.check_token(Token::RParen) {
             loop {
-                let value = match self.next_token()? {
-                    Token::Integer(i) => Value::Integer(i),
-                    Token::String(s) => Value::String(s),
-                    t => return Err(ParserError::UnexpectedToken(t)),
-                };
-                values.push(value);
+                values.push(self.parse_expr()?);
                 if !self.consume_if(Token::Comma) {
                     break;
                 }
@@ -130,12 +159,165 @@ impl<'a> Parser<'a> {
         Ok(Statement::Insert { table_name, values })
     }
 
+    fn parse_delete(&mut self) -> Result<Statement, ParserError> {
+        self.expect_word("delete")?;
+        self.expect_token(Token::From)?;
+        let table_name = self.expect_identifier()?;
+        let filter = if self.consume_word("where") {
+            let column = self.expect_identifier()?;
+            self.expect_token(Token::Eq)?;
+            Some((column, self.parse_expr()?))
+        } else {
+            None
+        };
+        Ok(Statement::Delete { table_name, filter })
+    }
+
+    fn parse_verify(&mut self) -> Result<Statement, ParserError> {
+        self.expect_word("verify")?;
+        self.expect_word("database")?;
+        Ok(Statement::VerifyDatabase)
+    }
+
+    fn parse_set(&mut self) -> Result<Statement, ParserError> {
+        self.expect_word("set")?;
+        let name = self.expect_identifier()?;
+        if !self.consume_if(Token::Eq) {
+            self.expect_word("to")?;
+        }
+        let value = match self.next_token()? {
+            Token::Ident(s) | Token::String(s) => s,
+            Token::Integer(i) => i.to_string(),
+            t => return Err(ParserError::UnexpectedToken(t)),
+        };
+        Ok(Statement::Set { name, value })
+    }
+
+    fn parse_show(&mut self) -> Result<Statement, ParserError> {
+        self.expect_word("show")?;
+        let name = self.expect_identifier()?;
+        if name.eq_ignore_ascii_case("sessions") {
+            return Ok(Statement::ShowSessions);
+        }
+        Ok(Statement::Show { name })
+    }
+
+    fn parse_vacuum(&mut self) -> Result<Statement, ParserError> {
+        self.expect_word("vacuum")?;
+        let table_name = match self.peek_token()? {
+            Token::Ident(_) => Some(self.expect_identifier()?),
+            _ => None,
+        };
+        Ok(Statement::Vacuum { table_name })
+    }
+
+    fn parse_prepare(&mut self) -> Result<Statement, ParserError> {
+        self.expect_word("prepare")?;
+        let name = self.expect_identifier()?;
+        let mut param_types = Vec::new();
+        if self.consume_if(Token::LParen) {
+            loop {
+                param_types.push(self.parse_data_type()?);
+                if !self.consume_if(Token::Comma) {
+                    break;
+                }
+            }
+            self.expect_token(Token::RParen)?;
+        }
+        self.expect_word("as")?;
+        // Like PostgreSQL, only queries and data changes can be prepared.
+        let statement = match self.peek_token()? {
+            Token::Insert => self.parse_insert()?,
+            Token::Select => self.parse_select()?,
+            Token::Ident(word) if word.eq_ignore_ascii_case("delete") => self.parse_delete()?,
+            t => return Err(ParserError::UnexpectedToken(t.clone())),
+        };
+        Ok(Statement::Prepare {
+            name,
+            param_types,
+            statement: Box::new(statement),
+        })
+    }
+
+    fn parse_execute(&mut self) -> Result<Statement, ParserError> {
+        self.expect_word("execute")?;
+        let name = self.expect_identifier()?;
+        let mut params = Vec::new();
+        if self.consume_if(Token::LParen) {
+            loop {
+                params.push(self.parse_literal()?);
+                if !self.consume_if(Token::Comma) {
+                    break;
+                }
+            }
+            self.expect_token(Token::RParen)?;
+        }
+        Ok(Statement::Execute { name, params })
+    }
+
+    fn parse_deallocate(&mut self) -> Result<Statement, ParserError> {
+        self.expect_word("deallocate")?;
+        self.consume_word("prepare");
+        let name = match self.expect_identifier()? {
+            name if name.eq_ignore_ascii_case("all") => None,
+            name => Some(name),
+        };
+        Ok(Statement::Deallocate { name })
+    }
+
     // === Helper Functions ===
+    fn parse_data_type(&mut self) -> Result<DataType, ParserError> {
+        match self.next_token()? {
+            Token::Int => Ok(DataType::Int),
+            Token::Varchar => Ok(DataType::Varchar),
+            Token::Ident(name) if name.eq_ignore_ascii_case("bytea") => Ok(DataType::Bytea),
+            t => Err(ParserError::UnexpectedToken(t)),
+        }
+    }
+
+    fn parse_literal(&mut self) -> Result<Value, ParserError> {
+        match self.next_token()? {
+            Token::Integer(i) => Ok(Value::Integer(i)),
+            Token::String(s) => Ok(Value::String(s)),
+            Token::Bytes(b) => Ok(Value::Bytes(b)),
+            Token::Ident(word) if word.eq_ignore_ascii_case("null") => Ok(Value::Null),
+            t => Err(ParserError::UnexpectedToken(t)),
+        }
+    }
+
+    /// 读取一个字面量或占位符
+    fn parse_expr(&mut self) -> Result<Expr, ParserError> {
+        match self.peek_token()? {
+            Token::Param(_) | Token::QuestionMark => Ok(Expr::Param(self.parse_param()?)),
+            _ => Ok(Expr::Value(self.parse_literal()?)),
+        }
+    }
+
+    /// 读取一个占位符，返回参数编号
+    fn parse_param(&mut self) -> Result<usize, ParserError> {
+        let token = self.next_token()?;
+        let n = match token {
+            Token::Param(n) if self.question_marks == 0 => {
+                self.numbered_params = true;
+                n
+            }
+            Token::QuestionMark if !self.numbered_params => {
+                self.question_marks += 1;
+                self.question_marks
+            }
+            t => return Err(ParserError::UnexpectedToken(t)),
+        };
+        if n == 0 || n > MAX_PARAMS {
+            return Err(ParserError::UnexpectedToken(Token::Param(n)));
+        }
+        Ok(n)
+    }
+
     fn next_token(&mut self) -> Result<Token, ParserError> {
         self.tokens
             .next()
             .unwrap_or(Ok(Token::Eof))
-            .map_err(|e| ParserError::LexerError(format!("{:?}", e)))
+            .map_err(|e| ParserError::LexerError(e.to_string()))
     }
 
     fn peek_token(&mut self) -> Result<&Token, ParserError> {
@@ -143,7 +325,7 @@ impl<'a> Parser<'a> {
             .peek()
             .unwrap_or(&Ok(Token::Eof))
             .as_ref()
-            .map_err(|e| ParserError::LexerError(format!("{:?}", e)))
+            .map_err(|e| ParserError::LexerError(e.to_string()))
     }
 
     fn expect_token(&mut self, expected: Token) -> Result<(), ParserError> {
@@ -173,4 +355,20 @@ impl<'a> Parser<'a> {
             false
         }
     }
+
+    /// 跳过一个不是关键字的单词，不区分大小写
+    fn consume_word(&mut self, word: &str) -> bool {
+        match self.peek_token() {
+            Ok(Token::Ident(ident)) if ident.eq_ignore_ascii_case(word) => self.next_token().is_ok(),
+            _ => false,
+        }
+    }
+
+    /// 读取一个不是关键字的单词，不区分大小写
+    fn expect_word(&mut self, word: &str) -> Result<(), ParserError> {
+        match self.next_token()? {
+            Token::Ident(ident) if ident.eq_ignore_ascii_case(word) => Ok(()),
+            t => Err(ParserError::UnexpectedToken(t)),
+        }
+    }
 }
@@ -1,6 +1,7 @@
 #[derive(Debug, PartialEq, Clone)]
 pub enum Token {
-    // Keywords
+    // Keywords. Words added after these (SET, VACUUM, NULL, ...) are not reserved;
+    // the parser recognizes them as identifiers where they are expected.
     Create,
     Table,
     Insert,
@@ -17,11 +18,17 @@ pub enum Token {
     // Literals
     Integer(i64),
     String(String),
+    Bytes(Vec<u8>),
+
+    // Placeholders
+    Param(usize), // $n
+    QuestionMark, // ?
 
     // Symbols
     LParen,    // (
     RParen,    // )
     Comma,     // ,
+    Eq,        // =
     Semicolon, // ;
 
     // End of input
@@ -32,4 +39,26 @@ pub enum Token {
 pub enum LexerError {
     InvalidCharacter(char),
     UnterminatedString,
+    InvalidHexString,
+    /// 整数字面量超出 i64 的范围
+    IntegerOutOfRange(String),
+    /// `$n` 的编号为 0 或超出 `MAX_PARAMS`
+    ParamOutOfRange(String),
+}
+
+impl std::fmt::Display for LexerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LexerError::InvalidCharacter(c) => write!(f, "invalid character '{}'", c),
+            LexerError::UnterminatedString => write!(f, "unterminated quoted string"),
+            LexerError::InvalidHexString => write!(f, "invalid hexadecimal string"),
+            LexerError::IntegerOutOfRange(digits) => write!(f, "integer {} is out of range", digits),
+            LexerError::ParamOutOfRange(digits) => write!(
+                f,
+                "parameter number ${} is out of range (expected 1 to {})",
+                digits,
+                crate::sql::parser::MAX_PARAMS
+            ),
+        }
+    }
 }
@@ -0,0 +1,63 @@
+//! 缓冲区访问策略（参考 PostgreSQL 的 ring buffer）。
+//!
+//! 大表的顺序扫描和批量写入如果走普通的置换流程，会把共享缓冲池里的热点页
+//! 全部挤出去。使用 `BufferAccessStrategy` 时，缺页只会在一个私有的小环里
+//! 循环复用帧：环中下一个槽位的帧仍装着我们自己之前读入的页且没有被 pin，
+//! 就直接复用它，否则才向置换器要一个新的牺牲帧并记录到该槽位。
+use std::sync::Mutex;
+
+use crate::storage::page::PageId;
+
+/// 环的默认大小（32 页 = 256KB，与 PostgreSQL 的 BAS_BULKREAD 相同）
+pub const DEFAULT_RING_SIZE: usize = 32;
+
+/// 表的页数超过缓冲池大小的这一比例时才启用环形策略
+pub const RING_THRESHOLD_DIVISOR: usize = 4;
+
+pub struct BufferAccessStrategy {
+    ring: Mutex<Ring>,
+}
+
+struct Ring {
+    /// 每个槽位记录 (frame_id, 装入时的 page_id)
+    slots: Vec<Option<(usize, PageId)>>,
+    current: usize,
+}
+
+impl BufferAccessStrategy {
+    pub fn new(ring_size: usize) -> Self {
+        Self {
+            ring: Mutex::new(Ring {
+                slots: vec![None; ring_size.max(1)],
+                current: 0,
+            }),
+        }
+    }
+
+    /// 为一个有 `page_count` 页的表选择访问策略；小表直接使用共享缓冲池。
+    pub fn for_table(page_count: usize, pool_size: usize) -> Option<Self> {
+        if page_count <= pool_size / RING_THRESHOLD_DIVISOR {
+            return None;
+        }
+        let ring_size = DEFAULT_RING_SIZE.min(pool_size / RING_THRESHOLD_DIVISOR).max(1);
+        Some(Self::new(ring_size))
+    }
+
+    pub fn ring_size(&self) -> usize {
+        self.ring.lock().unwrap().slots.len()
+    }
+
+    /// 当前槽位中可以复用的帧（以及它应当装着的页）
+    pub(crate) fn current(&self) -> Option<(usize, PageId)> {
+        let ring = self.ring.lock().unwrap();
+        ring.slots[ring.current]
+    }
+
+    /// 记录当前槽位装入了哪个帧，并前进到下一个槽位
+    pub(crate) fn record(&self, frame_id: usize, page_id: PageId) {
+        let mut ring = self.ring.lock().unwrap();
+        let current = ring.current;
+        ring.slots[current] = Some((frame_id, page_id));
+        ring.current = (current + 1) % ring.slots.len();
+    }
+}
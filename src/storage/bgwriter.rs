@@ -0,0 +1,393 @@
+//! 后台写进程与检查点。
+//!
+//! 每个 runtime 运行一个后台写任务，周期性地写回替换策略即将淘汰的脏帧，
+//! 多个 runtime 共享同一个缓冲池时按帧号划分各自负责的帧。
+//! 其中一个 runtime 还负责模糊检查点：不阻塞并发的修改，只把开始时已经是脏页的
+//! 页按 I/O 预算分批写回，然后把检查点记录写入控制文件。
+//! 不论 `synchronous_commit` 是什么级别，检查点都先把日志刷到重放起点、fsync 数据文件，
+//! 再推进控制文件中的重放起点。
+//!
+//! 控制文件同时记录数据文件的页格式版本，打开数据库时由 `check_format_version` 检查。
+use std::{
+    collections::BTreeSet,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use bincode::{
+    Decode, Encode,
+    config::{self, Configuration, Fixint, LittleEndian, NoLimit},
+};
+
+use crate::storage::{
+    buffer_pool::BufferPoolManager,
+    disk::DiskManager,
+    page::{PAGE_FORMAT_VERSION, PAGE_SIZE, Page, PageId, PageType},
+    wal::Lsn,
+};
+
+const RECORD_CONFIG: Configuration<LittleEndian, Fixint, NoLimit> =
+    config::standard().with_fixed_int_encoding();
+
+/// 后台写进程与检查点的配置
+#[derive(Debug, Clone, Copy)]
+pub struct BgWriterConfig {
+    /// 后台写进程两轮之间的间隔，也是检查点两批写回之间的间隔
+    pub delay: Duration,
+    /// I/O 预算：每轮（或检查点的每一批）最多写回的页数
+    pub max_pages: usize,
+    /// 两次检查点之间的间隔
+    pub checkpoint_interval: Duration,
+}
+
+impl Default for BgWriterConfig {
+    fn default() -> Self {
+        Self {
+            delay: Duration::from_millis(200),
+            max_pages: 100,
+            checkpoint_interval: Duration::from_secs(300),
+        }
+    }
+}
+
+/// 控制文件中保存的最近一次检查点
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct CheckpointRecord {
+    /// 单调递增的检查点序号
+    pub sequence: u64,
+    /// 检查点完成时的 Unix 时间（秒）
+    pub timestamp: u64,
+    /// 检查点时数据文件中已分配的页数
+    pub num_pages: PageId,
+    /// 崩溃恢复从这个 LSN 开始重放日志
+    pub redo_lsn: Lsn,
+    /// 数据文件的页格式版本，0 表示控制文件写于引入版本号之前
+    pub format_version: u32,
+    /// 检查点完成时的空闲页表
+    pub free_pages: Vec<PageId>,
+}
+
+/// 没有 `format_version` 字段的旧检查点记录的长度
+const LEGACY_RECORD_LEN: usize = 28;
+/// 没有 `free_pages` 字段的旧检查点记录的长度
+const PRE_FREE_LIST_RECORD_LEN: usize = 32;
+
+impl CheckpointRecord {
+    /// 编码为 CRC32C + 定长记录
+    fn encode(&self) -> Vec<u8> {
+        let body = bincode::encode_to_vec(self, RECORD_CONFIG).unwrap();
+        let mut bytes = crc32c::crc32c(&body).to_le_bytes().to_vec();
+        bytes.extend_from_slice(&body);
+        bytes
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, String> {
+        if bytes.len() < 4 {
+            return Err("control file is truncated".to_string());
+        }
+        let (checksum, body) = bytes.split_at(4);
+        if u32::from_le_bytes(checksum.try_into().unwrap()) != crc32c::crc32c(body) {
+            return Err("control file checksum mismatch".to_string());
+        }
+        let mut body = body.to_vec();
+        if body.len() == LEGACY_RECORD_LEN {
+            // Written before the format version was recorded.
+            body.extend_from_slice(&0u32.to_le_bytes());
+        }
+        if body.len() == PRE_FREE_LIST_RECORD_LEN {
+            // Written before the free list was recorded: an empty list.
+            body.extend_from_slice(&0u64.to_le_bytes());
+        }
+        bincode::decode_from_slice(&body, RECORD_CONFIG)
+            .map(|(record, _)| record)
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// 读取最近一次检查点，从未做过检查点时返回 `None`
+pub async fn last_checkpoint(disk_manager: &DiskManager) -> Result<Option<CheckpointRecord>, String> {
+    match disk_manager.read_control_file().await.map_err(|e| e.to_string())? {
+        Some(bytes) => CheckpointRecord::decode(&bytes).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// 检查数据文件的页格式能否由当前版本读取，在重放日志之后调用。
+///
+/// 控制文件记录的是当前版本时直接通过；版本更新的数据文件拒绝打开。控制文件没有记录版本
+/// （新建的数据库，或写于引入版本号之前）时逐页校验：所有页都符合当前格式才接受，
+/// 返回 `false` 表示调用者应当立即做一次检查点把版本号写入控制文件。
+pub async fn check_format_version(
+    disk_manager: &DiskManager,
+    checkpoint: Option<&CheckpointRecord>,
+) -> Result<bool, String> {
+    match checkpoint.map_or(0, |record| record.format_version) {
+        PAGE_FORMAT_VERSION => return Ok(true),
+        0 => {}
+        version => {
+            return Err(format!(
+                "Data file uses page format version {}, but this server only supports version {}",
+                version, PAGE_FORMAT_VERSION
+            ));
+        }
+    }
+
+    let mut buffer = vec![0u8; PAGE_SIZE];
+    for page_id in 0..disk_manager.num_pages() {
+        let (res, buf) = disk_manager.read_page(page_id, buffer).await;
+        buffer = buf;
+        res.map_err(|e| format!("Failed to read page {}: {}", page_id, e))?;
+        if let Err(e) = Page::verify(&buffer) {
+            return Err(format!(
+                "Data file was written in an older page format and cannot be opened \
+                 (page {}: {}); expected page format version {}",
+                page_id, e, PAGE_FORMAT_VERSION
+            ));
+        }
+    }
+    Ok(false)
+}
+
+/// 启动时重建空闲页表，在重放日志之后、分配任何页之前调用，返回恢复的页数。
+///
+/// 候选页是检查点记录的空闲页表加上重放过的页。检查点之后又被分配出去的页已经不是
+/// 空闲页标记，所以只有磁盘上仍是空闲页标记的页才会回到空闲页表。
+pub async fn restore_free_pages(disk_manager: &DiskManager, candidates: BTreeSet<PageId>) -> Result<usize, String> {
+    let mut free_pages = Vec::new();
+    let mut buffer = vec![0u8; PAGE_SIZE];
+    // Highest first, so allocation pops the lowest page ids.
+    for page_id in candidates.into_iter().rev().filter(|&page_id| page_id < disk_manager.num_pages()) {
+        let (res, buf) = disk_manager.read_page(page_id, buffer).await;
+        buffer = buf;
+        res.map_err(|e| format!("Failed to read page {}: {}", page_id, e))?;
+        // A damaged page is never handed out again.
+        if Page::verify(&buffer).is_ok()
+            && Page::from_bytes(buffer[..PAGE_SIZE].try_into().unwrap())
+                .is_ok_and(|page| page.header.page_type == PageType::Free)
+        {
+            free_pages.push(page_id);
+        }
+    }
+    let restored = free_pages.len();
+    disk_manager.set_free_pages(free_pages);
+    Ok(restored)
+}
+
+/// 执行一次模糊检查点。开始之后才变脏的页留给下一次检查点。
+///
+/// 写回之后把日志刷到检查点开始时的日志末尾并 fsync 数据文件，然后把重放起点推进到这里，
+/// 并删除不再需要重放的日志段。
+pub async fn checkpoint(bpm: &BufferPoolManager, config: &BgWriterConfig) -> Result<CheckpointRecord, String> {
+    let disk_manager = bpm.disk_manager();
+    // Every change logged before this point is in a page that is already marked dirty.
+    let redo_lsn = bpm.log_manager().map_or(0, |log_manager| log_manager.insert_lsn());
+    let dirty_pages = bpm.dirty_pages();
+    for (i, batch) in dirty_pages.chunks(config.max_pages.max(1)).enumerate() {
+        if i > 0 {
+            monoio::time::sleep(config.delay).await;
+        }
+        bpm.flush_pages(batch).await?;
+    }
+
+    // The control record must never point past the durable end of the log, and the
+    // pages it stops replaying for must be on disk, whatever the commit level.
+    if let Some(log_manager) = bpm.log_manager() {
+        log_manager.flush(redo_lsn).await.map_err(|e| e.to_string())?;
+    }
+    disk_manager.sync_data().await.map_err(|e| e.to_string())?;
+
+    // A damaged control file is simply replaced by the new checkpoint.
+    let previous = last_checkpoint(disk_manager).await.ok().flatten();
+    let record = CheckpointRecord {
+        sequence: previous.map_or(1, |record| record.sequence + 1),
+        timestamp: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs()),
+        num_pages: disk_manager.num_pages(),
+        redo_lsn,
+        format_version: PAGE_FORMAT_VERSION,
+        free_pages: disk_manager.free_pages(),
+    };
+    disk_manager
+        .write_control_file(record.encode())
+        .await
+        .map_err(|e| e.to_string())?;
+    // Replay now starts at `redo_lsn`, so older segments are no longer needed.
+    if let Some(log_manager) = bpm.log_manager() {
+        log_manager
+            .recycle(redo_lsn)
+            .map_err(|e| format!("Failed to remove old WAL segments: {}", e))?;
+    }
+    Ok(record)
+}
+
+/// 后台写进程的主循环，负责帧号模 `num_workers` 等于 `worker` 的帧，永不返回。
+pub async fn run_bgwriter(
+    bpm: Arc<BufferPoolManager>,
+    config: BgWriterConfig,
+    worker: usize,
+    num_workers: usize,
+) {
+    let num_workers = num_workers.max(1);
+    loop {
+        monoio::time::sleep(config.delay).await;
+        if let Err(e) = bpm
+            .clean_upcoming_victims(config.max_pages, |frame_id| {
+                frame_id % num_workers == worker
+            })
+            .await
+        {
+            log::error!("Background writer {} failed: {}", worker, e);
+        }
+    }
+}
+
+/// 检查点任务的主循环，永不返回
+pub async fn run_checkpointer(bpm: Arc<BufferPoolManager>, config: BgWriterConfig) {
+    loop {
+        monoio::time::sleep(config.checkpoint_interval).await;
+        let started = Instant::now();
+        match checkpoint(&bpm, &config).await {
+            Ok(record) => log::info!(
+                "Checkpoint {} complete in {:?}",
+                record.sequence,
+                started.elapsed()
+            ),
+            Err(e) => log::error!("Checkpoint failed: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{
+        replacer::ReplacerKind,
+        wal::{self, LogManager},
+    };
+
+    fn temp_db(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("ringdb_{}_{}.db", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(format!("{}.control", path.display()));
+        wal::remove_log(&format!("{}.wal", path.display()));
+        path.to_string_lossy().into_owned()
+    }
+
+    #[monoio::test(timer_enabled = true)]
+    async fn test_bgwriter_and_checkpoint_clean_dirty_pages() {
+        let file = temp_db("bgwriter");
+        let dm = Arc::new(DiskManager::new(&file).await.unwrap());
+        let wal = Arc::new(LogManager::open(&format!("{}.wal", file)).await.unwrap());
+        let bpm = BufferPoolManager::with_log_manager(8, ReplacerKind::default(), dm.clone(), wal.clone());
+        for page_id in 0..6 {
+            bpm.fetch_page_mut(page_id).await.unwrap()[200] = 1;
+        }
+        assert_eq!(bpm.dirty_pages().len(), 6);
+
+        // Two writers split the pool; each one only touches its own frames.
+        let written = bpm
+            .clean_upcoming_victims(2, |frame_id| frame_id % 2 == 0)
+            .await
+            .unwrap();
+        assert_eq!(written, 2);
+        assert_eq!(bpm.dirty_pages().len(), 4);
+
+        assert_eq!(last_checkpoint(&dm).await.unwrap(), None);
+        let config = BgWriterConfig {
+            delay: Duration::from_millis(1),
+            max_pages: 3,
+            ..Default::default()
+        };
+        let first = checkpoint(&bpm, &config).await.unwrap();
+        assert!(bpm.dirty_pages().is_empty());
+        assert_eq!(first.sequence, 1);
+        // The redo point always advances, and the log is durable up to it.
+        assert_eq!(first.redo_lsn, wal.insert_lsn());
+        assert!(wal.flushed_lsn() >= first.redo_lsn);
+        let second = checkpoint(&bpm, &config).await.unwrap();
+        assert_eq!(second.sequence, 2);
+        assert_eq!(last_checkpoint(&dm).await.unwrap(), Some(second));
+
+        let _ = std::fs::remove_file(&file);
+        let _ = std::fs::remove_file(format!("{}.control", file));
+        wal::remove_log(&format!("{}.wal", file));
+    }
+
+    #[monoio::test]
+    async fn test_format_version_is_checked() {
+        let file = temp_db("format_version");
+        let dm = DiskManager::new(&file).await.unwrap();
+        // A fresh data file is accepted and needs its version recorded.
+        assert_eq!(check_format_version(&dm, None).await, Ok(false));
+
+        let record = CheckpointRecord {
+            sequence: 7,
+            timestamp: 0,
+            num_pages: 2,
+            redo_lsn: 100,
+            format_version: PAGE_FORMAT_VERSION,
+            free_pages: vec![1],
+        };
+        assert_eq!(check_format_version(&dm, Some(&record)).await, Ok(true));
+        let newer = CheckpointRecord {
+            format_version: PAGE_FORMAT_VERSION + 1,
+            ..record.clone()
+        };
+        assert!(check_format_version(&dm, Some(&newer)).await.is_err());
+
+        // A control file from before the version was recorded still decodes.
+        let mut bytes = record.encode();
+        bytes.truncate(4 + LEGACY_RECORD_LEN);
+        let checksum = crc32c::crc32c(&bytes[4..]);
+        bytes[..4].copy_from_slice(&checksum.to_le_bytes());
+        let legacy = CheckpointRecord::decode(&bytes).unwrap();
+        assert_eq!(legacy.format_version, 0);
+        assert_eq!(legacy.redo_lsn, 100);
+        assert!(legacy.free_pages.is_empty());
+
+        // A page in the old header-only layout has no checksum and is refused.
+        let mut page = vec![0u8; PAGE_SIZE];
+        page[0] = 1;
+        page[2] = 42;
+        let (res, _) = dm.write_pages(vec![(1, page)]).await;
+        res.unwrap();
+        assert!(check_format_version(&dm, Some(&legacy)).await.is_err());
+
+        let _ = std::fs::remove_file(&file);
+    }
+
+    #[monoio::test(timer_enabled = true)]
+    async fn test_deleted_pages_are_restored_after_a_crash() {
+        let file = temp_db("free_pages");
+        let wal_path = format!("{}.wal", file);
+        {
+            let dm = Arc::new(DiskManager::new(&file).await.unwrap());
+            let wal = Arc::new(LogManager::open(&wal_path).await.unwrap());
+            let bpm = BufferPoolManager::with_log_manager(8, ReplacerKind::default(), dm, wal.clone());
+            for _ in 0..4 {
+                bpm.new_page().await.unwrap()[200] = 1;
+            }
+            bpm.delete_page(2).await.unwrap();
+            bpm.delete_page(3).await.unwrap();
+            // Page 3 is handed out again before the crash.
+            let mut guard = bpm.new_page().await.unwrap();
+            assert_eq!(guard.page_id(), 3);
+            guard[200] = 1;
+            drop(guard);
+            // Only the log reaches the disk.
+            wal.flush(wal.insert_lsn()).await.unwrap();
+        }
+
+        let dm = DiskManager::new(&file).await.unwrap();
+        let wal = LogManager::open(&wal_path).await.unwrap();
+        let replayed = wal.replay(0, &dm).await.unwrap();
+        assert_eq!(restore_free_pages(&dm, replayed).await, Ok(1));
+        assert_eq!(dm.free_pages(), vec![2]);
+        assert_eq!(dm.allocate_page(), 2);
+        assert_eq!(dm.allocate_page(), 5);
+
+        let _ = std::fs::remove_file(&file);
+        wal::remove_log(&wal_path);
+    }
+}
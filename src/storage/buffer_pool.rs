@@ -1,126 +1,684 @@
 use std::{
     collections::HashMap,
-    sync::{Arc, Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard},
+    ops::{Deref, DerefMut},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
+    },
 };
 
+use async_lock::{RwLock, RwLockReadGuardArc, RwLockWriteGuardArc};
+
 use crate::storage::{
+    access_strategy::BufferAccessStrategy,
     disk::DiskManager,
-    page::{PAGE_SIZE, PageId},
-    replacer::ClockReplacer,
+    page::{INVALID_PAGE_ID, PAGE_SIZE, Page, PageError, PageId},
+    replacer::{Replacer, ReplacerKind},
+    wal::{LogManager, Lsn, WalRecord},
 };
 
 type FrameId = usize;
 
+#[derive(Debug, Clone)]
+pub enum BufferPoolError {
+    NoFreeFrame,
+    Io { page_id: PageId, error: String },
+    Corrupted { page_id: PageId, error: PageError },
+    /// fsync 失败
+    Sync(String),
+    /// 写回数据页之前刷日志失败
+    Wal(String),
+}
+
+impl std::fmt::Display for BufferPoolError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BufferPoolError::NoFreeFrame => write!(f, "No free frame available"),
+            BufferPoolError::Io { page_id, error } => {
+                write!(f, "I/O error on page {}: {}", page_id, error)
+            }
+            BufferPoolError::Corrupted { page_id, error } => {
+                write!(f, "Page {} is corrupted: {}", page_id, error)
+            }
+            BufferPoolError::Sync(error) => write!(f, "Failed to sync data file: {}", error),
+            BufferPoolError::Wal(error) => write!(f, "Failed to flush WAL: {}", error),
+        }
+    }
+}
+
+impl From<BufferPoolError> for String {
+    fn from(e: BufferPoolError) -> Self {
+        e.to_string()
+    }
+}
+
+/// 缓冲池中的一个帧。每个帧有独立的闩 (latch) 保护页数据，
+/// 元数据使用原子变量，读写不同页的任务之间互不阻塞。
+/// 闩是异步的：等待者挂起当前任务而不是阻塞 runtime 线程。
 pub struct Frame {
-    pub page_id: PageId,
-    pub data: Vec<u8>,
-    // pin_count: usize,
-    pub is_dirty: bool,
+    page_id: AtomicU32,
+    is_dirty: AtomicBool,
+    /// 该页最后一条日志记录的 LSN，写回前日志必须已刷到这里
+    page_lsn: AtomicU64,
+    data: Arc<RwLock<Vec<u8>>>,
 }
 
 pub struct BufferPoolManager {
-    _pool_size: usize,
-    // disk_manager: Arc<DiskManager>,
-    frames: Arc<RwLock<Vec<Frame>>>,
+    pool_size: usize,
+    disk_manager: Arc<DiskManager>,
+    log_manager: Option<Arc<LogManager>>,
+    frames: Vec<Frame>,
     page_table: Arc<Mutex<HashMap<PageId, FrameId>>>,
-    replacer: Arc<ClockReplacer>,
+    replacer: Box<dyn Replacer>,
+    hits: AtomicU64,
+    misses: AtomicU64,
 }
 
+/// 缓冲池命中统计
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BufferPoolStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl BufferPoolStats {
+    pub fn hit_ratio(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total == 0 {
+            0.0
+        } else {
+            self.hits as f64 / total as f64
+        }
+    }
+}
+
+/// 持有帧的共享闩，可直接解引用为页数据
 pub struct PageGuard {
     bpm: Arc<BufferPoolManager>,
     frame_id: FrameId,
+    page_id: PageId,
+    data: RwLockReadGuardArc<Vec<u8>>,
 }
 
+/// 持有帧的排他闩，可直接修改页数据。释放时只有页确实被修改过才把帧标记为脏并写日志
 pub struct PageWriteGuard {
     bpm: Arc<BufferPoolManager>,
     frame_id: FrameId,
+    page_id: PageId,
+    data: RwLockWriteGuardArc<Vec<u8>>,
+    /// 是否通过 `DerefMut` 修改过页数据
+    modified: bool,
 }
 
 impl BufferPoolManager {
-    pub fn new(pool_size: usize) -> Arc<Self> {
+    pub fn new(pool_size: usize, disk_manager: Arc<DiskManager>) -> Arc<Self> {
+        Self::with_replacer(pool_size, ReplacerKind::default(), disk_manager)
+    }
+
+    pub fn with_replacer(pool_size: usize, replacer: ReplacerKind, disk_manager: Arc<DiskManager>) -> Arc<Self> {
+        Self::build(pool_size, replacer, disk_manager, None)
+    }
+
+    /// 带预写日志的缓冲池：释放修改过页的排他闩时把页镜像写入日志，写回数据页之前先刷日志
+    pub fn with_log_manager(
+        pool_size: usize,
+        replacer: ReplacerKind,
+        disk_manager: Arc<DiskManager>,
+        log_manager: Arc<LogManager>,
+    ) -> Arc<Self> {
+        Self::build(pool_size, replacer, disk_manager, Some(log_manager))
+    }
+
+    fn build(
+        pool_size: usize,
+        replacer: ReplacerKind,
+        disk_manager: Arc<DiskManager>,
+        log_manager: Option<Arc<LogManager>>,
+    ) -> Arc<Self> {
         let frames = (0..pool_size)
             .map(|_| Frame {
-                page_id: u32::MAX,
-                is_dirty: false,
-                data: vec![0; PAGE_SIZE],
+                page_id: AtomicU32::new(INVALID_PAGE_ID),
+                is_dirty: AtomicBool::new(false),
+                page_lsn: AtomicU64::new(0),
+                data: Arc::new(RwLock::new(vec![0; PAGE_SIZE])),
             })
             .collect();
         Arc::new(Self {
-            _pool_size: pool_size,
-            // disk_manager,
-            frames: Arc::new(RwLock::new(frames)),
+            pool_size,
+            disk_manager,
+            log_manager,
+            frames,
             page_table: Arc::new(Mutex::new(HashMap::new())),
-            replacer: Arc::new(ClockReplacer::new(pool_size)),
+            replacer: replacer.build(pool_size),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
         })
     }
 
-    pub async fn fetch_page(self: &Arc<Self>, page_id: PageId, disk_manager: Arc<DiskManager>) -> Result<PageGuard, String> {
-        let frame_id = self.get_frame_for_page(page_id, disk_manager).await?;
-        Ok(PageGuard {
-            bpm: self.clone(),
-            frame_id,
-        })
+    pub fn stats(&self) -> BufferPoolStats {
+        BufferPoolStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
     }
 
-    pub async fn fetch_page_mut(self: &Arc<Self>, page_id: PageId, disk_manager: Arc<DiskManager>) -> Result<PageWriteGuard, String> {
-        let frame_id = self.get_frame_for_page(page_id, disk_manager).await?;
-        Ok(PageWriteGuard {
-            bpm: self.clone(),
-            frame_id,
-        })
+    pub fn pool_size(&self) -> usize {
+        self.pool_size
+    }
+
+    pub fn disk_manager(&self) -> &Arc<DiskManager> {
+        &self.disk_manager
+    }
+
+    pub fn log_manager(&self) -> Option<&Arc<LogManager>> {
+        self.log_manager.as_ref()
+    }
+
+    pub async fn fetch_page(self: &Arc<Self>, page_id: PageId) -> Result<PageGuard, BufferPoolError> {
+        self.fetch_page_with(page_id, None).await
+    }
+
+    pub async fn fetch_page_mut(self: &Arc<Self>, page_id: PageId) -> Result<PageWriteGuard, BufferPoolError> {
+        self.fetch_page_mut_with(page_id, None).await
     }
 
-    async fn get_frame_for_page(&self, page_id: PageId, disk_manager: Arc<DiskManager>) -> Result<usize, String> {
+    /// 与 `fetch_page` 相同，但缺页时可以通过 `strategy` 限定只复用其私有环中的帧
+    pub async fn fetch_page_with(
+        self: &Arc<Self>,
+        page_id: PageId,
+        strategy: Option<&BufferAccessStrategy>,
+    ) -> Result<PageGuard, BufferPoolError> {
+        loop {
+            let frame_id = self.get_frame_for_page(page_id, strategy, true).await?;
+            let data = self.frames[frame_id].data.read_arc().await;
+            if self.frames[frame_id].page_id.load(Ordering::Acquire) == page_id {
+                return Ok(PageGuard {
+                    bpm: self.clone(),
+                    frame_id,
+                    page_id,
+                    data,
+                });
+            }
+            // The load we waited on failed or the frame was repurposed; try again.
+            drop(data);
+            self.replacer.unpin(frame_id);
+        }
+    }
+
+    /// 一次取回多个页，返回的守卫与 `page_ids` 一一对应。
+    ///
+    /// 所有缺页先各自选好牺牲帧，然后一起写回脏的旧页、一起读入新页，
+    /// 相邻的页由 `DiskManager` 合并成一次 I/O。任何一页失败时释放已经拿到的页并返回错误。
+    pub async fn fetch_pages_with(
+        self: &Arc<Self>,
+        page_ids: &[PageId],
+        strategy: Option<&BufferAccessStrategy>,
+    ) -> Result<Vec<PageGuard>, BufferPoolError> {
+        let mut frames: Vec<Option<FrameId>> = vec![None; page_ids.len()];
+        let mut loads = Vec::new();
+        let mut load_slots = Vec::new();
+        let mut error = None;
+        for (i, &page_id) in page_ids.iter().enumerate() {
+            match self.reserve_frame(page_id, strategy, true) {
+                Ok(Reservation::Resident(frame_id)) => frames[i] = Some(frame_id),
+                Ok(Reservation::Load(load)) => {
+                    load_slots.push(i);
+                    loads.push(load);
+                }
+                Err(e) => {
+                    error = Some(e);
+                    break;
+                }
+            }
+        }
+        for (i, result) in load_slots.into_iter().zip(self.load_frames(loads, true).await) {
+            match result {
+                Ok(frame_id) => frames[i] = Some(frame_id),
+                Err(e) => {
+                    error.get_or_insert(e);
+                }
+            }
+        }
+
+        let mut guards = Vec::with_capacity(page_ids.len());
+        for (&page_id, frame_id) in page_ids.iter().zip(frames) {
+            let Some(frame_id) = frame_id else { continue };
+            if error.is_some() {
+                self.replacer.unpin(frame_id);
+                continue;
+            }
+            let data = self.frames[frame_id].data.read_arc().await;
+            if self.frames[frame_id].page_id.load(Ordering::Acquire) == page_id {
+                guards.push(PageGuard {
+                    bpm: self.clone(),
+                    frame_id,
+                    page_id,
+                    data,
+                });
+                continue;
+            }
+            // Another task's load of this page failed; fall back to a single fetch.
+            drop(data);
+            self.replacer.unpin(frame_id);
+            match self.fetch_page_with(page_id, strategy).await {
+                Ok(guard) => guards.push(guard),
+                Err(e) => error = Some(e),
+            }
+        }
+
+        match error {
+            Some(e) => Err(e),
+            None => Ok(guards),
+        }
+    }
+
+    pub async fn fetch_page_mut_with(
+        self: &Arc<Self>,
+        page_id: PageId,
+        strategy: Option<&BufferAccessStrategy>,
+    ) -> Result<PageWriteGuard, BufferPoolError> {
+        self.fetch_page_mut_inner(page_id, strategy, true).await
+    }
+
+    /// 在数据文件中分配一个新页，并返回装有全零内容、已 pin 住的帧。
+    /// 新页不需要从磁盘读取；释放守卫后它和其它脏页一样等待写回。
+    pub async fn new_page(self: &Arc<Self>) -> Result<PageWriteGuard, BufferPoolError> {
+        let page_id = self.disk_manager.allocate_page();
+        let mut guard = match self.fetch_page_mut_inner(page_id, None, false).await {
+            Ok(guard) => guard,
+            Err(e) => {
+                self.disk_manager.deallocate_page(page_id);
+                return Err(e);
+            }
+        };
+        // A scan may have cached this page id before it was allocated.
+        guard.fill(0);
+        Ok(guard)
+    }
+
+    /// 把 `page_id` 归还给 `DiskManager`，页内容被替换为空闲页标记。
+    ///
+    /// 标记和其它修改一样写入 WAL 并等待写回，重启时只有磁盘上仍是空闲页标记的页
+    /// 才会回到空闲页表，所以已经重新分配出去的页不会被再次分配。
+    /// 调用者需保证已经没有指向这个页的引用；页被其它守卫持有时等待它释放。
+    pub async fn delete_page(self: &Arc<Self>, page_id: PageId) -> Result<(), BufferPoolError> {
+        let mut guard = self.fetch_page_mut_inner(page_id, None, false).await?;
+        guard.copy_from_slice(&Page::new_free().to_bytes());
+        drop(guard);
+        self.disk_manager.deallocate_page(page_id);
+        Ok(())
+    }
+
+    /// 如果 `page_id` 在缓冲池中且是脏页，把它写回磁盘。
+    /// 返回该页是否在缓冲池中。写回不包含 fsync，见 `flush_all`。
+    pub async fn flush_page(&self, page_id: PageId) -> Result<bool, BufferPoolError> {
+        let frame_id = {
+            let page_table = self.page_table.lock().unwrap();
+            let Some(&frame_id) = page_table.get(&page_id) else {
+                return Ok(false);
+            };
+            self.replacer.pin(frame_id);
+            frame_id
+        };
+        let result = self.flush_frame(frame_id, page_id).await;
+        self.replacer.unpin(frame_id);
+        result
+    }
+
+    /// 批量写回 `page_ids` 中仍在缓冲池里的脏页，相邻的页合并成一次写入。
+    /// 返回实际写回的页数，写回不包含 fsync。
+    pub async fn flush_pages(&self, page_ids: &[PageId]) -> Result<usize, BufferPoolError> {
+        let mut pinned = Vec::new();
         {
             let page_table = self.page_table.lock().unwrap();
-            if let Some(&frame_id) = page_table.get(&page_id) {
-                self.replacer.pin(frame_id);
-                return Ok(frame_id);
+            for &page_id in page_ids {
+                if let Some(&frame_id) = page_table.get(&page_id) {
+                    self.replacer.pin(frame_id);
+                    pinned.push((frame_id, page_id));
+                }
             }
         }
 
-        let frame_id = self.replacer.victim().ok_or("No free frame available")?;
-        self.replacer.pin(frame_id);
-
-        let (old_page_id, is_dirty, mut data_buf) = {
-            let mut frames = self.frames.write().unwrap();
-            let victim_frame = &mut frames[frame_id];
-            let old_page_id = victim_frame.page_id;
-            let is_dirty = victim_frame.is_dirty;
-            // Postpone modifying the frame's metadata until after I/O
-            let data_buf = std::mem::take(&mut victim_frame.data);
+        // Holding the shared latches keeps writers out until the dirty bits are cleared.
+        let mut latches = Vec::new();
+        let mut pages = Vec::new();
+        for &(frame_id, page_id) in &pinned {
+            let frame = &self.frames[frame_id];
+            let data = frame.data.read_arc().await;
+            if frame.page_id.load(Ordering::Acquire) != page_id || !frame.is_dirty.load(Ordering::Acquire) {
+                continue;
+            }
+            let mut buf = data.to_vec();
+            Page::update_checksum(&mut buf);
+            pages.push((page_id, buf));
+            latches.push((frame_id, data));
+        }
 
-            (old_page_id, is_dirty, data_buf)
+        let written = pages.len();
+        let first_page_id = pages.first().map(|&(page_id, _)| page_id);
+        let res = match self.flush_log_for(latches.iter().map(|&(frame_id, _)| frame_id)).await {
+            Ok(()) => self.disk_manager.write_pages(pages).await.0.map_err(|e| BufferPoolError::Io {
+                page_id: first_page_id.unwrap_or(INVALID_PAGE_ID),
+                error: e.to_string(),
+            }),
+            Err(e) => Err(e),
         };
+        if res.is_ok() {
+            for &(frame_id, _) in &latches {
+                self.frames[frame_id].is_dirty.store(false, Ordering::Release);
+            }
+        }
+        drop(latches);
+        for (frame_id, _) in pinned {
+            self.replacer.unpin(frame_id);
+        }
+        res.map(|_| written)
+    }
+
+    /// 写回所有脏页并 fsync 数据文件，用于检查点和正常关闭。
+    pub async fn flush_all(&self) -> Result<(), BufferPoolError> {
+        self.flush_pages(&self.dirty_pages()).await?;
+        self.disk_manager
+            .sync()
+            .await
+            .map_err(|e| BufferPoolError::Sync(e.to_string()))
+    }
+
+    /// 当前缓冲池中所有脏页的页号。检查点以此作为本次需要写回的集合。
+    pub fn dirty_pages(&self) -> Vec<PageId> {
+        self.page_table
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|&(_, &frame_id)| self.frames[frame_id].is_dirty.load(Ordering::Acquire))
+            .map(|(&page_id, _)| page_id)
+            .collect()
+    }
+
+    /// 提前写回替换策略接下来要淘汰的脏帧，让缺页的查询不必在关键路径上同步写盘。
+    /// 只处理 `owns(frame_id)` 为真的帧，最多写回 `max_pages` 页，返回实际写回的页数。
+    pub async fn clean_upcoming_victims(
+        &self,
+        max_pages: usize,
+        owns: impl Fn(usize) -> bool,
+    ) -> Result<usize, BufferPoolError> {
+        let page_ids: Vec<PageId> = self
+            .replacer
+            .upcoming_victims(self.pool_size)
+            .into_iter()
+            .filter(|&frame_id| owns(frame_id) && self.frames[frame_id].is_dirty.load(Ordering::Acquire))
+            .map(|frame_id| self.frames[frame_id].page_id.load(Ordering::Acquire))
+            .filter(|&page_id| page_id != INVALID_PAGE_ID)
+            .take(max_pages)
+            .collect();
+        self.flush_pages(&page_ids).await
+    }
 
-        if is_dirty {
-            let (res, buf) = disk_manager.write_page(old_page_id, data_buf).await;
-            res.map_err(|e| format!("Failed to write page {}: {}", old_page_id, e))?;
-            data_buf = buf;
+    /// 在共享闩下写回一个已 pin 住的帧。持有共享闩期间没有写者，
+    /// 所以清除脏标记时不会丢掉并发的修改。
+    async fn flush_frame(&self, frame_id: FrameId, page_id: PageId) -> Result<bool, BufferPoolError> {
+        let frame = &self.frames[frame_id];
+        let data = frame.data.read_arc().await;
+        if frame.page_id.load(Ordering::Acquire) != page_id {
+            // Still being loaded and the load failed; nothing to write.
+            return Ok(false);
+        }
+        if !frame.is_dirty.load(Ordering::Acquire) {
+            return Ok(true);
         }
-        data_buf.fill(0);
 
-        let (res, buf) = disk_manager.read_page(page_id, data_buf).await;
-        res.map_err(|e| format!("Failed to read page {}: {}", page_id, e))?;
+        self.flush_log_for([frame_id]).await?;
+        let mut buf = data.to_vec();
+        Page::update_checksum(&mut buf);
+        let (res, _) = self.disk_manager.write_page(page_id, buf).await;
+        res.map_err(|e| BufferPoolError::Io {
+            page_id,
+            error: e.to_string(),
+        })?;
+        frame.is_dirty.store(false, Ordering::Release);
+        drop(data);
+        Ok(true)
+    }
+
+    /// 先把日志刷到这些帧中页的最后一条记录，保证日志先于数据落盘
+    async fn flush_log_for(&self, frame_ids: impl IntoIterator<Item = FrameId>) -> Result<(), BufferPoolError> {
+        let Some(log_manager) = &self.log_manager else {
+            return Ok(());
+        };
+        let lsn: Lsn = frame_ids
+            .into_iter()
+            .map(|frame_id| self.frames[frame_id].page_lsn.load(Ordering::Acquire))
+            .max()
+            .unwrap_or(0);
+        log_manager
+            .flush(lsn)
+            .await
+            .map_err(|e| BufferPoolError::Wal(e.to_string()))
+    }
+
+    async fn fetch_page_mut_inner(
+        self: &Arc<Self>,
+        page_id: PageId,
+        strategy: Option<&BufferAccessStrategy>,
+        read_from_disk: bool,
+    ) -> Result<PageWriteGuard, BufferPoolError> {
+        loop {
+            let frame_id = self
+                .get_frame_for_page(page_id, strategy, read_from_disk)
+                .await?;
+            let data = self.frames[frame_id].data.write_arc().await;
+            if self.frames[frame_id].page_id.load(Ordering::Acquire) == page_id {
+                return Ok(PageWriteGuard {
+                    bpm: self.clone(),
+                    frame_id,
+                    page_id,
+                    data,
+                    modified: false,
+                });
+            }
+            drop(data);
+            self.replacer.unpin(frame_id);
+        }
+    }
 
+    /// 尝试复用环中当前槽位的帧：它必须仍装着该环之前读入的页，且没有被 pin。
+    fn reuse_ring_frame(&self, strategy: &BufferAccessStrategy) -> Option<(FrameId, RwLockWriteGuardArc<Vec<u8>>)> {
+        let (frame_id, ring_page_id) = strategy.current()?;
+        if self.frames[frame_id].page_id.load(Ordering::Acquire) != ring_page_id
+            || !self.replacer.evict(frame_id)
         {
-            let mut frames = self.frames.write().unwrap();
-            let mut page_table = self.page_table.lock().unwrap();
+            return None;
+        }
+        match self.frames[frame_id].data.try_write_arc() {
+            Some(latch) => Some((frame_id, latch)),
+            None => {
+                self.replacer.unpin(frame_id);
+                None
+            }
+        }
+    }
+
+    /// 返回一个已 pin 住、正在或已经装入 `page_id` 的帧。
+    ///
+    /// 页表在 I/O 开始前就指向目标帧，而装入过程全程持有该帧的排他闩，
+    /// 所以并发请求同一个缺失页的任务只会等待同一次 I/O。调用者拿到闩之后
+    /// 仍需检查帧中的页号，因为装入可能失败。
+    /// `read_from_disk` 为 false 时不读盘，缺页直接得到全零的帧（用于新分配的页）。
+    async fn get_frame_for_page(
+        &self,
+        page_id: PageId,
+        strategy: Option<&BufferAccessStrategy>,
+        read_from_disk: bool,
+    ) -> Result<usize, BufferPoolError> {
+        match self.reserve_frame(page_id, strategy, read_from_disk)? {
+            Reservation::Resident(frame_id) => Ok(frame_id),
+            Reservation::Load(load) => self.load_frames(vec![load], read_from_disk).await.pop().unwrap(),
+        }
+    }
+
+    /// 命中时 pin 住所在的帧；缺页时选出牺牲帧、拿到它的排他闩并发布新的映射。
+    fn reserve_frame(
+        &self,
+        page_id: PageId,
+        strategy: Option<&BufferAccessStrategy>,
+        read_from_disk: bool,
+    ) -> Result<Reservation, BufferPoolError> {
+        let mut page_table = self.page_table.lock().unwrap();
+        if let Some(&frame_id) = page_table.get(&page_id) {
+            self.replacer.pin(frame_id);
+            self.replacer.record_access(frame_id, page_id);
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(Reservation::Resident(frame_id));
+        }
+        if read_from_disk {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+        }
 
-            if old_page_id != u32::MAX {
-                page_table.remove(&old_page_id);
+        // An unpinned frame's latch can only be held by a guard that is
+        // being dropped on another core; skip such frames.
+        let mut victim = strategy.and_then(|s| self.reuse_ring_frame(s));
+        for _ in 0..self.pool_size {
+            if victim.is_some() {
+                break;
+            }
+            let frame_id = self.replacer.victim().ok_or(BufferPoolError::NoFreeFrame)?;
+            if let Some(latch) = self.frames[frame_id].data.try_write_arc() {
+                victim = Some((frame_id, latch));
+                break;
             }
+            self.replacer.unpin(frame_id);
+        }
+        let (frame_id, latch) = victim.ok_or(BufferPoolError::NoFreeFrame)?;
+        if let Some(strategy) = strategy {
+            strategy.record(frame_id, page_id);
+        }
+
+        page_table.insert(page_id, frame_id);
+        Ok(Reservation::Load(FrameLoad {
+            frame_id,
+            page_id,
+            old_page_id: self.frames[frame_id].page_id.load(Ordering::Acquire),
+            latch,
+        }))
+    }
 
-            let victim_frame = &mut frames[frame_id];
-            victim_frame.page_id = page_id;
-            victim_frame.is_dirty = false;
-            victim_frame.data = buf;
+    /// 完成一批缺页的装入：先批量写回牺牲帧中的脏页，再批量读入新页。
+    /// 结果与 `loads` 一一对应。
+    async fn load_frames(&self, loads: Vec<FrameLoad>, read_from_disk: bool) -> Vec<Result<FrameId, BufferPoolError>> {
+        let count = loads.len();
+        let mut loads = match self.write_back_victims(loads).await {
+            Ok(loads) => loads,
+            Err(e) => return vec![Err(e); count],
+        };
 
-            page_table.insert(page_id, frame_id);
+        let mut results = Vec::with_capacity(count);
+        if read_from_disk {
+            let requests = loads
+                .iter_mut()
+                .map(|load| (load.page_id, std::mem::take(&mut *load.latch)))
+                .collect();
+            let (res, buffers) = self.disk_manager.read_pages(requests).await;
+            for (load, (page_id, buf)) in loads.into_iter().zip(buffers) {
+                let load_result = match &res {
+                    Ok(()) => Page::verify(&buf).map_err(|error| BufferPoolError::Corrupted { page_id, error }),
+                    Err(e) => Err(BufferPoolError::Io {
+                        page_id,
+                        error: e.to_string(),
+                    }),
+                };
+                results.push(self.finish_load(load, buf, load_result));
+            }
+        } else {
+            for mut load in loads {
+                let buf = std::mem::take(&mut *load.latch);
+                results.push(self.finish_load(load, buf, Ok(())));
+            }
         }
+        results
+    }
 
-        Ok(frame_id)
+    /// 写回牺牲帧中的旧页（如果是脏页），然后解除旧页的映射，并把帧清零。
+    /// 旧页在写回之前一直映射在原来的帧上，并发的读取不会从磁盘读到过时的内容。
+    async fn write_back_victims(&self, mut loads: Vec<FrameLoad>) -> Result<Vec<FrameLoad>, BufferPoolError> {
+        let dirty: Vec<usize> = (0..loads.len())
+            .filter(|&i| self.frames[loads[i].frame_id].is_dirty.load(Ordering::Acquire))
+            .collect();
+        if !dirty.is_empty() {
+            let res = match self.flush_log_for(dirty.iter().map(|&i| loads[i].frame_id)).await {
+                Ok(()) => {
+                    let pages = dirty
+                        .iter()
+                        .map(|&i| {
+                            let mut buf = std::mem::take(&mut *loads[i].latch);
+                            Page::update_checksum(&mut buf);
+                            (loads[i].old_page_id, buf)
+                        })
+                        .collect();
+                    let (res, pages) = self.disk_manager.write_pages(pages).await;
+                    for (&i, (_, buf)) in dirty.iter().zip(pages) {
+                        *loads[i].latch = buf;
+                    }
+                    res.map_err(|e| BufferPoolError::Io {
+                        page_id: loads[dirty[0]].old_page_id,
+                        error: e.to_string(),
+                    })
+                }
+                Err(e) => Err(e),
+            };
+            if let Err(e) = res {
+                // The victims keep their (still dirty) pages so nothing is lost.
+                for load in loads {
+                    self.page_table.lock().unwrap().remove(&load.page_id);
+                    drop(load.latch);
+                    self.replacer.unpin(load.frame_id);
+                }
+                return Err(e);
+            }
+        }
+
+        for load in loads.iter_mut() {
+            self.frames[load.frame_id].is_dirty.store(false, Ordering::Release);
+            if load.old_page_id != INVALID_PAGE_ID {
+                self.page_table.lock().unwrap().remove(&load.old_page_id);
+            }
+            load.latch.fill(0);
+        }
+        Ok(loads)
     }
+
+    /// 把读入的内容放进帧中并释放排他闩。装入失败时帧变为空帧。
+    fn finish_load(&self, mut load: FrameLoad, mut buf: Vec<u8>, load_result: Result<(), BufferPoolError>) -> Result<FrameId, BufferPoolError> {
+        let frame = &self.frames[load.frame_id];
+        if let Err(e) = load_result {
+            // The old page has already been written back, so the frame becomes empty.
+            frame.page_id.store(INVALID_PAGE_ID, Ordering::Release);
+            buf.fill(0);
+            *load.latch = buf;
+            self.page_table.lock().unwrap().remove(&load.page_id);
+            drop(load.latch);
+            self.replacer.unpin(load.frame_id);
+            return Err(e);
+        }
+
+        frame.page_id.store(load.page_id, Ordering::Release);
+        *load.latch = buf;
+        self.replacer.record_access(load.frame_id, load.page_id);
+        Ok(load.frame_id)
+    }
+}
+
+/// 缺页时为 `page_id` 选中的牺牲帧。装入完成之前一直持有它的排他闩。
+struct FrameLoad {
+    frame_id: FrameId,
+    page_id: PageId,
+    old_page_id: PageId,
+    latch: RwLockWriteGuardArc<Vec<u8>>,
+}
+
+enum Reservation {
+    /// 页已经在（或正在装入）这个帧中
+    Resident(FrameId),
+    Load(FrameLoad),
 }
 
 impl PageGuard {
@@ -128,12 +686,14 @@ impl PageGuard {
         self.frame_id
     }
     pub fn page_id(&self) -> u32 {
-        let frames = self.bpm.frames.read().unwrap();
-        frames[self.frame_id].page_id
+        self.page_id
     }
+}
+impl Deref for PageGuard {
+    type Target = [u8];
 
-    pub fn get<'a>(&'a self) -> RwLockReadGuard<'a, Vec<Frame>> {
-        self.bpm.frames.read().unwrap()
+    fn deref(&self) -> &[u8] {
+        &self.data
     }
 }
 impl Drop for PageGuard {
@@ -146,18 +706,227 @@ impl PageWriteGuard {
         self.frame_id
     }
     pub fn page_id(&self) -> u32 {
-        let frames = self.bpm.frames.read().unwrap();
-        frames[self.frame_id].page_id
+        self.page_id
     }
+}
+impl Deref for PageWriteGuard {
+    type Target = [u8];
 
-    pub fn get_mut<'a>(&'a mut self) -> RwLockWriteGuard<'a, Vec<Frame>> {
-        self.bpm.frames.write().unwrap()
+    fn deref(&self) -> &[u8] {
+        &self.data
+    }
+}
+impl DerefMut for PageWriteGuard {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        self.modified = true;
+        &mut self.data
     }
 }
 impl Drop for PageWriteGuard {
     fn drop(&mut self) {
-        let mut frames = self.bpm.frames.write().unwrap();
-        frames[self.frame_id].is_dirty = true;
+        if !self.modified {
+            self.bpm.replacer.unpin(self.frame_id);
+            return;
+        }
+        let frame = &self.bpm.frames[self.frame_id];
+        // Mark the page dirty before logging it, so a checkpoint whose redo point
+        // lies after this record is guaranteed to see the page as dirty.
+        frame.is_dirty.store(true, Ordering::Release);
+        if let Some(log_manager) = &self.bpm.log_manager {
+            let lsn = log_manager.append(&WalRecord::PageImage {
+                page_id: self.page_id,
+                image: self.data.to_vec(),
+            });
+            frame.page_lsn.store(lsn, Ordering::Release);
+        }
         self.bpm.replacer.unpin(self.frame_id);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    const POOL_SIZE: usize = 8;
+    const NUM_PAGES: PageId = 32;
+    const NUM_TASKS: usize = 32;
+    const ROUNDS: usize = 50;
+
+    fn temp_db(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("ringdb_{}_{}.db", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path.to_string_lossy().into_owned()
+    }
+
+    async fn fetch_with_retry(bpm: &Arc<BufferPoolManager>, page_id: PageId) -> PageGuard {
+        loop {
+            match bpm.fetch_page(page_id).await {
+                Ok(guard) => return guard,
+                Err(BufferPoolError::NoFreeFrame) => monoio::time::sleep(Duration::from_micros(50)).await,
+                Err(e) => panic!("{}", e),
+            }
+        }
+    }
+
+    async fn fetch_mut_with_retry(bpm: &Arc<BufferPoolManager>, page_id: PageId) -> PageWriteGuard {
+        loop {
+            match bpm.fetch_page_mut(page_id).await {
+                Ok(guard) => return guard,
+                Err(BufferPoolError::NoFreeFrame) => monoio::time::sleep(Duration::from_micros(50)).await,
+                Err(e) => panic!("{}", e),
+            }
+        }
+    }
+
+    #[monoio::test(timer_enabled = true)]
+    async fn test_pinned_frames_are_never_evicted() {
+        for kind in [ReplacerKind::Clock, ReplacerKind::LruK(2), ReplacerKind::Arc] {
+            pin_stress(kind).await;
+        }
+    }
+
+    async fn pin_stress(kind: ReplacerKind) {
+        let file = temp_db(&format!("pin_stress_{:?}", kind));
+        let dm = Arc::new(DiskManager::new(&file).await.unwrap());
+        let bpm = BufferPoolManager::with_replacer(POOL_SIZE, kind, dm);
+
+        let tasks: Vec<_> = (0..NUM_TASKS)
+            .map(|t| {
+                let bpm = bpm.clone();
+                monoio::spawn(async move {
+                    for round in 0..ROUNDS {
+                        let page_id = ((t * 7 + round * 13) % NUM_PAGES as usize) as PageId;
+                        let stamp = (page_id + 1).to_le_bytes();
+
+                        let mut guard = fetch_mut_with_retry(&bpm, page_id).await;
+                        if guard[100..104] == [0; 4] {
+                            guard[100..104].copy_from_slice(&stamp);
+                        }
+                        assert_eq!(guard[100..104], stamp);
+                        drop(guard);
+
+                        // Hold a pin while the other tasks churn through the pool.
+                        let guard = fetch_with_retry(&bpm, page_id).await;
+                        for _ in 0..3 {
+                            monoio::time::sleep(Duration::from_micros(50)).await;
+                            assert_eq!(guard[100..104], stamp);
+                            let frame = &bpm.frames[guard.frame_id()];
+                            assert_eq!(frame.page_id.load(Ordering::Acquire), page_id);
+                            assert!(bpm.replacer.pin_count(guard.frame_id()) > 0);
+                        }
+                    }
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await;
+        }
+
+        for frame_id in 0..POOL_SIZE {
+            assert_eq!(bpm.replacer.pin_count(frame_id), 0);
+        }
+        let _ = std::fs::remove_file(&file);
+    }
+
+    #[monoio::test(timer_enabled = true)]
+    async fn test_concurrent_misses_share_one_frame() {
+        let file = temp_db("single_flight");
+        let dm = Arc::new(DiskManager::new(&file).await.unwrap());
+        let bpm = BufferPoolManager::new(POOL_SIZE, dm.clone());
+
+        let (a, b) = futures::join!(bpm.fetch_page(3), bpm.fetch_page(3));
+        let (a, b) = (a.unwrap(), b.unwrap());
+        assert_eq!(a.frame_id(), b.frame_id());
+        assert_eq!(bpm.replacer.pin_count(a.frame_id()), 2);
+        assert_eq!(bpm.page_table.lock().unwrap().len(), 1);
+
+        drop(a);
+        assert_eq!(bpm.replacer.pin_count(b.frame_id()), 1);
+        let _ = std::fs::remove_file(&file);
+    }
+
+    #[monoio::test(timer_enabled = true)]
+    async fn test_ring_scan_keeps_hot_pages_resident() {
+        let file = temp_db("ring_scan");
+        let dm = Arc::new(DiskManager::new(&file).await.unwrap());
+        let bpm = BufferPoolManager::new(16, dm);
+        for page_id in 0..4 {
+            drop(bpm.fetch_page(page_id).await.unwrap());
+        }
+
+        let strategy = BufferAccessStrategy::for_table(100, bpm.pool_size()).unwrap();
+        assert_eq!(strategy.ring_size(), 4);
+        for page_id in 100..200 {
+            drop(bpm.fetch_page_with(page_id, Some(&strategy)).await.unwrap());
+        }
+
+        let page_table = bpm.page_table.lock().unwrap();
+        for page_id in 0..4 {
+            assert!(page_table.contains_key(&page_id), "hot page {} was evicted", page_id);
+        }
+        assert!(page_table.len() <= 4 + strategy.ring_size());
+        drop(page_table);
+        let _ = std::fs::remove_file(&file);
+    }
+
+    #[monoio::test(timer_enabled = true)]
+    async fn test_new_page_flush_and_delete() {
+        let file = temp_db("flush_all");
+        let dm = Arc::new(DiskManager::new(&file).await.unwrap());
+        let bpm = BufferPoolManager::new(POOL_SIZE, dm.clone());
+
+        let mut guard = bpm.new_page().await.unwrap();
+        let page_id = guard.page_id();
+        assert_eq!(page_id, 1);
+        assert!(guard.iter().all(|&b| b == 0));
+        guard[100] = 42;
+        drop(guard);
+        bpm.flush_all().await.unwrap();
+        assert!(!bpm.frames.iter().any(|f| f.is_dirty.load(Ordering::Acquire)));
+        // Taking the write latch without changing the page leaves it clean.
+        drop(bpm.fetch_page_mut(page_id).await.unwrap());
+        assert!(bpm.dirty_pages().is_empty());
+
+        // A fresh pool must see the flushed contents on disk.
+        let other = BufferPoolManager::new(POOL_SIZE, dm.clone());
+        assert_eq!(other.fetch_page(page_id).await.unwrap()[100], 42);
+
+        // The free marker is written back like any other change.
+        bpm.delete_page(page_id).await.unwrap();
+        assert_eq!(bpm.dirty_pages(), vec![page_id]);
+        assert!(bpm.flush_page(page_id).await.unwrap());
+        let (res, buf) = dm.read_page(page_id, vec![0; PAGE_SIZE]).await;
+        res.unwrap();
+        let page = Page::from_bytes(buf.try_into().unwrap()).unwrap();
+        assert_eq!(page.header.page_type, crate::storage::page::PageType::Free);
+        assert_eq!(dm.allocate_page(), page_id);
+        let _ = std::fs::remove_file(&file);
+    }
+
+    #[monoio::test(timer_enabled = true)]
+    async fn test_batched_fetch_and_flush() {
+        let file = temp_db("batched");
+        let dm = Arc::new(DiskManager::new(&file).await.unwrap());
+        let bpm = BufferPoolManager::new(16, dm.clone());
+        for page_id in [0, 1, 2, 3, 7, 8, 12] {
+            bpm.fetch_page_mut(page_id).await.unwrap()[100] = page_id as u8 + 1;
+        }
+        assert_eq!(bpm.flush_pages(&bpm.dirty_pages()).await.unwrap(), 7);
+        assert!(bpm.dirty_pages().is_empty());
+
+        // Out of order, with a duplicate and a page that was never written.
+        let other = BufferPoolManager::new(16, dm.clone());
+        let page_ids = [8, 0, 1, 2, 3, 12, 7, 1, 20];
+        let guards = other.fetch_pages_with(&page_ids, None).await.unwrap();
+        for (guard, &page_id) in guards.iter().zip(&page_ids) {
+            assert_eq!(guard.page_id(), page_id);
+            let expected = if page_id == 20 { 0 } else { page_id as u8 + 1 };
+            assert_eq!(guard[100], expected);
+        }
+        assert_eq!(guards[2].frame_id(), guards[7].frame_id());
+        assert_eq!(other.stats().misses, 8);
+        let _ = std::fs::remove_file(&file);
+    }
+}
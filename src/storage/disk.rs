@@ -1,23 +1,134 @@
-use std::io;
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    io,
+    rc::Rc,
+    sync::{
+        Mutex,
+        atomic::{AtomicU32, AtomicU64, Ordering},
+    },
+};
 
 use crate::storage::page::{PAGE_SIZE, PageId};
-use monoio::fs::{File, OpenOptions};
-// use std::os::unix::fs::OpenOptionsExt;
+use monoio::{
+    buf::{IoBuf, IoBufMut},
+    fs::{File, OpenOptions},
+};
+
+/// 一次合并读写最多包含的页数 (256 KiB)
+pub const MAX_COALESCED_PAGES: usize = 32;
+
+static NEXT_SHARED_FILE_ID: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    /// 每个 runtime 线程各自持有的文件句柄，按 `SharedFile::id` 索引
+    static CORE_FILES: RefCell<HashMap<u64, Rc<File>>> = RefCell::new(HashMap::new());
+}
+
+/// 可以在多个 runtime 线程之间共享的文件。
+/// monoio 的文件句柄不能跨线程使用，所以每个 runtime 线程在第一次访问时
+/// 复制一份文件描述符，之后的 I/O 都提交到本线程自己的 io_uring 上。
+pub(crate) struct SharedFile {
+    id: u64,
+    file: std::fs::File,
+}
+
+impl SharedFile {
+    pub(crate) fn new(file: std::fs::File) -> Self {
+        Self {
+            id: NEXT_SHARED_FILE_ID.fetch_add(1, Ordering::Relaxed),
+            file,
+        }
+    }
+
+    pub(crate) fn std_file(&self) -> &std::fs::File {
+        &self.file
+    }
+
+    /// 当前线程上的文件句柄，第一次调用时创建
+    pub(crate) fn core_file(&self) -> io::Result<Rc<File>> {
+        CORE_FILES.with(|files| {
+            if let Some(file) = files.borrow().get(&self.id) {
+                return Ok(file.clone());
+            }
+            let file = Rc::new(File::from_std(self.file.try_clone()?)?);
+            files.borrow_mut().insert(self.id, file.clone());
+            Ok(file)
+        })
+    }
+}
+
+impl Drop for SharedFile {
+    fn drop(&mut self) {
+        // Handles opened on other cores are closed when those threads exit.
+        let _ = CORE_FILES.try_with(|files| files.borrow_mut().remove(&self.id));
+    }
+}
+
+/// 管理一个数据文件。页分配状态在所有核之间共享，I/O 通过 `SharedFile`
+/// 提交到调用者所在线程的 io_uring 上。
 pub struct DiskManager {
-    file: File,
+    file: SharedFile,
+    /// 控制文件路径，保存最近一次检查点等元数据
+    control_path: String,
+    next_page_id: AtomicU32,
+    /// 被删除、可以重新分配的页。检查点把它写入控制文件，启动时由
+    /// `bgwriter::restore_free_pages` 恢复
+    free_pages: Mutex<Vec<PageId>>,
 }
 
 impl DiskManager {
     pub async fn new(file_path: &str) -> io::Result<Self> {
-        let file = OpenOptions::new()
+        let file = std::fs::OpenOptions::new()
             .read(true)
             .write(true)
             .create(true)
+            .truncate(false)
             // .custom_flags(libc::O_DIRECT) // TODO: 目前直接使用 O_DIRECT 会导致读写失败，后续需要处理对齐问题
-            .open(file_path)
-            .await?;
+            .open(file_path)?;
+
+        // 页 0 保留不分配，新分配的页从文件末尾开始
+        let file_len = file.metadata()?.len();
+        let page_count = file_len.div_ceil(PAGE_SIZE as u64) as PageId;
+
+        Ok(Self {
+            file: SharedFile::new(file),
+            control_path: format!("{}.control", file_path),
+            next_page_id: AtomicU32::new(page_count.max(1)),
+            free_pages: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// 分配一个新页，返回其页号。优先复用被删除的页，否则在文件末尾分配。
+    /// 文件末尾的新页在第一次被写回之前都视为全零；复用的页上还是空闲页标记，
+    /// 调用者需要覆盖整页。
+    pub fn allocate_page(&self) -> PageId {
+        if let Some(page_id) = self.free_pages.lock().unwrap().pop() {
+            return page_id;
+        }
+        self.next_page_id.fetch_add(1, Ordering::SeqCst)
+    }
+
+    /// 归还一个不再使用的页，之后的 `allocate_page` 可以复用它。
+    /// 只修改内存中的空闲页表；要在重启后复用，页上必须已经写了空闲页标记，见
+    /// `BufferPoolManager::delete_page`。
+    pub fn deallocate_page(&self, page_id: PageId) {
+        self.free_pages.lock().unwrap().push(page_id);
+    }
+
+    /// 当前空闲页表的快照
+    pub fn free_pages(&self) -> Vec<PageId> {
+        self.free_pages.lock().unwrap().clone()
+    }
+
+    /// 启动时设置空闲页表，之前的内容被丢弃
+    pub fn set_free_pages(&self, page_ids: Vec<PageId>) {
+        *self.free_pages.lock().unwrap() = page_ids;
+    }
 
-        Ok(Self { file })
+    /// 已分配的页数（包括尚未写回磁盘的页）
+    pub fn num_pages(&self) -> PageId {
+        self.next_page_id.load(Ordering::SeqCst)
     }
 
     pub async fn read_page(
@@ -26,19 +137,261 @@ impl DiskManager {
         mut buffer: Vec<u8>,
     ) -> (io::Result<usize>, Vec<u8>) {
         let offset = page_id as u64 * PAGE_SIZE as u64;
-        buffer.resize(PAGE_SIZE, 0);
-
-        let (res, buf) = self.file.read_at(buffer, offset).await;
-        (res, buf)
+        let file = match self.file.core_file() {
+            Ok(file) => file,
+            Err(e) => {
+                buffer.resize(PAGE_SIZE, 0);
+                return (Err(e), buffer);
+            }
+        };
+        read_full_at(&file, buffer, offset, PAGE_SIZE).await
     }
 
+    /// 写回一个页，`buffer` 中只有前 `PAGE_SIZE` 字节被写入。
+    /// 短写会被续写完，写不完整时返回错误。
     pub async fn write_page(
         &self,
         page_id: PageId,
         buffer: Vec<u8>,
-    ) -> (io::Result<usize>, Vec<u8>) {
+    ) -> (io::Result<()>, Vec<u8>) {
         let offset = page_id as u64 * PAGE_SIZE as u64;
-        let (res, buffer) = self.file.write_at(buffer, offset).await;
-        (res, buffer)
+        if let Err(e) = check_page_buffer(page_id, &buffer) {
+            return (Err(e), buffer);
+        }
+        let file = match self.file.core_file() {
+            Ok(file) => file,
+            Err(e) => return (Err(e), buffer),
+        };
+        let (res, slice) = file.write_all_at(buffer.slice(..PAGE_SIZE), offset).await;
+        (res, slice.into_inner())
+    }
+
+    /// 批量读取多个页，返回的缓冲区与请求一一对应、顺序不变。
+    ///
+    /// 页号连续的请求合并成一次读取（最多 `MAX_COALESCED_PAGES` 页），所有读取在同一轮
+    /// 中提交给 io_uring。monoio 0.2 没有公开按偏移的 readv 和注册缓冲区，
+    /// 所以合并后的读取先读入一块连续的缓冲区，再拷贝到各页的缓冲区中。
+    pub async fn read_pages(&self, mut pages: Vec<(PageId, Vec<u8>)>) -> (io::Result<()>, Vec<(PageId, Vec<u8>)>) {
+        let file = match self.file.core_file() {
+            Ok(file) => file,
+            Err(e) => return (Err(e), pages),
+        };
+        let runs = coalesce(&pages);
+        let reads = runs.iter().map(|run| {
+            let first_page_id = pages[run[0]].0;
+            let buf = if run.len() == 1 {
+                std::mem::take(&mut pages[run[0]].1)
+            } else {
+                Vec::new()
+            };
+            Self::read_run(&file, first_page_id, run.len(), buf)
+        });
+        let results = futures::future::join_all(reads.collect::<Vec<_>>()).await;
+
+        let mut status = Ok(());
+        for (run, (res, buf)) in runs.iter().zip(results) {
+            if let Err(e) = res {
+                status = Err(e);
+            }
+            if run.len() == 1 {
+                pages[run[0]].1 = buf;
+            } else {
+                for (&i, chunk) in run.iter().zip(buf.chunks(PAGE_SIZE)) {
+                    pages[i].1.clear();
+                    pages[i].1.extend_from_slice(chunk);
+                }
+            }
+        }
+        (status, pages)
+    }
+
+    async fn read_run(file: &File, first_page_id: PageId, count: usize, buf: Vec<u8>) -> (io::Result<()>, Vec<u8>) {
+        let (res, buf) = read_full_at(file, buf, first_page_id as u64 * PAGE_SIZE as u64, count * PAGE_SIZE).await;
+        (res.map(|_| ()), buf)
+    }
+
+    /// 批量写回多个页，页号连续的页合并成一次写入，所有写入在同一轮中提交。
+    /// 和 `read_pages` 一样，因为没有按偏移的 writev，合并的页先拷贝到一块连续的缓冲区。
+    /// 和 `write_page` 一样，短写会被续写完。返回传入的缓冲区以便复用。
+    pub async fn write_pages(&self, pages: Vec<(PageId, Vec<u8>)>) -> (io::Result<()>, Vec<(PageId, Vec<u8>)>) {
+        if let Some(e) = pages.iter().find_map(|(page_id, buf)| check_page_buffer(*page_id, buf).err()) {
+            return (Err(e), pages);
+        }
+        let file = match self.file.core_file() {
+            Ok(file) => file,
+            Err(e) => return (Err(e), pages),
+        };
+        // Recovery may write pages past the end of the file; keep them allocated.
+        if let Some(last) = pages.iter().map(|&(page_id, _)| page_id).max() {
+            self.next_page_id.fetch_max(last.saturating_add(1), Ordering::SeqCst);
+        }
+        let runs = coalesce(&pages);
+        let writes = runs.iter().map(|run| {
+            let first_page_id = pages[run[0]].0;
+            let mut buf = Vec::with_capacity(run.len() * PAGE_SIZE);
+            for &i in run {
+                buf.extend_from_slice(&pages[i].1[..PAGE_SIZE]);
+            }
+            let file = &file;
+            async move {
+                let (res, _) = file.write_all_at(buf, first_page_id as u64 * PAGE_SIZE as u64).await;
+                res
+            }
+        });
+        let status = futures::future::join_all(writes.collect::<Vec<_>>())
+            .await
+            .into_iter()
+            .collect::<io::Result<Vec<()>>>()
+            .map(|_| ());
+        (status, pages)
+    }
+
+    /// 把所有已写入的页连同文件元数据刷到持久存储 (fsync)
+    pub async fn sync(&self) -> io::Result<()> {
+        self.file.core_file()?.sync_all().await
+    }
+
+    /// 只把数据（以及文件长度）刷到持久存储 (fdatasync)，省去不影响读取的元数据
+    pub async fn sync_data(&self) -> io::Result<()> {
+        self.file.core_file()?.sync_data().await
+    }
+
+    /// 读取控制文件的全部内容，文件不存在时返回 `None`
+    pub async fn read_control_file(&self) -> io::Result<Option<Vec<u8>>> {
+        let file = match File::open(&self.control_path).await {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let len = std::fs::metadata(&self.control_path)?.len() as usize;
+        let (res, buf) = file.read_exact_at(vec![0u8; len], 0).await;
+        res?;
+        Ok(Some(buf))
+    }
+
+    /// 覆盖写入控制文件并 fsync。内容需自带校验，写到一半的控制文件由读者发现。
+    pub async fn write_control_file(&self, data: Vec<u8>) -> io::Result<()> {
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&self.control_path)
+            .await?;
+        let (res, _) = file.write_all_at(data, 0).await;
+        res?;
+        file.sync_all().await
+    }
+}
+
+/// 从 `pos` 开始读取 `len` 字节到 `buf` 中，返回实际从文件读到的字节数。
+///
+/// 一次 `read_at` 可能只读到一部分，所以一直读到缓冲区读满或者真正到达文件末尾为止。
+/// 只有文件末尾之后的部分（尚未写回的新页）按全零处理。
+/// 写页用的缓冲区至少要有一整页，否则拒绝写入，而不是写出半页
+fn check_page_buffer(page_id: PageId, buf: &[u8]) -> io::Result<()> {
+    if buf.len() < PAGE_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("buffer for page {} holds {} bytes, expected {}", page_id, buf.len(), PAGE_SIZE),
+        ));
+    }
+    Ok(())
+}
+
+async fn read_full_at(file: &File, mut buf: Vec<u8>, pos: u64, len: usize) -> (io::Result<usize>, Vec<u8>) {
+    buf.clear();
+    buf.reserve(len);
+    let mut read = 0;
+    while read < len {
+        let (res, slice) = file.read_at(buf.slice_mut(read..len), pos + read as u64).await;
+        buf = slice.into_inner();
+        match res {
+            Ok(0) => break,
+            Ok(n) => read += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => {
+                buf.resize(len, 0);
+                return (Err(e), buf);
+            }
+        }
+    }
+    buf.resize(len, 0);
+    (Ok(read), buf)
+}
+
+/// 按页号排序后把连续的页分成若干组，每组是 `pages` 中的下标
+fn coalesce(pages: &[(PageId, Vec<u8>)]) -> Vec<Vec<usize>> {
+    let mut order: Vec<usize> = (0..pages.len()).collect();
+    order.sort_by_key(|&i| pages[i].0);
+
+    let mut runs: Vec<Vec<usize>> = Vec::new();
+    for i in order {
+        match runs.last_mut() {
+            Some(run)
+                if run.len() < MAX_COALESCED_PAGES
+                    && pages[*run.last().unwrap()].0.checked_add(1) == Some(pages[i].0) =>
+            {
+                run.push(i)
+            }
+            _ => runs.push(vec![i]),
+        }
+    }
+    runs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_coalesce_contiguous_pages() {
+        let pages: Vec<(PageId, Vec<u8>)> = [5, 1, 2, 9, 3, 6, 3]
+            .into_iter()
+            .map(|page_id| (page_id, Vec::new()))
+            .collect();
+        let runs: Vec<Vec<PageId>> = coalesce(&pages)
+            .into_iter()
+            .map(|run| run.into_iter().map(|i| pages[i].0).collect())
+            .collect();
+        assert_eq!(runs, vec![vec![1, 2, 3], vec![3], vec![5, 6], vec![9]]);
+
+        let long: Vec<(PageId, Vec<u8>)> = (0..MAX_COALESCED_PAGES as PageId + 1)
+            .map(|page_id| (page_id, Vec::new()))
+            .collect();
+        assert_eq!(coalesce(&long).len(), 2);
+    }
+
+    #[monoio::test]
+    async fn test_read_run_past_end_of_file() {
+        let path = std::env::temp_dir().join(format!("ringdb_disk_{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let dm = DiskManager::new(&path.to_string_lossy()).await.unwrap();
+        let (res, _) = dm.write_pages(vec![(1, vec![7u8; PAGE_SIZE])]).await;
+        res.unwrap();
+
+        // Page 1 is read in full; only page 2, past the end of the file, reads as zeros.
+        let requests = (1..3).map(|page_id| (page_id, Vec::new())).collect();
+        let (res, pages) = dm.read_pages(requests).await;
+        res.unwrap();
+        assert!(pages[0].1.iter().all(|&b| b == 7));
+        assert!(pages[1].1.len() == PAGE_SIZE && pages[1].1.iter().all(|&b| b == 0));
+        let (res, buf) = dm.read_page(1, Vec::new()).await;
+        assert_eq!(res.unwrap(), PAGE_SIZE);
+        assert!(buf.iter().all(|&b| b == 7));
+
+        // A short buffer is refused rather than written as part of a page, and a longer
+        // one writes exactly one page.
+        let (res, _) = dm.write_page(1, vec![1u8; 100]).await;
+        assert_eq!(res.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        let (res, _) = dm.write_pages(vec![(1, vec![1u8; PAGE_SIZE]), (2, Vec::new())]).await;
+        assert_eq!(res.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        let (res, _) = dm.write_page(1, vec![9u8; PAGE_SIZE + 1]).await;
+        res.unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 2 * PAGE_SIZE as u64);
+        let (res, buf) = dm.read_page(1, Vec::new()).await;
+        res.unwrap();
+        assert!(buf.iter().all(|&b| b == 9));
+
+        let _ = std::fs::remove_file(&path);
     }
 }
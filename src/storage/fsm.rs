@@ -0,0 +1,404 @@
+//! 空闲空间映射 (FSM)。
+//!
+//! 每个表有一条 FSM 页链，按加入的顺序记录表的所有数据页以及它们大约还剩多少空闲空间。
+//! 空闲空间按 `FSM_CATEGORY_SIZE` 字节一档压缩成一个字节的类别，每个 FSM 页内的类别组成
+//! 一棵最大值树（见 `Page::fsm_find`），插入时不用读数据页就能找到放得下元组的页。
+//!
+//! FSM 页链之上还有一层只在内存中的索引 (`FsmIndex`)：每个 FSM 页的树根，以及每个数据页
+//! 在 FSM 中的位置。索引在第一次使用时遍历一遍页链建立，之后由本模块中修改 FSM 的函数
+//! 在持有 FSM 页排他闩时同步维护。所以查找空闲空间时只读一个 FSM 页，按页号记录空闲空间
+//! 和找链尾都不需要遍历页链。
+//!
+//! FSM 页链同时是表的页目录，顺序扫描按它列出数据页。
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use crate::storage::{
+    buffer_pool::BufferPoolManager,
+    page::{INVALID_PAGE_ID, PAGE_SIZE, Page, PageId, PageType},
+};
+
+/// 一个空闲空间类别代表的字节数
+pub const FSM_CATEGORY_SIZE: usize = PAGE_SIZE / 256;
+
+/// 数据页在 FSM 中的位置
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FsmSlot {
+    pub fsm_page_id: PageId,
+    pub slot: usize,
+}
+
+/// 一张表的 FSM 在内存中的上层索引，每张表一个，重启后重新建立
+#[derive(Debug, Default)]
+pub struct FsmIndex {
+    state: Mutex<IndexState>,
+}
+
+#[derive(Debug, Default)]
+struct IndexState {
+    /// 尚未建立时为 `None`
+    loaded: Option<LoadedIndex>,
+    /// 每次修改 FSM 时递增。建立索引期间 FSM 被修改过时，建立的结果作废
+    changes: u64,
+}
+
+#[derive(Debug, Default)]
+struct LoadedIndex {
+    /// 链中各 FSM 页的页号及其最大类别（页内树的根），按链中顺序排列
+    fsm_pages: Vec<(PageId, u8)>,
+    /// 每个数据页在 FSM 中的位置
+    slots: HashMap<PageId, FsmSlot>,
+}
+
+impl LoadedIndex {
+    fn set_max(&mut self, fsm_page_id: PageId, max: u8) {
+        if let Some(entry) = self.fsm_pages.iter_mut().find(|(id, _)| *id == fsm_page_id) {
+            entry.1 = max;
+        }
+    }
+}
+
+impl FsmIndex {
+    /// 记录一次对 FSM 的修改。调用者必须仍持有被修改的 FSM 页的排他闩，
+    /// 这样各次修改写入索引的顺序与修改页的顺序一致。
+    fn modify(&self, f: impl FnOnce(&mut LoadedIndex)) {
+        let mut state = self.state.lock().unwrap();
+        state.changes += 1;
+        if let Some(loaded) = state.loaded.as_mut() {
+            f(loaded);
+        }
+    }
+
+    /// 在索引上执行 `f`，索引还没有建立时先遍历页链建立它
+    async fn with<R>(
+        &self,
+        bpm: &Arc<BufferPoolManager>,
+        first_fsm_page_id: PageId,
+        mut f: impl FnMut(&mut LoadedIndex) -> R,
+    ) -> Result<R, String> {
+        loop {
+            let changes = {
+                let mut state = self.state.lock().unwrap();
+                if let Some(loaded) = state.loaded.as_mut() {
+                    return Ok(f(loaded));
+                }
+                state.changes
+            };
+            let loaded = load_index(bpm, first_fsm_page_id).await?;
+            let mut state = self.state.lock().unwrap();
+            if state.loaded.is_none() && state.changes == changes {
+                state.loaded = Some(loaded);
+            }
+        }
+    }
+}
+
+async fn load_index(bpm: &Arc<BufferPoolManager>, first_fsm_page_id: PageId) -> Result<LoadedIndex, String> {
+    let mut index = LoadedIndex::default();
+    let mut fsm_page_id = first_fsm_page_id;
+    while fsm_page_id != INVALID_PAGE_ID {
+        let page = read_fsm_page(bpm, fsm_page_id).await?;
+        index.fsm_pages.push((fsm_page_id, page.fsm_max()));
+        for slot in 0..page.fsm_len() {
+            index.slots.insert(page.fsm_page_id(slot), FsmSlot { fsm_page_id, slot });
+        }
+        fsm_page_id = page.fsm_next();
+    }
+    Ok(index)
+}
+
+/// `free` 字节空闲空间对应的类别。向下取整，所以类别代表的空间一定可用。
+pub fn category_for(free: usize) -> u8 {
+    (free / FSM_CATEGORY_SIZE).min(u8::MAX as usize) as u8
+}
+
+/// 放得下 `len` 字节所需的最小类别
+fn required_category(len: usize) -> u8 {
+    len.div_ceil(FSM_CATEGORY_SIZE).min(u8::MAX as usize) as u8
+}
+
+fn decode_fsm_page(page_id: PageId, data: &[u8]) -> Result<Page, String> {
+    let page = Page::from_bytes(data[..PAGE_SIZE].try_into().unwrap())
+        .map_err(|e| format!("Page {} is corrupted: {}", page_id, e))?;
+    if page.header.page_type != PageType::FreeSpaceMap {
+        return Err(format!("Page {} is not a free space map page", page_id));
+    }
+    Ok(page)
+}
+
+async fn read_fsm_page(bpm: &Arc<BufferPoolManager>, page_id: PageId) -> Result<Page, String> {
+    decode_fsm_page(page_id, &bpm.fetch_page(page_id).await?)
+}
+
+/// 为新表分配一条空的 FSM 页链，返回链首页号
+pub async fn create(bpm: &Arc<BufferPoolManager>) -> Result<PageId, String> {
+    let mut page_write_guard = bpm
+        .new_page()
+        .await
+        .map_err(|e| format!("Failed to allocate free space map page: {}", e))?;
+    page_write_guard.copy_from_slice(&Page::new_free_space_map().to_bytes());
+    Ok(page_write_guard.page_id())
+}
+
+/// 找一个至少有 `len` 字节空闲空间的数据页。先在索引中找到树根够大的 FSM 页，再在页内的树中查找。
+/// FSM 中的信息可能已经过时：插入失败时调用者应当用页的实际空闲空间 `update` 之后重新查找。
+pub async fn find_page(
+    bpm: &Arc<BufferPoolManager>,
+    first_fsm_page_id: PageId,
+    index: &FsmIndex,
+    len: usize,
+) -> Result<Option<(PageId, FsmSlot)>, String> {
+    let category = required_category(len);
+    loop {
+        let candidate = index
+            .with(bpm, first_fsm_page_id, |loaded| {
+                loaded
+                    .fsm_pages
+                    .iter()
+                    .find(|&&(_, max)| max >= category)
+                    .map(|&(fsm_page_id, _)| fsm_page_id)
+            })
+            .await?;
+        let Some(fsm_page_id) = candidate else {
+            return Ok(None);
+        };
+        let page_guard = bpm.fetch_page(fsm_page_id).await?;
+        let page = decode_fsm_page(fsm_page_id, &page_guard)?;
+        if let Some(slot) = page.fsm_find(category) {
+            return Ok(Some((page.fsm_page_id(slot), FsmSlot { fsm_page_id, slot })));
+        }
+        // The index was built while this page was changing; the shared latch
+        // keeps writers out while it is corrected.
+        index.modify(|loaded| loaded.set_max(fsm_page_id, page.fsm_max()));
+    }
+}
+
+/// 把新分配的数据页加入表中，链尾的 FSM 页满了时在其后接一个新的 FSM 页
+pub async fn add_page(
+    bpm: &Arc<BufferPoolManager>,
+    first_fsm_page_id: PageId,
+    index: &FsmIndex,
+    page_id: PageId,
+    free: usize,
+) -> Result<FsmSlot, String> {
+    let mut fsm_page_id = index
+        .with(bpm, first_fsm_page_id, |loaded| loaded.fsm_pages.last().map(|&(id, _)| id))
+        .await?
+        .unwrap_or(first_fsm_page_id);
+    loop {
+        let mut page_write_guard = bpm.fetch_page_mut(fsm_page_id).await?;
+        let mut page = decode_fsm_page(fsm_page_id, &page_write_guard)?;
+        if page.fsm_next() != INVALID_PAGE_ID {
+            // Another insert extended the chain after we looked up its tail.
+            fsm_page_id = page.fsm_next();
+            continue;
+        }
+        if let Some(slot) = page.fsm_push(page_id, category_for(free)) {
+            page_write_guard.copy_from_slice(&page.to_bytes());
+            let fsm_slot = FsmSlot { fsm_page_id, slot };
+            index.modify(|loaded| {
+                loaded.set_max(fsm_page_id, page.fsm_max());
+                loaded.slots.insert(page_id, fsm_slot);
+            });
+            return Ok(fsm_slot);
+        }
+        let next = create(bpm).await?;
+        page.set_fsm_next(next);
+        page_write_guard.copy_from_slice(&page.to_bytes());
+        index.modify(|loaded| loaded.fsm_pages.push((next, 0)));
+        fsm_page_id = next;
+    }
+}
+
+/// 记录数据页现在的空闲空间。类别没有变化时不修改 FSM 页。
+pub async fn update(
+    bpm: &Arc<BufferPoolManager>,
+    index: &FsmIndex,
+    slot: FsmSlot,
+    free: usize,
+) -> Result<(), String> {
+    let category = category_for(free);
+    let current = read_fsm_page(bpm, slot.fsm_page_id).await?;
+    if current.fsm_category(slot.slot) == category {
+        return Ok(());
+    }
+
+    let mut page_write_guard = bpm.fetch_page_mut(slot.fsm_page_id).await?;
+    let mut page = decode_fsm_page(slot.fsm_page_id, &page_write_guard)?;
+    page.fsm_set(slot.slot, category);
+    page_write_guard.copy_from_slice(&page.to_bytes());
+    index.modify(|loaded| loaded.set_max(slot.fsm_page_id, page.fsm_max()));
+    Ok(())
+}
+
+/// 按页号记录数据页的空闲空间，用于删除和清理这类事先不知道 FSM 位置的修改。
+/// 位置从索引中查到；数据页不属于这张表时返回错误。
+pub async fn record_free_space(
+    bpm: &Arc<BufferPoolManager>,
+    first_fsm_page_id: PageId,
+    index: &FsmIndex,
+    page_id: PageId,
+    free: usize,
+) -> Result<(), String> {
+    let slot = index
+        .with(bpm, first_fsm_page_id, |loaded| loaded.slots.get(&page_id).copied())
+        .await?
+        .ok_or_else(|| format!("Page {} is not in the free space map", page_id))?;
+    update(bpm, index, slot, free).await
+}
+
+/// 按加入顺序列出表的所有数据页
+pub async fn table_pages(bpm: &Arc<BufferPoolManager>, first_fsm_page_id: PageId) -> Result<Vec<PageId>, String> {
+    Ok(table_slots(bpm, first_fsm_page_id)
+        .await?
+        .into_iter()
+        .map(|(page_id, _)| page_id)
+        .collect())
+}
+
+/// 按加入顺序列出表的所有数据页及其在 FSM 中的位置
+pub async fn table_slots(
+    bpm: &Arc<BufferPoolManager>,
+    first_fsm_page_id: PageId,
+) -> Result<Vec<(PageId, FsmSlot)>, String> {
+    let mut slots = Vec::new();
+    let mut fsm_page_id = first_fsm_page_id;
+    while fsm_page_id != INVALID_PAGE_ID {
+        let page = read_fsm_page(bpm, fsm_page_id).await?;
+        slots.extend((0..page.fsm_len()).map(|slot| (page.fsm_page_id(slot), FsmSlot { fsm_page_id, slot })));
+        fsm_page_id = page.fsm_next();
+    }
+    Ok(slots)
+}
+
+/// `page_id` 是否仍是表的数据页
+pub async fn contains_page(
+    bpm: &Arc<BufferPoolManager>,
+    first_fsm_page_id: PageId,
+    index: &FsmIndex,
+    page_id: PageId,
+) -> Result<bool, String> {
+    index
+        .with(bpm, first_fsm_page_id, |loaded| loaded.slots.contains_key(&page_id))
+        .await
+}
+
+/// 链尾 FSM 页和它的前一页（链尾就是链首时为 `None`）
+async fn last_fsm_page(
+    bpm: &Arc<BufferPoolManager>,
+    first_fsm_page_id: PageId,
+    index: &FsmIndex,
+) -> Result<(Option<PageId>, PageId), String> {
+    index
+        .with(bpm, first_fsm_page_id, |loaded| {
+            let len = loaded.fsm_pages.len();
+            let last = loaded.fsm_pages.last().map_or(first_fsm_page_id, |&(id, _)| id);
+            (len.checked_sub(2).map(|i| loaded.fsm_pages[i].0), last)
+        })
+        .await
+}
+
+/// 表中最后加入的数据页，表为空时返回 `None`。
+/// 除链首外的 FSM 页都不为空，所以只需看链尾。
+pub async fn last_page(
+    bpm: &Arc<BufferPoolManager>,
+    first_fsm_page_id: PageId,
+    index: &FsmIndex,
+) -> Result<Option<PageId>, String> {
+    let (_, fsm_page_id) = last_fsm_page(bpm, first_fsm_page_id, index).await?;
+    let page = read_fsm_page(bpm, fsm_page_id).await?;
+    Ok(page.fsm_len().checked_sub(1).map(|slot| page.fsm_page_id(slot)))
+}
+
+/// 把最后加入的数据页移出表，返回其页号。链尾的 FSM 页因此变空时把它也从链中摘掉并释放。
+/// 调用者负责保证没有并发的 `add_page`。
+pub async fn remove_last_page(
+    bpm: &Arc<BufferPoolManager>,
+    first_fsm_page_id: PageId,
+    index: &FsmIndex,
+) -> Result<Option<PageId>, String> {
+    let (prev, fsm_page_id) = last_fsm_page(bpm, first_fsm_page_id, index).await?;
+    let mut page_write_guard = bpm.fetch_page_mut(fsm_page_id).await?;
+    let mut page = decode_fsm_page(fsm_page_id, &page_write_guard)?;
+    let Some(page_id) = page.fsm_pop() else {
+        return Ok(None);
+    };
+    page_write_guard.copy_from_slice(&page.to_bytes());
+    index.modify(|loaded| {
+        loaded.slots.remove(&page_id);
+        loaded.set_max(fsm_page_id, page.fsm_max());
+    });
+    drop(page_write_guard);
+
+    if let (0, Some(prev)) = (page.fsm_len(), prev) {
+        let mut page_write_guard = bpm.fetch_page_mut(prev).await?;
+        let mut prev_page = decode_fsm_page(prev, &page_write_guard)?;
+        prev_page.set_fsm_next(INVALID_PAGE_ID);
+        page_write_guard.copy_from_slice(&prev_page.to_bytes());
+        index.modify(|loaded| {
+            loaded.fsm_pages.pop();
+        });
+        drop(page_write_guard);
+        bpm.delete_page(fsm_page_id).await?;
+    }
+    Ok(Some(page_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{disk::DiskManager, page::FSM_SLOTS};
+
+    #[monoio::test]
+    async fn test_find_add_and_update_across_fsm_pages() {
+        let path = std::env::temp_dir().join(format!("ringdb_fsm_{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let file = path.to_string_lossy().into_owned();
+        let bpm = BufferPoolManager::new(8, Arc::new(DiskManager::new(&file).await.unwrap()));
+
+        let fsm = create(&bpm).await.unwrap();
+        let index = FsmIndex::default();
+        assert_eq!(find_page(&bpm, fsm, &index, 100).await.unwrap(), None);
+
+        // More data pages than one FSM page can track; only the last one has room.
+        let count = FSM_SLOTS as PageId + 10;
+        for page_id in 1000..1000 + count {
+            let free = if page_id == 1000 + count - 1 { 4000 } else { 40 };
+            add_page(&bpm, fsm, &index, page_id, free).await.unwrap();
+        }
+        let pages = table_pages(&bpm, fsm).await.unwrap();
+        assert_eq!(pages, (1000..1000 + count).collect::<Vec<_>>());
+
+        let (page_id, slot) = find_page(&bpm, fsm, &index, 100).await.unwrap().unwrap();
+        assert_eq!(page_id, 1000 + count - 1);
+        assert_ne!(slot.fsm_page_id, fsm);
+        assert_eq!(find_page(&bpm, fsm, &index, 32).await.unwrap().unwrap().0, 1000);
+
+        update(&bpm, &index, slot, 10).await.unwrap();
+        assert_eq!(find_page(&bpm, fsm, &index, 100).await.unwrap(), None);
+        record_free_space(&bpm, fsm, &index, 1005, 8000).await.unwrap();
+        assert_eq!(find_page(&bpm, fsm, &index, 100).await.unwrap().unwrap().0, 1005);
+        assert!(record_free_space(&bpm, fsm, &index, 7, 8000).await.is_err());
+
+        // Removing the pages tracked by the second FSM page unlinks it again.
+        for page_id in (1000 + FSM_SLOTS as PageId - 1..1000 + count).rev() {
+            assert_eq!(last_page(&bpm, fsm, &index).await.unwrap(), Some(page_id));
+            assert_eq!(remove_last_page(&bpm, fsm, &index).await.unwrap(), Some(page_id));
+        }
+        assert_eq!(read_fsm_page(&bpm, fsm).await.unwrap().fsm_next(), INVALID_PAGE_ID);
+        assert_eq!(table_pages(&bpm, fsm).await.unwrap().len(), FSM_SLOTS - 1);
+        assert_eq!(find_page(&bpm, fsm, &index, 100).await.unwrap().unwrap().0, 1005);
+
+        // A fresh index, as after a restart, is rebuilt from the chain.
+        let rebuilt = FsmIndex::default();
+        assert_eq!(find_page(&bpm, fsm, &rebuilt, 100).await.unwrap().unwrap().0, 1005);
+        assert_eq!(
+            last_page(&bpm, fsm, &rebuilt).await.unwrap(),
+            Some(1000 + FSM_SLOTS as PageId - 2)
+        );
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
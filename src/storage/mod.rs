@@ -1,4 +1,10 @@
+pub mod access_strategy;
+pub mod bgwriter;
 pub mod buffer_pool;
 pub mod disk;
+pub mod fsm;
+pub mod overflow;
 pub mod page;
 pub mod replacer;
+pub mod vacuum;
+pub mod wal;
@@ -0,0 +1,128 @@
+//! 溢出页链 (TOAST)：把超过 `TOAST_THRESHOLD` 的元组切分后存放在页外，
+//! 表页中只保留一个指向链首的指针，扫描时再透明地拼接回来。
+use std::sync::Arc;
+
+use crate::storage::{
+    access_strategy::BufferAccessStrategy,
+    buffer_pool::BufferPoolManager,
+    page::{INVALID_PAGE_ID, OVERFLOW_CHUNK_SIZE, PAGE_SIZE, Page, PageId, PageType},
+};
+
+/// 将 `data` 写入新分配的溢出页链，返回链首页号。
+/// 失败时已分配的页都会被归还，不留下没有元组指向的半条链。
+pub async fn write_overflow_chain(
+    bpm: &Arc<BufferPoolManager>,
+    data: &[u8],
+) -> Result<PageId, String> {
+    let chunks: Vec<&[u8]> = data.chunks(OVERFLOW_CHUNK_SIZE).collect();
+    let page_ids: Vec<PageId> = chunks.iter().map(|_| bpm.disk_manager().allocate_page()).collect();
+    // 很长的链属于批量写入，只在私有环中复用帧
+    let strategy = BufferAccessStrategy::for_table(page_ids.len(), bpm.pool_size());
+
+    for (i, chunk) in chunks.iter().enumerate() {
+        let page_id = page_ids[i];
+        let next_page_id = page_ids.get(i + 1).copied().unwrap_or(INVALID_PAGE_ID);
+        let page = Page::new_overflow(next_page_id, chunk);
+
+        match bpm.fetch_page_mut_with(page_id, strategy.as_ref()).await {
+            Ok(mut page_write_guard) => page_write_guard.copy_from_slice(&page.to_bytes()),
+            Err(e) => {
+                for &page_id in &page_ids {
+                    // Without a frame for the free marker the page is only reusable until a restart.
+                    if bpm.delete_page(page_id).await.is_err() {
+                        bpm.disk_manager().deallocate_page(page_id);
+                    }
+                }
+                return Err(format!("Failed to fetch overflow page {}: {}", page_id, e));
+            }
+        }
+    }
+
+    Ok(page_ids.first().copied().unwrap_or(INVALID_PAGE_ID))
+}
+
+/// 沿溢出页链读取并拼接出长度为 `len` 的元组数据。
+pub async fn read_overflow_chain(
+    bpm: &Arc<BufferPoolManager>,
+    first_page_id: PageId,
+    len: u32,
+) -> Result<Vec<u8>, String> {
+    let mut data = Vec::with_capacity(len as usize);
+    let mut page_id = first_page_id;
+
+    while data.len() < len as usize {
+        if page_id == INVALID_PAGE_ID {
+            return Err(format!(
+                "Overflow chain starting at page {} ended after {} of {} bytes",
+                first_page_id,
+                data.len(),
+                len
+            ));
+        }
+
+        let page_guard = bpm.fetch_page(page_id).await?;
+        let page = Page::from_bytes(page_guard[..PAGE_SIZE].try_into().unwrap())
+            .map_err(|e| format!("Page {} is corrupted: {}", page_id, e))?;
+        if page.header.page_type != PageType::Overflow {
+            return Err(format!("Page {} is not an overflow page", page_id));
+        }
+
+        data.extend_from_slice(page.overflow_chunk());
+        page_id = page.overflow_next();
+    }
+
+    data.truncate(len as usize);
+    Ok(data)
+}
+
+/// 释放整条溢出页链，返回释放的页数。调用者需保证已经没有指向这条链的元组。
+pub async fn free_overflow_chain(bpm: &Arc<BufferPoolManager>, first_page_id: PageId) -> Result<usize, String> {
+    let mut freed = 0;
+    let mut page_id = first_page_id;
+    while page_id != INVALID_PAGE_ID {
+        let next_page_id = {
+            let page_guard = bpm.fetch_page(page_id).await?;
+            let page = Page::from_bytes(page_guard[..PAGE_SIZE].try_into().unwrap())
+                .map_err(|e| format!("Page {} is corrupted: {}", page_id, e))?;
+            if page.header.page_type != PageType::Overflow {
+                return Err(format!("Page {} is not an overflow page", page_id));
+            }
+            page.overflow_next()
+        };
+        bpm.delete_page(page_id).await?;
+        freed += 1;
+        page_id = next_page_id;
+    }
+    Ok(freed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::disk::DiskManager;
+
+    #[monoio::test(timer_enabled = true)]
+    async fn test_failed_chain_write_returns_its_pages() {
+        let path = std::env::temp_dir().join(format!("ringdb_overflow_{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let dm = Arc::new(DiskManager::new(&path.to_string_lossy()).await.unwrap());
+        let bpm = BufferPoolManager::new(2, dm.clone());
+        let data = vec![5u8; 2 * OVERFLOW_CHUNK_SIZE];
+
+        // With every frame pinned the first chunk has nowhere to go.
+        let pinned = [bpm.new_page().await.unwrap(), bpm.new_page().await.unwrap()];
+        assert!(write_overflow_chain(&bpm, &data).await.is_err());
+        let mut free_pages = dm.free_pages();
+        free_pages.sort();
+        assert_eq!(free_pages, [3, 4]);
+        drop(pinned);
+
+        // The pages are handed out again for the next chain.
+        let first_page_id = write_overflow_chain(&bpm, &data).await.unwrap();
+        assert!(dm.free_pages().is_empty());
+        assert_eq!(dm.num_pages(), 5);
+        assert_eq!(read_overflow_chain(&bpm, first_page_id, data.len() as u32).await.unwrap(), data);
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -1,15 +1,104 @@
-use bincode::{Decode, Encode, config, decode_from_slice, encode_into_slice};
+use bincode::{
+    Decode, Encode,
+    config::{self, Configuration, Fixint, LittleEndian, NoLimit},
+    decode_from_slice, encode_into_slice,
+};
 
 pub const PAGE_SIZE: usize = 8192; // 8KB
 pub type PageId = u32;
 
+/// 页格式版本，记录在控制文件中。页的布局发生不兼容的变化时递增。
+/// 版本 1：带 CRC32C 校验和与页类型的 10 字节定长页头。
+pub const PAGE_FORMAT_VERSION: u32 = 1;
+
+/// 无效页号，用于表示空帧或链表末尾
+pub const INVALID_PAGE_ID: PageId = PageId::MAX;
+
+/// 超过该大小的元组会被移出页外，存放在溢出页链中 (TOAST)
+pub const TOAST_THRESHOLD: usize = PAGE_SIZE / 4;
+
+/// 槽位长度字段的最高位：置位表示该槽位存放的是溢出指针而不是元组本身
+const OVERFLOW_FLAG: u16 = 0x8000;
+/// 槽位长度字段的次高位：置位表示元组已被删除，等待 VACUUM 回收空间
+const DEAD_FLAG: u16 = 0x4000;
+/// 槽位长度字段中表示长度的位
+const LEN_MASK: u16 = !(OVERFLOW_FLAG | DEAD_FLAG);
+/// 溢出指针的大小：首个溢出页号 (u32) + 元组总长度 (u32)
+pub const OVERFLOW_POINTER_SIZE: usize = 8;
+
+/// 溢出页的页内头：下一页页号 (u32) + 本页数据长度 (u16)
+const OVERFLOW_HEADER_SIZE: usize = 6;
+/// 每个溢出页能容纳的数据量
+pub const OVERFLOW_CHUNK_SIZE: usize = PAGE_SIZE - HEADER_SIZE - OVERFLOW_HEADER_SIZE;
+
+/// 每个槽位在元组数据之外占用的空间（长度字段）
+pub const SLOT_OVERHEAD: usize = 2;
+
+/// 一个 FSM 页最多跟踪的数据页数，必须是 2 的幂
+pub const FSM_SLOTS: usize = 1024;
+/// FSM 页的页内头：下一个 FSM 页号 (u32) + 已使用的槽位数 (u16)
+const FSM_HEADER_SIZE: usize = 6;
+/// FSM 页中数据页页号数组的起始位置
+const FSM_PAGE_IDS: usize = HEADER_SIZE + FSM_HEADER_SIZE;
+/// FSM 页中最大值树的起始位置。节点 1 是根，节点 i 的子节点是 2i 和 2i+1，
+/// 叶子 `FSM_SLOTS + slot` 是对应数据页的空闲空间类别。
+const FSM_TREE: usize = FSM_PAGE_IDS + 4 * FSM_SLOTS;
+const _: () = assert!(FSM_TREE + 2 * FSM_SLOTS <= PAGE_SIZE);
+
+/// 页的类型。全零的页会被解码为 `Table`，因此新页默认是空的表页。
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum PageType {
+    Table,
+    Overflow,
+    FreeSpaceMap,
+    /// 已归还给空闲页表的页，重启时据此重建空闲页表
+    Free,
+}
+
 /// 页头，存储页的元数据
 #[derive(Debug, Copy, Clone, Encode, Decode)]
 pub struct PageHeader {
+    /// 页内容（不含本字段）的 CRC32C，由 BufferPoolManager 在写盘前填写
+    pub checksum: u32,
     pub tuple_count: u16,
+    pub page_type: PageType,
+}
+
+// 4 bytes checksum + 2 bytes tuple_count + 4 bytes page_type tag. Every field is
+// fixed-width, so the header never grows into the first slot.
+const HEADER_SIZE: usize = 10;
+const CHECKSUM_SIZE: usize = 4;
+
+/// 页头使用定长整数编码，保证各字段在页内的位置固定
+const HEADER_CONFIG: Configuration<LittleEndian, Fixint, NoLimit> =
+    config::standard().with_fixed_int_encoding();
+
+/// 页内容损坏时返回的错误
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PageError {
+    ChecksumMismatch { stored: u32, computed: u32 },
+    InvalidHeader(String),
+}
+
+impl std::fmt::Display for PageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PageError::ChecksumMismatch { stored, computed } => write!(
+                f,
+                "checksum mismatch (stored {:#010x}, computed {:#010x})",
+                stored, computed
+            ),
+            PageError::InvalidHeader(e) => write!(f, "invalid page header: {}", e),
+        }
+    }
 }
 
-const HEADER_SIZE: usize = 2; // 2 bytes for u16 tuple_count
+/// `get_tuple` 返回的元组数据：要么直接存放在页内，要么指向一条溢出页链
+#[derive(Debug, PartialEq, Eq)]
+pub enum TupleData<'a> {
+    Inline(&'a [u8]),
+    Overflow { first_page_id: PageId, len: u32 },
+}
 
 /// 一个磁盘页的内存表示
 pub struct Page {
@@ -19,61 +108,475 @@ pub struct Page {
 
 impl Page {
     /// 从原始字节数组中加载一个页
-    pub fn from_bytes(bytes: [u8; PAGE_SIZE]) -> Self {
-        let (header, _) = decode_from_slice(&bytes[0..HEADER_SIZE], config::standard()).unwrap();
-        Self {
+    pub fn from_bytes(bytes: [u8; PAGE_SIZE]) -> Result<Self, PageError> {
+        let (header, _) = decode_from_slice(&bytes[0..HEADER_SIZE], HEADER_CONFIG)
+            .map_err(|e| PageError::InvalidHeader(e.to_string()))?;
+        Ok(Self {
             header,
             data: bytes,
-        }
+        })
     }
 
     /// 将页内容序列化以便写入磁盘
     pub fn to_bytes(&self) -> [u8; PAGE_SIZE] {
         let mut bytes = self.data;
-        encode_into_slice(self.header, &mut bytes[0..HEADER_SIZE], config::standard()).unwrap();
+        encode_into_slice(self.header, &mut bytes[0..HEADER_SIZE], HEADER_CONFIG).unwrap();
         bytes
     }
 
+    /// 计算页内容的校验和（跳过校验和字段本身）
+    pub fn compute_checksum(bytes: &[u8]) -> u32 {
+        crc32c::crc32c(&bytes[CHECKSUM_SIZE..PAGE_SIZE])
+    }
+
+    /// 在写盘前重新计算并填写校验和
+    pub fn update_checksum(bytes: &mut [u8]) {
+        let checksum = Self::compute_checksum(bytes);
+        bytes[0..CHECKSUM_SIZE].copy_from_slice(&checksum.to_le_bytes());
+    }
+
+    /// 检查从磁盘读到的页是否完好。从未写过的全零页视为合法的空页。
+    pub fn verify(bytes: &[u8]) -> Result<(), PageError> {
+        if bytes.iter().all(|&b| b == 0) {
+            return Ok(());
+        }
+        let stored = u32::from_le_bytes(bytes[0..CHECKSUM_SIZE].try_into().unwrap());
+        let computed = Self::compute_checksum(bytes);
+        if stored != computed {
+            return Err(PageError::ChecksumMismatch { stored, computed });
+        }
+        decode_from_slice::<PageHeader, _>(&bytes[0..HEADER_SIZE], HEADER_CONFIG)
+            .map(|_| ())
+            .map_err(|e| PageError::InvalidHeader(e.to_string()))
+    }
+
     /// 尝试在页中插入一个元组，返回元组的槽位ID。
     /// 这是一个非常简单的实现，仅在末尾追加数据。
     pub fn insert_tuple(&mut self, tuple_data: &[u8]) -> Option<u16> {
+        if tuple_data.len() > LEN_MASK as usize {
+            return None;
+        }
+        self.append_slot(tuple_data.len() as u16, tuple_data)
+    }
+
+    /// 插入一个指向溢出页链的槽位，用于存放超过 `TOAST_THRESHOLD` 的元组。
+    pub fn insert_overflow_pointer(&mut self, first_page_id: PageId, len: u32) -> Option<u16> {
+        let mut pointer = [0u8; OVERFLOW_POINTER_SIZE];
+        pointer[0..4].copy_from_slice(&first_page_id.to_le_bytes());
+        pointer[4..8].copy_from_slice(&len.to_le_bytes());
+        self.append_slot(OVERFLOW_FLAG | OVERFLOW_POINTER_SIZE as u16, &pointer)
+    }
+
+    /// 表页中剩余的空闲字节数。插入一个元组需要 `SLOT_OVERHEAD` 加上元组本身的长度。
+    pub fn free_space(&self) -> usize {
+        PAGE_SIZE - self.slots_end()
+    }
+
+    /// 最后一个槽位之后的偏移
+    fn slots_end(&self) -> usize {
         let mut offset = HEADER_SIZE;
         for _ in 0..self.header.tuple_count {
             // Read tuple length (u16, 2 bytes) to find the start of the next one
-            let tuple_len =
-                u16::from_le_bytes(self.data[offset..offset + 2].try_into().unwrap()) as usize;
-            offset += 2 + tuple_len;
+            offset += 2 + self.slot_len(offset);
         }
+        offset
+    }
+
+    fn append_slot(&mut self, len_word: u16, payload: &[u8]) -> Option<u16> {
+        let offset = self.slots_end();
 
         // Check if there is enough space (2 bytes for length + data)
-        if offset + 2 + tuple_data.len() > PAGE_SIZE {
+        if offset + 2 + payload.len() > PAGE_SIZE {
             return None;
         }
 
         // Write length
-        let len_bytes = (tuple_data.len() as u16).to_le_bytes();
-        self.data[offset..offset + 2].copy_from_slice(&len_bytes);
+        self.data[offset..offset + 2].copy_from_slice(&len_word.to_le_bytes());
         // Write data
-        self.data[offset + 2..offset + 2 + tuple_data.len()].copy_from_slice(tuple_data);
+        self.data[offset + 2..offset + 2 + payload.len()].copy_from_slice(payload);
 
         let slot_id = self.header.tuple_count;
         self.header.tuple_count += 1;
         Some(slot_id)
     }
 
-    /// 根据槽位ID获取元组的数据切片。
-    pub fn get_tuple(&self, slot_id: u16) -> Option<&[u8]> {
+    /// 根据槽位ID获取元组的数据。
+    /// 已删除的元组返回 `None`
+    pub fn get_tuple(&self, slot_id: u16) -> Option<TupleData<'_>> {
         if slot_id >= self.header.tuple_count {
             return None;
         }
+        let offset = self.slot_offset(slot_id);
+        let len_word = self.len_word(offset);
+        if len_word & DEAD_FLAG != 0 {
+            return None;
+        }
+        Some(Self::decode_slot(len_word, self.slot_payload(offset)))
+    }
+
+    /// 把元组标记为已删除。它占用的空间在 VACUUM 压缩页之前不会被复用。
+    /// 槽位不存在或已经删除时返回 false。
+    pub fn delete_tuple(&mut self, slot_id: u16) -> bool {
+        if slot_id >= self.header.tuple_count {
+            return false;
+        }
+        let offset = self.slot_offset(slot_id);
+        let len_word = self.len_word(offset);
+        if len_word & DEAD_FLAG != 0 {
+            return false;
+        }
+        self.data[offset..offset + 2].copy_from_slice(&(len_word | DEAD_FLAG).to_le_bytes());
+        true
+    }
+
+    /// 已删除、尚未回收的元组个数
+    pub fn dead_tuple_count(&self) -> usize {
+        let mut offset = HEADER_SIZE;
+        let mut dead = 0;
+        for _ in 0..self.header.tuple_count {
+            if self.len_word(offset) & DEAD_FLAG != 0 {
+                dead += 1;
+            }
+            offset += 2 + self.slot_len(offset);
+        }
+        dead
+    }
+
+    /// 移除所有已删除的元组并把剩下的元组紧凑地排在一起，之后的槽位号会改变。
+    /// 返回回收的字节数，以及被移除的溢出指针所指向的溢出链（由调用者释放）。
+    pub fn compact(&mut self) -> (usize, Vec<PageId>) {
+        let end = self.slots_end();
+        let mut read = HEADER_SIZE;
+        let mut write = HEADER_SIZE;
+        let mut live = 0;
+        let mut overflow_chains = Vec::new();
+        for _ in 0..self.header.tuple_count {
+            let len_word = self.len_word(read);
+            let slot_size = 2 + self.slot_len(read);
+            if len_word & DEAD_FLAG == 0 {
+                self.data.copy_within(read..read + slot_size, write);
+                write += slot_size;
+                live += 1;
+            } else if let TupleData::Overflow { first_page_id, .. } =
+                Self::decode_slot(len_word & !DEAD_FLAG, self.slot_payload(read))
+            {
+                overflow_chains.push(first_page_id);
+            }
+            read += slot_size;
+        }
+        self.data[write..end].fill(0);
+        self.header.tuple_count = live;
+        (end - write, overflow_chains)
+    }
+
+    fn slot_offset(&self, slot_id: u16) -> usize {
         let mut offset = HEADER_SIZE;
         for _ in 0..slot_id {
-            let tuple_len =
-                u16::from_le_bytes(self.data[offset..offset + 2].try_into().unwrap()) as usize;
-            offset += 2 + tuple_len;
+            offset += 2 + self.slot_len(offset);
+        }
+        offset
+    }
+
+    fn len_word(&self, offset: usize) -> u16 {
+        u16::from_le_bytes(self.data[offset..offset + 2].try_into().unwrap())
+    }
+
+    fn slot_len(&self, offset: usize) -> usize {
+        (self.len_word(offset) & LEN_MASK) as usize
+    }
+
+    fn slot_payload(&self, offset: usize) -> &[u8] {
+        &self.data[offset + 2..offset + 2 + self.slot_len(offset)]
+    }
+
+    fn decode_slot(len_word: u16, payload: &[u8]) -> TupleData<'_> {
+        if len_word & OVERFLOW_FLAG != 0 {
+            TupleData::Overflow {
+                first_page_id: PageId::from_le_bytes(payload[0..4].try_into().unwrap()),
+                len: u32::from_le_bytes(payload[4..8].try_into().unwrap()),
+            }
+        } else {
+            TupleData::Inline(payload)
+        }
+    }
+
+    /// 构造一个溢出页，`chunk` 不能超过 `OVERFLOW_CHUNK_SIZE`。
+    pub fn new_overflow(next_page_id: PageId, chunk: &[u8]) -> Self {
+        assert!(chunk.len() <= OVERFLOW_CHUNK_SIZE);
+        let mut data = [0u8; PAGE_SIZE];
+        let body = HEADER_SIZE;
+        data[body..body + 4].copy_from_slice(&next_page_id.to_le_bytes());
+        data[body + 4..body + 6].copy_from_slice(&(chunk.len() as u16).to_le_bytes());
+        data[body + OVERFLOW_HEADER_SIZE..body + OVERFLOW_HEADER_SIZE + chunk.len()]
+            .copy_from_slice(chunk);
+        Self {
+            header: PageHeader {
+                checksum: 0,
+                tuple_count: 0,
+                page_type: PageType::Overflow,
+            },
+            data,
+        }
+    }
+
+    /// 溢出链中的下一页，`INVALID_PAGE_ID` 表示链表结束。
+    pub fn overflow_next(&self) -> PageId {
+        PageId::from_le_bytes(self.data[HEADER_SIZE..HEADER_SIZE + 4].try_into().unwrap())
+    }
+
+    /// 本溢出页中保存的数据片段。
+    pub fn overflow_chunk(&self) -> &[u8] {
+        let body = HEADER_SIZE;
+        let len = u16::from_le_bytes(self.data[body + 4..body + 6].try_into().unwrap()) as usize;
+        &self.data[body + OVERFLOW_HEADER_SIZE..body + OVERFLOW_HEADER_SIZE + len]
+    }
+
+    /// 构造一个空闲页标记
+    pub fn new_free() -> Self {
+        Self {
+            header: PageHeader {
+                checksum: 0,
+                tuple_count: 0,
+                page_type: PageType::Free,
+            },
+            data: [0u8; PAGE_SIZE],
+        }
+    }
+
+    /// 构造一个空的 FSM 页
+    pub fn new_free_space_map() -> Self {
+        let mut page = Self {
+            header: PageHeader {
+                checksum: 0,
+                tuple_count: 0,
+                page_type: PageType::FreeSpaceMap,
+            },
+            data: [0u8; PAGE_SIZE],
+        };
+        page.set_fsm_next(INVALID_PAGE_ID);
+        page
+    }
+
+    /// FSM 链中的下一页，`INVALID_PAGE_ID` 表示链表结束。
+    pub fn fsm_next(&self) -> PageId {
+        PageId::from_le_bytes(self.data[HEADER_SIZE..HEADER_SIZE + 4].try_into().unwrap())
+    }
+
+    pub fn set_fsm_next(&mut self, next_page_id: PageId) {
+        self.data[HEADER_SIZE..HEADER_SIZE + 4].copy_from_slice(&next_page_id.to_le_bytes());
+    }
+
+    /// 本 FSM 页中已使用的槽位数
+    pub fn fsm_len(&self) -> usize {
+        u16::from_le_bytes(self.data[HEADER_SIZE + 4..HEADER_SIZE + 6].try_into().unwrap()) as usize
+    }
+
+    /// 槽位 `slot` 跟踪的数据页
+    pub fn fsm_page_id(&self, slot: usize) -> PageId {
+        let offset = FSM_PAGE_IDS + 4 * slot;
+        PageId::from_le_bytes(self.data[offset..offset + 4].try_into().unwrap())
+    }
+
+    /// 槽位 `slot` 中数据页的空闲空间类别
+    pub fn fsm_category(&self, slot: usize) -> u8 {
+        self.data[FSM_TREE + FSM_SLOTS + slot]
+    }
+
+    /// 本页中所有数据页的最大空闲空间类别（树根）
+    pub fn fsm_max(&self) -> u8 {
+        self.data[FSM_TREE + 1]
+    }
+
+    /// 开始跟踪一个数据页，本页已满时返回 `None`
+    pub fn fsm_push(&mut self, page_id: PageId, category: u8) -> Option<usize> {
+        let slot = self.fsm_len();
+        if slot == FSM_SLOTS {
+            return None;
+        }
+        let offset = FSM_PAGE_IDS + 4 * slot;
+        self.data[offset..offset + 4].copy_from_slice(&page_id.to_le_bytes());
+        self.data[HEADER_SIZE + 4..HEADER_SIZE + 6].copy_from_slice(&(slot as u16 + 1).to_le_bytes());
+        self.fsm_set(slot, category);
+        Some(slot)
+    }
+
+    /// 停止跟踪最后一个数据页并返回其页号，本页为空时返回 `None`
+    pub fn fsm_pop(&mut self) -> Option<PageId> {
+        let slot = self.fsm_len().checked_sub(1)?;
+        let page_id = self.fsm_page_id(slot);
+        self.fsm_set(slot, 0);
+        let offset = FSM_PAGE_IDS + 4 * slot;
+        self.data[offset..offset + 4].fill(0);
+        self.data[HEADER_SIZE + 4..HEADER_SIZE + 6].copy_from_slice(&(slot as u16).to_le_bytes());
+        Some(page_id)
+    }
+
+    /// 更新槽位 `slot` 的类别，并沿路径更新到树根
+    pub fn fsm_set(&mut self, slot: usize, category: u8) {
+        let mut node = FSM_SLOTS + slot;
+        self.data[FSM_TREE + node] = category;
+        while node > 1 {
+            node /= 2;
+            let max = self.data[FSM_TREE + 2 * node].max(self.data[FSM_TREE + 2 * node + 1]);
+            self.data[FSM_TREE + node] = max;
         }
-        let tuple_len =
-            u16::from_le_bytes(self.data[offset..offset + 2].try_into().unwrap()) as usize;
-        Some(&self.data[offset + 2..offset + 2 + tuple_len])
+    }
+
+    /// 找一个类别不小于 `category`（至少为 1）的槽位，从树根向下只访问 log2(FSM_SLOTS) 个节点
+    pub fn fsm_find(&self, category: u8) -> Option<usize> {
+        // Unused slots are category 0, so they never match.
+        let category = category.max(1);
+        if self.fsm_max() < category {
+            return None;
+        }
+        let mut node = 1;
+        while node < FSM_SLOTS {
+            node = if self.data[FSM_TREE + 2 * node] >= category {
+                2 * node
+            } else {
+                2 * node + 1
+            };
+        }
+        Some(node - FSM_SLOTS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inline_and_overflow_slots() {
+        let mut page = Page::from_bytes([0; PAGE_SIZE]).unwrap();
+        assert_eq!(page.header.page_type, PageType::Table);
+
+        assert_eq!(page.insert_tuple(b"hello"), Some(0));
+        assert_eq!(page.insert_overflow_pointer(7, 100_000), Some(1));
+        assert_eq!(page.insert_tuple(b"world"), Some(2));
+
+        let page = Page::from_bytes(page.to_bytes()).unwrap();
+        assert_eq!(page.get_tuple(0), Some(TupleData::Inline(b"hello")));
+        assert_eq!(
+            page.get_tuple(1),
+            Some(TupleData::Overflow {
+                first_page_id: 7,
+                len: 100_000
+            })
+        );
+        assert_eq!(page.get_tuple(2), Some(TupleData::Inline(b"world")));
+        assert_eq!(page.get_tuple(3), None);
+    }
+
+    #[test]
+    fn test_header_width_does_not_depend_on_tuple_count() {
+        // A varint tuple_count grows past one byte at 251 tuples and used to
+        // overwrite the first slot.
+        let header = PageHeader {
+            checksum: u32::MAX,
+            tuple_count: u16::MAX,
+            page_type: PageType::FreeSpaceMap,
+        };
+        assert_eq!(bincode::encode_to_vec(header, HEADER_CONFIG).unwrap().len(), HEADER_SIZE);
+
+        let mut page = Page::from_bytes([0; PAGE_SIZE]).unwrap();
+        for i in 0..300u16 {
+            assert_eq!(page.insert_tuple(&[i as u8]), Some(i));
+        }
+        let page = Page::from_bytes(page.to_bytes()).unwrap();
+        assert_eq!(page.header.tuple_count, 300);
+        for i in 0..300u16 {
+            assert_eq!(page.get_tuple(i), Some(TupleData::Inline(&[i as u8][..])));
+        }
+    }
+
+    #[test]
+    fn test_overflow_page_roundtrip() {
+        let chunk = vec![0xAB; OVERFLOW_CHUNK_SIZE];
+        let page = Page::from_bytes(Page::new_overflow(3, &chunk).to_bytes()).unwrap();
+        assert_eq!(page.header.page_type, PageType::Overflow);
+        assert_eq!(page.header.tuple_count, 0);
+        assert_eq!(page.overflow_next(), 3);
+        assert_eq!(page.overflow_chunk(), &chunk[..]);
+    }
+
+    #[test]
+    fn test_delete_and_compact() {
+        let mut page = Page::from_bytes([0; PAGE_SIZE]).unwrap();
+        page.insert_tuple(b"alpha").unwrap();
+        page.insert_overflow_pointer(7, 100_000).unwrap();
+        page.insert_tuple(b"beta").unwrap();
+        page.insert_tuple(b"gamma").unwrap();
+        let free_before = page.free_space();
+
+        assert!(page.delete_tuple(1));
+        assert!(page.delete_tuple(2));
+        assert!(!page.delete_tuple(2));
+        assert!(!page.delete_tuple(9));
+        assert_eq!(page.get_tuple(1), None);
+        assert_eq!(page.dead_tuple_count(), 2);
+        assert_eq!(page.free_space(), free_before);
+
+        let (reclaimed, chains) = page.compact();
+        assert_eq!(reclaimed, (2 + OVERFLOW_POINTER_SIZE) + (2 + 4));
+        assert_eq!(chains, vec![7]);
+        assert_eq!(page.free_space(), free_before + reclaimed);
+        assert_eq!(page.header.tuple_count, 2);
+        assert_eq!(page.dead_tuple_count(), 0);
+        assert_eq!(page.get_tuple(0), Some(TupleData::Inline(b"alpha")));
+        assert_eq!(page.get_tuple(1), Some(TupleData::Inline(b"gamma")));
+    }
+
+    #[test]
+    fn test_free_space_map_tree() {
+        let mut page = Page::new_free_space_map();
+        assert_eq!(page.fsm_find(1), None);
+        for (i, category) in [3u8, 0, 9, 5].into_iter().enumerate() {
+            assert_eq!(page.fsm_push(100 + i as PageId, category), Some(i));
+        }
+
+        let mut page = Page::from_bytes(page.to_bytes()).unwrap();
+        assert_eq!(page.header.page_type, PageType::FreeSpaceMap);
+        assert_eq!(page.fsm_next(), INVALID_PAGE_ID);
+        assert_eq!(page.fsm_len(), 4);
+        assert_eq!(page.fsm_max(), 9);
+        assert_eq!(page.fsm_find(4), Some(2));
+        assert_eq!(page.fsm_page_id(2), 102);
+        assert_eq!(page.fsm_find(10), None);
+
+        page.fsm_set(2, 1);
+        assert_eq!(page.fsm_max(), 5);
+        assert_eq!(page.fsm_find(4), Some(3));
+        assert_eq!(page.fsm_find(2), Some(0));
+
+        for i in 4..FSM_SLOTS {
+            assert!(page.fsm_push(i as PageId, 0).is_some());
+        }
+        assert_eq!(page.fsm_push(0, 0), None);
+    }
+
+    #[test]
+    fn test_checksum_detects_corruption() {
+        let mut page = Page::from_bytes([0; PAGE_SIZE]).unwrap();
+        page.insert_tuple(b"hello").unwrap();
+        let mut bytes = page.to_bytes();
+        Page::update_checksum(&mut bytes);
+        assert_eq!(Page::verify(&bytes), Ok(()));
+        assert_eq!(Page::verify(&[0; PAGE_SIZE]), Ok(()));
+
+        bytes[100] ^= 0x01;
+        assert!(matches!(
+            Page::verify(&bytes),
+            Err(PageError::ChecksumMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_garbage_header_is_an_error() {
+        let mut bytes = [0u8; PAGE_SIZE];
+        bytes[6] = 0xEE; // page_type tag
+        assert!(matches!(
+            Page::from_bytes(bytes),
+            Err(PageError::InvalidHeader(_))
+        ));
     }
 }
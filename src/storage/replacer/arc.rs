@@ -0,0 +1,226 @@
+use std::{
+    collections::{HashSet, VecDeque},
+    sync::Mutex,
+};
+
+use crate::storage::{page::PageId, replacer::Replacer};
+
+/// Adaptive Replacement Cache。
+///
+/// T1 保存只被访问过一次的页，T2 保存至少访问过两次的页，B1/B2 记录最近
+/// 从 T1/T2 淘汰的页号（幽灵项）。当被淘汰的页很快又被访问时，目标大小 `p`
+/// 会向对应的一侧调整，从而在扫描型和热点型负载之间自适应。
+pub struct ArcReplacer {
+    inner: Mutex<ArcReplacerInner>,
+}
+struct ArcReplacerInner {
+    capacity: usize,
+    /// T1 的目标大小
+    p: usize,
+    pin_counts: Vec<usize>,
+    frame_pages: Vec<Option<PageId>>,
+    /// 没有装入任何页的帧
+    free: VecDeque<usize>,
+    /// 以下队列的前端为 LRU 端
+    t1: VecDeque<usize>,
+    t2: VecDeque<usize>,
+    b1: VecDeque<PageId>,
+    b2: VecDeque<PageId>,
+    ghosts: HashSet<PageId>,
+}
+
+impl ArcReplacer {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            inner: Mutex::new(ArcReplacerInner {
+                capacity,
+                p: 0,
+                pin_counts: vec![0; capacity],
+                frame_pages: vec![None; capacity],
+                free: (0..capacity).collect(),
+                t1: VecDeque::new(),
+                t2: VecDeque::new(),
+                b1: VecDeque::new(),
+                b2: VecDeque::new(),
+                ghosts: HashSet::new(),
+            }),
+        }
+    }
+}
+
+impl ArcReplacerInner {
+    /// 从 `list` 的 LRU 端取出第一个未被 pin 的帧
+    fn take_unpinned(list: &mut VecDeque<usize>, pin_counts: &[usize]) -> Option<usize> {
+        let pos = list.iter().position(|&f| pin_counts[f] == 0)?;
+        list.remove(pos)
+    }
+
+    fn remove_ghost(list: &mut VecDeque<PageId>, page_id: PageId) -> bool {
+        match list.iter().position(|&p| p == page_id) {
+            Some(pos) => {
+                list.remove(pos);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn trim_ghosts(&mut self) {
+        while self.t1.len() + self.b1.len() > self.capacity {
+            match self.b1.pop_front() {
+                Some(page_id) => self.ghosts.remove(&page_id),
+                None => break,
+            };
+        }
+        while self.t1.len() + self.t2.len() + self.b1.len() + self.b2.len() > 2 * self.capacity {
+            match self.b2.pop_front() {
+                Some(page_id) => self.ghosts.remove(&page_id),
+                None => break,
+            };
+        }
+    }
+}
+
+impl Replacer for ArcReplacer {
+    fn victim(&self) -> Option<usize> {
+        let mut guard = self.inner.lock().unwrap();
+        let inner = &mut *guard;
+
+        let frame_id = if let Some(frame_id) = inner.free.pop_front() {
+            frame_id
+        } else {
+            let prefer_t1 = !inner.t1.is_empty() && inner.t1.len() > inner.p;
+            let (first, second, first_ghosts, second_ghosts) = if prefer_t1 {
+                (&mut inner.t1, &mut inner.t2, &mut inner.b1, &mut inner.b2)
+            } else {
+                (&mut inner.t2, &mut inner.t1, &mut inner.b2, &mut inner.b1)
+            };
+            let (frame_id, ghosts) = match ArcReplacerInner::take_unpinned(first, &inner.pin_counts) {
+                Some(frame_id) => (frame_id, first_ghosts),
+                None => (
+                    ArcReplacerInner::take_unpinned(second, &inner.pin_counts)?,
+                    second_ghosts,
+                ),
+            };
+            if let Some(page_id) = inner.frame_pages[frame_id].take() {
+                ghosts.push_back(page_id);
+                inner.ghosts.insert(page_id);
+            }
+            inner.trim_ghosts();
+            frame_id
+        };
+
+        inner.pin_counts[frame_id] = 1;
+        Some(frame_id)
+    }
+    fn evict(&self, frame_id: usize) -> bool {
+        let mut guard = self.inner.lock().unwrap();
+        let inner = &mut *guard;
+        if inner.pin_counts[frame_id] != 0 {
+            return false;
+        }
+        // Pages recycled by a ring are not worth remembering as ghosts.
+        for list in [&mut inner.free, &mut inner.t1, &mut inner.t2] {
+            if let Some(pos) = list.iter().position(|&f| f == frame_id) {
+                list.remove(pos);
+            }
+        }
+        inner.frame_pages[frame_id] = None;
+        inner.pin_counts[frame_id] = 1;
+        true
+    }
+    fn pin(&self, frame_id: usize) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.pin_counts[frame_id] += 1;
+    }
+    fn unpin(&self, frame_id: usize) {
+        let mut inner = self.inner.lock().unwrap();
+        debug_assert!(inner.pin_counts[frame_id] > 0, "unpin of unpinned frame {}", frame_id);
+        inner.pin_counts[frame_id] = inner.pin_counts[frame_id].saturating_sub(1);
+        // A frame whose load failed never got a page; hand it back as free.
+        if inner.pin_counts[frame_id] == 0
+            && inner.frame_pages[frame_id].is_none()
+            && !inner.free.contains(&frame_id)
+        {
+            inner.free.push_back(frame_id);
+        }
+    }
+    fn record_access(&self, frame_id: usize, page_id: PageId) {
+        let mut guard = self.inner.lock().unwrap();
+        let inner = &mut *guard;
+
+        if inner.frame_pages[frame_id] == Some(page_id) {
+            // Hit: promote to the MRU end of T2.
+            if let Some(pos) = inner.t1.iter().position(|&f| f == frame_id) {
+                inner.t1.remove(pos);
+            } else if let Some(pos) = inner.t2.iter().position(|&f| f == frame_id) {
+                inner.t2.remove(pos);
+            }
+            inner.t2.push_back(frame_id);
+            return;
+        }
+
+        inner.frame_pages[frame_id] = Some(page_id);
+        if let Some(pos) = inner.free.iter().position(|&f| f == frame_id) {
+            inner.free.remove(pos);
+        }
+
+        if inner.ghosts.remove(&page_id) {
+            if ArcReplacerInner::remove_ghost(&mut inner.b1, page_id) {
+                let delta = (inner.b2.len() / inner.b1.len().max(1)).max(1);
+                inner.p = (inner.p + delta).min(inner.capacity);
+            } else if ArcReplacerInner::remove_ghost(&mut inner.b2, page_id) {
+                let delta = (inner.b1.len() / inner.b2.len().max(1)).max(1);
+                inner.p = inner.p.saturating_sub(delta);
+            }
+            inner.t2.push_back(frame_id);
+        } else {
+            inner.t1.push_back(frame_id);
+        }
+        inner.trim_ghosts();
+    }
+    fn pin_count(&self, frame_id: usize) -> usize {
+        self.inner.lock().unwrap().pin_counts[frame_id]
+    }
+    fn upcoming_victims(&self, n: usize) -> Vec<usize> {
+        let inner = self.inner.lock().unwrap();
+        let prefer_t1 = !inner.t1.is_empty() && inner.t1.len() > inner.p;
+        let (first, second) = if prefer_t1 {
+            (&inner.t1, &inner.t2)
+        } else {
+            (&inner.t2, &inner.t1)
+        };
+        first
+            .iter()
+            .chain(second)
+            .copied()
+            .filter(|&f| inner.pin_counts[f] == 0)
+            .take(n)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ArcReplacer;
+    use crate::storage::replacer::Replacer;
+
+    #[test]
+    fn test_frequent_pages_survive_a_scan() {
+        let replacer = ArcReplacer::new(4);
+        // Two hot pages, each accessed twice so they move to T2.
+        for page_id in 0..2 {
+            let frame_id = replacer.victim().unwrap();
+            replacer.record_access(frame_id, page_id);
+            replacer.record_access(frame_id, page_id);
+            replacer.unpin(frame_id);
+        }
+        // A scan of once-accessed pages only recycles T1 frames.
+        for page_id in 100..110 {
+            let frame_id = replacer.victim().unwrap();
+            assert!(frame_id >= 2, "hot frame {} evicted by scan", frame_id);
+            replacer.record_access(frame_id, page_id);
+            replacer.unpin(frame_id);
+        }
+    }
+}
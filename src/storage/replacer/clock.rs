@@ -0,0 +1,116 @@
+use std::sync::Mutex;
+
+use crate::storage::{page::PageId, replacer::Replacer};
+
+pub struct ClockReplacer {
+    inner: Mutex<ClockReplacerInner>,
+}
+struct ClockReplacerInner {
+    frames: Vec<FrameState>,
+    clock_hand: usize,
+    capacity: usize,
+}
+struct FrameState {
+    pin_count: usize,
+    ref_bit: bool,
+}
+
+impl ClockReplacer {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            inner: Mutex::new(ClockReplacerInner {
+                frames: (0..capacity)
+                    .map(|_| FrameState {
+                        pin_count: 0,
+                        ref_bit: false,
+                    })
+                    .collect(),
+                clock_hand: 0,
+                capacity,
+            }),
+        }
+    }
+}
+
+impl Replacer for ClockReplacer {
+    fn victim(&self) -> Option<usize> {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.capacity == 0 {
+            return None;
+        }
+        for _ in 0..(2 * inner.capacity) {
+            let hand = inner.clock_hand;
+            inner.clock_hand = (inner.clock_hand + 1) % inner.capacity;
+            let frame_state = &mut inner.frames[hand];
+            if frame_state.pin_count == 0 {
+                if frame_state.ref_bit {
+                    frame_state.ref_bit = false;
+                } else {
+                    frame_state.pin_count = 1;
+                    return Some(hand);
+                }
+            }
+        }
+        None
+    }
+    fn evict(&self, frame_id: usize) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        let frame_state = &mut inner.frames[frame_id];
+        if frame_state.pin_count != 0 {
+            return false;
+        }
+        frame_state.pin_count = 1;
+        frame_state.ref_bit = false;
+        true
+    }
+    fn pin(&self, frame_id: usize) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.frames[frame_id].pin_count += 1;
+    }
+    fn unpin(&self, frame_id: usize) {
+        let mut inner = self.inner.lock().unwrap();
+        let frame_state = &mut inner.frames[frame_id];
+        debug_assert!(frame_state.pin_count > 0, "unpin of unpinned frame {}", frame_id);
+        frame_state.pin_count = frame_state.pin_count.saturating_sub(1);
+    }
+    fn record_access(&self, frame_id: usize, _page_id: PageId) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.frames[frame_id].ref_bit = true;
+    }
+    fn pin_count(&self, frame_id: usize) -> usize {
+        self.inner.lock().unwrap().frames[frame_id].pin_count
+    }
+    fn upcoming_victims(&self, n: usize) -> Vec<usize> {
+        let inner = self.inner.lock().unwrap();
+        let ahead = (0..inner.capacity).map(|i| (inner.clock_hand + i) % inner.capacity);
+        // Frames without a reference bit go on this sweep, the others on the next.
+        let (cold, warm): (Vec<usize>, Vec<usize>) = ahead
+            .filter(|&f| inner.frames[f].pin_count == 0)
+            .partition(|&f| !inner.frames[f].ref_bit);
+        cold.into_iter().chain(warm).take(n).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ClockReplacer;
+    use crate::storage::replacer::Replacer;
+
+    #[test]
+    fn test_frame_stays_pinned_until_last_unpin() {
+        let replacer = ClockReplacer::new(2);
+        let a = replacer.victim().unwrap();
+        let b = replacer.victim().unwrap();
+        assert_ne!(a, b);
+        assert_eq!(replacer.victim(), None);
+
+        // A second guard on frame `a`; dropping one of them must not free it.
+        replacer.pin(a);
+        replacer.unpin(a);
+        assert_eq!(replacer.pin_count(a), 1);
+        assert_eq!(replacer.victim(), None);
+
+        replacer.unpin(a);
+        assert_eq!(replacer.victim(), Some(a));
+    }
+}
@@ -0,0 +1,139 @@
+use std::{collections::VecDeque, sync::Mutex};
+
+use crate::storage::{page::PageId, replacer::Replacer};
+
+/// LRU-K：淘汰“倒数第 K 次访问”最早的帧。访问次数不足 K 次的帧
+/// 视为距离无穷大，优先淘汰，其中再按最早一次访问排序。
+/// 只被顺序扫描访问过一次的页因此不会挤掉反复被点查的热点页。
+pub struct LruKReplacer {
+    inner: Mutex<LruKReplacerInner>,
+}
+struct LruKReplacerInner {
+    frames: Vec<FrameState>,
+    k: usize,
+    current_timestamp: u64,
+}
+struct FrameState {
+    pin_count: usize,
+    page_id: Option<PageId>,
+    /// 最近 K 次访问的时间戳，最早的在前
+    history: VecDeque<u64>,
+}
+
+impl LruKReplacer {
+    pub fn new(capacity: usize, k: usize) -> Self {
+        let k = k.max(1);
+        Self {
+            inner: Mutex::new(LruKReplacerInner {
+                frames: (0..capacity)
+                    .map(|_| FrameState {
+                        pin_count: 0,
+                        page_id: None,
+                        history: VecDeque::with_capacity(k),
+                    })
+                    .collect(),
+                k,
+                current_timestamp: 0,
+            }),
+        }
+    }
+}
+
+impl Replacer for LruKReplacer {
+    fn victim(&self) -> Option<usize> {
+        let mut inner = self.inner.lock().unwrap();
+        let k = inner.k;
+        // (has fewer than K accesses, oldest relevant timestamp)
+        let victim = inner
+            .frames
+            .iter()
+            .enumerate()
+            .filter(|(_, f)| f.pin_count == 0)
+            .min_by_key(|(_, f)| {
+                let oldest = f.history.front().copied().unwrap_or(0);
+                (f.history.len() >= k, oldest)
+            })
+            .map(|(frame_id, _)| frame_id)?;
+
+        let frame_state = &mut inner.frames[victim];
+        frame_state.pin_count = 1;
+        frame_state.page_id = None;
+        frame_state.history.clear();
+        Some(victim)
+    }
+    fn evict(&self, frame_id: usize) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        let frame_state = &mut inner.frames[frame_id];
+        if frame_state.pin_count != 0 {
+            return false;
+        }
+        frame_state.pin_count = 1;
+        frame_state.page_id = None;
+        frame_state.history.clear();
+        true
+    }
+    fn pin(&self, frame_id: usize) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.frames[frame_id].pin_count += 1;
+    }
+    fn unpin(&self, frame_id: usize) {
+        let mut inner = self.inner.lock().unwrap();
+        let frame_state = &mut inner.frames[frame_id];
+        debug_assert!(frame_state.pin_count > 0, "unpin of unpinned frame {}", frame_id);
+        frame_state.pin_count = frame_state.pin_count.saturating_sub(1);
+    }
+    fn record_access(&self, frame_id: usize, page_id: PageId) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.current_timestamp += 1;
+        let now = inner.current_timestamp;
+        let k = inner.k;
+        let frame_state = &mut inner.frames[frame_id];
+        if frame_state.page_id != Some(page_id) {
+            frame_state.page_id = Some(page_id);
+            frame_state.history.clear();
+        }
+        if frame_state.history.len() == k {
+            frame_state.history.pop_front();
+        }
+        frame_state.history.push_back(now);
+    }
+    fn pin_count(&self, frame_id: usize) -> usize {
+        self.inner.lock().unwrap().frames[frame_id].pin_count
+    }
+    fn upcoming_victims(&self, n: usize) -> Vec<usize> {
+        let inner = self.inner.lock().unwrap();
+        let k = inner.k;
+        let mut candidates: Vec<(bool, u64, usize)> = inner
+            .frames
+            .iter()
+            .enumerate()
+            .filter(|(_, f)| f.pin_count == 0 && f.page_id.is_some())
+            .map(|(frame_id, f)| {
+                let oldest = f.history.front().copied().unwrap_or(0);
+                (f.history.len() >= k, oldest, frame_id)
+            })
+            .collect();
+        candidates.sort_unstable();
+        candidates.into_iter().take(n).map(|(_, _, frame_id)| frame_id).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LruKReplacer;
+    use crate::storage::replacer::Replacer;
+
+    #[test]
+    fn test_single_access_pages_are_evicted_first() {
+        let replacer = LruKReplacer::new(3, 2);
+        for page_id in 0..3 {
+            let frame_id = replacer.victim().unwrap();
+            replacer.record_access(frame_id, page_id);
+            replacer.unpin(frame_id);
+        }
+        // Frames 0 and 2 become hot; frame 1 was only touched once.
+        replacer.record_access(0, 0);
+        replacer.record_access(2, 2);
+        assert_eq!(replacer.victim(), Some(1));
+    }
+}
@@ -0,0 +1,47 @@
+//! 页面置换策略。`BufferPoolManager` 只通过 `Replacer` trait 与策略交互，
+//! 构造缓冲池时可以通过 `ReplacerKind` 选择具体实现。
+mod arc;
+mod clock;
+mod lru_k;
+
+pub use arc::ArcReplacer;
+pub use clock::ClockReplacer;
+pub use lru_k::LruKReplacer;
+
+use crate::storage::page::PageId;
+
+pub trait Replacer: Send + Sync {
+    /// 选出一个未被 pin 的帧作为牺牲者，并在同一临界区内 pin 住它，
+    /// 避免选出后、pin 之前被其他任务抢走。
+    fn victim(&self) -> Option<usize>;
+    /// 与 `victim` 相同，但指定要回收的帧；帧已被 pin 时返回 false。
+    /// 供环形访问策略复用它自己的帧。
+    fn evict(&self, frame_id: usize) -> bool;
+    fn pin(&self, frame_id: usize);
+    fn unpin(&self, frame_id: usize);
+    /// 记录一次对 `frame_id` 中页 `page_id` 的访问（命中或刚刚装入）。
+    fn record_access(&self, frame_id: usize, page_id: PageId);
+    fn pin_count(&self, frame_id: usize) -> usize;
+    /// 按淘汰顺序返回接下来最可能被 `victim` 选中的至多 `n` 个未被 pin 的帧，
+    /// 不改变任何状态。后台写进程据此提前写回这些帧中的脏页。
+    fn upcoming_victims(&self, n: usize) -> Vec<usize>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReplacerKind {
+    #[default]
+    Clock,
+    /// LRU-K，参数为 K
+    LruK(usize),
+    Arc,
+}
+
+impl ReplacerKind {
+    pub fn build(self, capacity: usize) -> Box<dyn Replacer> {
+        match self {
+            ReplacerKind::Clock => Box::new(ClockReplacer::new(capacity)),
+            ReplacerKind::LruK(k) => Box::new(LruKReplacer::new(capacity, k)),
+            ReplacerKind::Arc => Box::new(ArcReplacer::new(capacity)),
+        }
+    }
+}
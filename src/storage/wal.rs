@@ -0,0 +1,712 @@
+//! 预写日志 (WAL)。
+//!
+//! 日志只用于重做：每次修改过页的排他闩释放时把页的完整镜像追加到日志中，
+//! 所以重放时不需要读旧页，也能修复写到一半的数据页。LSN 是日志文件中的字节偏移，
+//! 一条记录的 LSN 指向它的末尾。数据页写回之前必须先把日志刷到该页最后一条记录的 LSN。
+//!
+//! 日志分成多个段文件 `<path>.<起始 LSN>`，记录不跨段。当前段写满 `SEGMENT_SIZE` 之后，
+//! 下一次刷写换到一个新段。检查点之后，整段都在重放起点之前的段被删除，
+//! 打开和重放都按块读取，内存占用与日志的长度无关。
+//!
+//! 提交采用组提交：启动了日志刷写任务 (`run_log_flusher`) 之后，提交只把自己的 LSN 排进队列，
+//! 刷写任务把同一时间排队的提交合并成一次写入和一次 fsync，再唤醒所有等待者。
+use std::{
+    collections::{BTreeSet, HashMap, VecDeque},
+    io,
+    path::Path,
+    str::FromStr,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use bincode::{Decode, Encode, config};
+use futures::{
+    StreamExt,
+    channel::{mpsc, oneshot},
+};
+
+use crate::storage::{
+    disk::{DiskManager, SharedFile},
+    page::{Page, PageId},
+};
+
+/// 日志序号：日志文件中的字节偏移
+pub type Lsn = u64;
+
+/// 记录头：记录体长度 (u32) + 记录体的 CRC32C (u32)
+const RECORD_HEADER_SIZE: usize = 8;
+/// 一个日志段写到这么大之后换新段
+const SEGMENT_SIZE: u64 = 16 << 20;
+/// 读日志时每次读入的字节数
+const READ_CHUNK_SIZE: u64 = 1 << 20;
+/// 重放时内存中最多攒这么多页镜像，再写回数据文件
+const REPLAY_BATCH_PAGES: usize = 256;
+
+/// 提交时的持久化级别
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SynchronousCommit {
+    /// 提交时不等待日志落盘，崩溃可能丢失最近提交的语句，但不会损坏数据
+    Off,
+    /// 提交前把日志 fsync 到该语句的 LSN
+    Normal,
+    /// 在 `Normal` 的基础上，检查点还会 fsync 数据文件并推进重放起点
+    #[default]
+    Full,
+}
+
+impl FromStr for SynchronousCommit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "off" => Ok(SynchronousCommit::Off),
+            "normal" => Ok(SynchronousCommit::Normal),
+            "full" => Ok(SynchronousCommit::Full),
+            _ => Err(format!(
+                "invalid value for synchronous_commit: '{}' (expected off, normal or full)",
+                s
+            )),
+        }
+    }
+}
+
+/// 日志相关的配置，同时是新会话的默认设置
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WalConfig {
+    /// 新会话的提交级别，同时决定检查点是否 fsync 数据文件
+    pub synchronous_commit: SynchronousCommit,
+    /// 刷写任务收到一批中的第一个提交后，等待这么久再刷日志，让更多并发的提交合入同一次 fsync。
+    /// 只有一个客户端时这纯粹是额外的延迟。
+    pub commit_delay: Duration,
+}
+
+impl std::fmt::Display for SynchronousCommit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SynchronousCommit::Off => write!(f, "off"),
+            SynchronousCommit::Normal => write!(f, "normal"),
+            SynchronousCommit::Full => write!(f, "full"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum WalRecord {
+    /// 页在修改之后的完整内容（不含校验和）
+    PageImage { page_id: PageId, image: Vec<u8> },
+}
+
+impl WalRecord {
+    fn encode(&self) -> Vec<u8> {
+        let body = bincode::encode_to_vec(self, config::standard()).unwrap();
+        let mut bytes = Vec::with_capacity(RECORD_HEADER_SIZE + body.len());
+        bytes.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&crc32c::crc32c(&body).to_le_bytes());
+        bytes.extend_from_slice(&body);
+        bytes
+    }
+}
+
+/// 解码 `bytes` 中完整且校验通过的记录，`bytes[0]` 对应 `start`。
+/// 返回各记录及其 LSN，以及最后一条有效记录的末尾。
+fn decode_records(bytes: &[u8], start: Lsn) -> (Vec<(Lsn, WalRecord)>, Lsn) {
+    let mut records = Vec::new();
+    let mut pos = 0;
+    while let Some(header) = bytes.get(pos..pos + RECORD_HEADER_SIZE) {
+        let len = u32::from_le_bytes(header[..4].try_into().unwrap()) as usize;
+        let checksum = u32::from_le_bytes(header[4..].try_into().unwrap());
+        let body_start = pos + RECORD_HEADER_SIZE;
+        let Some(body) = bytes.get(body_start..body_start + len) else {
+            break;
+        };
+        if crc32c::crc32c(body) != checksum {
+            break;
+        }
+        let Ok((record, _)) = bincode::decode_from_slice(body, config::standard()) else {
+            break;
+        };
+        pos = body_start + len;
+        records.push((start + pos as Lsn, record));
+    }
+    (records, start + pos as Lsn)
+}
+
+/// `bytes` 开头的记录已经完整，只是没有通过校验，不必再读更多数据
+fn starts_with_invalid_record(bytes: &[u8]) -> bool {
+    bytes.get(..4).is_some_and(|len| {
+        bytes.len() >= RECORD_HEADER_SIZE + u32::from_le_bytes(len.try_into().unwrap()) as usize
+    })
+}
+
+fn segment_path(path: &str, start: Lsn) -> String {
+    format!("{}.{:016X}", path, start)
+}
+
+/// 日志 `path` 现有各段的起始 LSN，从小到大
+fn list_segments(path: &str) -> io::Result<Vec<Lsn>> {
+    let path = Path::new(path);
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let prefix = format!("{}.", path.file_name().unwrap_or_default().to_string_lossy());
+    let mut segments = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let name = entry?.file_name();
+        let Some(suffix) = name.to_str().and_then(|name| name.strip_prefix(&prefix)) else {
+            continue;
+        };
+        if suffix.len() == 16
+            && let Ok(start) = Lsn::from_str_radix(suffix, 16)
+        {
+            segments.push(start);
+        }
+    }
+    segments.sort_unstable();
+    Ok(segments)
+}
+
+/// 创建段文件，并 fsync 所在目录，让新段在崩溃之后仍然存在
+fn create_segment(path: &str, start: Lsn) -> io::Result<SharedFile> {
+    let segment = segment_path(path, start);
+    let file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&segment)?;
+    if let Some(dir) = Path::new(&segment).parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        std::fs::File::open(dir)?.sync_all()?;
+    }
+    Ok(SharedFile::new(file))
+}
+
+/// 按块顺序读取 `[from, end)` 中的记录，内存中只有一块和上一块末尾不完整的记录
+struct LogReader {
+    path: String,
+    /// 还没有打开的段
+    segments: VecDeque<Lsn>,
+    /// 正在读的段文件、它的起点和可以读到的末尾
+    segment: Option<(SharedFile, Lsn, Lsn)>,
+    /// 下一次读取的位置
+    pos: Lsn,
+    end: Lsn,
+    /// 已读入但还不完整的记录，紧接着 `valid_end`
+    pending: Vec<u8>,
+    /// 最后一条有效记录的末尾
+    valid_end: Lsn,
+    done: bool,
+}
+
+impl LogReader {
+    /// `segments` 是日志现有各段的起点。`from` 之前的段已经被删除时从最早的段开始读，
+    /// 多重放的记录不影响结果
+    fn new(path: &str, segments: &[Lsn], from: Lsn, end: Lsn) -> Self {
+        // Skip segments that end at or before `from`.
+        let first = segments.iter().rposition(|&start| start <= from).unwrap_or(0);
+        let segments: VecDeque<Lsn> = segments[first..].iter().copied().collect();
+        let pos = segments.front().map_or(from, |&start| start.max(from));
+        Self {
+            path: path.to_string(),
+            segments,
+            segment: None,
+            pos,
+            end,
+            pending: Vec::new(),
+            valid_end: pos,
+            done: false,
+        }
+    }
+
+    /// 下一批记录，读完或者遇到无效记录之后返回空
+    async fn next_chunk(&mut self) -> io::Result<Vec<(Lsn, WalRecord)>> {
+        loop {
+            if self.done {
+                return Ok(Vec::new());
+            }
+            let Some((file, start, segment_end)) = &self.segment else {
+                let Some(start) = self.segments.pop_front() else {
+                    self.done = true;
+                    continue;
+                };
+                let path = segment_path(&self.path, start);
+                let file = std::fs::File::open(&path)?;
+                let segment_end = (start + file.metadata()?.len()).min(self.end);
+                self.pos = self.pos.max(start);
+                self.valid_end = self.valid_end.max(start);
+                self.segment = Some((SharedFile::new(file), start, segment_end));
+                continue;
+            };
+            if self.pos >= *segment_end {
+                // Records never span segments, so a leftover is a torn record.
+                self.done = !self.pending.is_empty() || self.pos >= self.end;
+                self.segment = None;
+                continue;
+            }
+
+            let len = READ_CHUNK_SIZE.min(segment_end - self.pos);
+            let (res, chunk) = file
+                .core_file()?
+                .read_exact_at(vec![0u8; len as usize], self.pos - start)
+                .await;
+            res?;
+            self.pos += len;
+            self.pending.extend_from_slice(&chunk);
+            let (records, valid_end) = decode_records(&self.pending, self.valid_end);
+            self.pending.drain(..(valid_end - self.valid_end) as usize);
+            self.valid_end = valid_end;
+            if starts_with_invalid_record(&self.pending) {
+                self.done = true;
+            }
+            if !records.is_empty() {
+                return Ok(records);
+            }
+        }
+    }
+}
+
+/// 排队等待组提交的一个提交
+struct CommitRequest {
+    lsn: Lsn,
+    delay: Duration,
+    done: oneshot::Sender<Result<(), String>>,
+}
+
+/// 尚未写入日志文件的记录
+struct LogBuffer {
+    data: Vec<u8>,
+    /// `data[0]` 对应的 LSN
+    start_lsn: Lsn,
+}
+
+/// 正在写的日志段
+struct Segment {
+    start: Lsn,
+    file: SharedFile,
+}
+
+/// 管理日志文件。追加只写内存缓冲区，`flush` 把缓冲区写入当前段并 fdatasync。
+pub struct LogManager {
+    path: String,
+    segment_size: u64,
+    /// 现有各段的起始 LSN，从小到大，最后一个是当前段
+    segments: Mutex<Vec<Lsn>>,
+    buffer: Mutex<LogBuffer>,
+    /// 已经落盘的日志末尾
+    flushed_lsn: AtomicU64,
+    /// 同一时刻只有一个任务写日志，保证日志按顺序落盘
+    flush_lock: async_lock::Mutex<Segment>,
+    /// 日志文件 fsync 的次数
+    flush_count: AtomicU64,
+    /// 交给刷写任务的提交；刷写任务没有启动时由提交者自己刷日志
+    commit_tx: mpsc::UnboundedSender<CommitRequest>,
+    commit_rx: Mutex<Option<mpsc::UnboundedReceiver<CommitRequest>>>,
+    flusher_running: AtomicBool,
+}
+
+impl LogManager {
+    /// 打开（或创建）日志。末尾不完整或校验失败的记录是崩溃时没写完的，直接截掉。
+    /// 只需要读最后一段来找到日志的末尾。
+    pub async fn open(path: &str) -> io::Result<Self> {
+        Self::open_with_segment_size(path, SEGMENT_SIZE).await
+    }
+
+    async fn open_with_segment_size(path: &str, segment_size: u64) -> io::Result<Self> {
+        let mut segments = list_segments(path)?;
+        if segments.is_empty() && Path::new(path).is_file() {
+            // A log written as a single file becomes the first segment.
+            std::fs::rename(path, segment_path(path, 0))?;
+            segments.push(0);
+        }
+        if segments.is_empty() {
+            segments.push(0);
+        }
+        let start = *segments.last().unwrap();
+        let file = create_segment(path, start)?;
+
+        let mut reader = LogReader::new(path, &[start], start, Lsn::MAX);
+        while !reader.next_chunk().await?.is_empty() {}
+        let end = reader.valid_end;
+        if end - start < file.std_file().metadata()?.len() {
+            file.std_file().set_len(end - start)?;
+        }
+        let (commit_tx, commit_rx) = mpsc::unbounded();
+        Ok(Self {
+            path: path.to_string(),
+            segment_size,
+            segments: Mutex::new(segments),
+            buffer: Mutex::new(LogBuffer {
+                data: Vec::new(),
+                start_lsn: end,
+            }),
+            flushed_lsn: AtomicU64::new(end),
+            flush_lock: async_lock::Mutex::new(Segment { start, file }),
+            flush_count: AtomicU64::new(0),
+            commit_tx,
+            commit_rx: Mutex::new(Some(commit_rx)),
+            flusher_running: AtomicBool::new(false),
+        })
+    }
+
+    /// 追加一条记录，返回它的 LSN。记录在 `flush` 之前只在内存中。
+    pub fn append(&self, record: &WalRecord) -> Lsn {
+        let bytes = record.encode();
+        let mut buffer = self.buffer.lock().unwrap();
+        buffer.data.extend_from_slice(&bytes);
+        buffer.start_lsn + buffer.data.len() as Lsn
+    }
+
+    /// 下一条记录开始的位置，即目前所有已追加记录的末尾
+    pub fn insert_lsn(&self) -> Lsn {
+        let buffer = self.buffer.lock().unwrap();
+        buffer.start_lsn + buffer.data.len() as Lsn
+    }
+
+    pub fn flushed_lsn(&self) -> Lsn {
+        self.flushed_lsn.load(Ordering::Acquire)
+    }
+
+    pub fn flush_count(&self) -> u64 {
+        self.flush_count.load(Ordering::Relaxed)
+    }
+
+    /// 提交：等待日志落盘到 `lsn`。刷写任务在运行时把请求排队，和并发的提交共用一次 fsync，
+    /// `commit_delay` 是该提交愿意为此多等的时间。
+    pub async fn commit(&self, lsn: Lsn, commit_delay: Duration) -> io::Result<()> {
+        if self.flushed_lsn() >= lsn {
+            return Ok(());
+        }
+        if !self.flusher_running.load(Ordering::Acquire) {
+            return self.flush(lsn).await;
+        }
+        let (done, wait) = oneshot::channel();
+        let request = CommitRequest {
+            lsn,
+            delay: commit_delay,
+            done,
+        };
+        if self.commit_tx.unbounded_send(request).is_err() {
+            return self.flush(lsn).await;
+        }
+        match wait.await {
+            Ok(res) => res.map_err(io::Error::other),
+            // The flusher went away with our request; flush it ourselves.
+            Err(_) => self.flush(lsn).await,
+        }
+    }
+
+    /// 保证 `lsn` 之前的日志都已落盘。会顺带写出缓冲区中 `lsn` 之后的记录。
+    pub async fn flush(&self, lsn: Lsn) -> io::Result<()> {
+        if self.flushed_lsn() >= lsn {
+            return Ok(());
+        }
+        let mut segment = self.flush_lock.lock().await;
+        if self.flushed_lsn() >= lsn {
+            return Ok(());
+        }
+        let flushed_lsn = self.flushed_lsn();
+        if flushed_lsn - segment.start >= self.segment_size {
+            let file = create_segment(&self.path, flushed_lsn)?;
+            *segment = Segment {
+                start: flushed_lsn,
+                file,
+            };
+            self.segments.lock().unwrap().push(flushed_lsn);
+        }
+
+        let (data, start_lsn) = {
+            let mut buffer = self.buffer.lock().unwrap();
+            let data = std::mem::take(&mut buffer.data);
+            let start_lsn = buffer.start_lsn;
+            buffer.start_lsn += data.len() as Lsn;
+            (data, start_lsn)
+        };
+        let end_lsn = start_lsn + data.len() as Lsn;
+        let file = segment.file.core_file()?;
+        let (res, data) = file.write_all_at(data, start_lsn - segment.start).await;
+        let res = match res {
+            Ok(()) => file.sync_data().await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = res {
+            // Put the records back in front of anything appended meanwhile.
+            let mut buffer = self.buffer.lock().unwrap();
+            let appended = std::mem::replace(&mut buffer.data, data);
+            buffer.data.extend_from_slice(&appended);
+            buffer.start_lsn = start_lsn;
+            return Err(e);
+        }
+        self.flushed_lsn.store(end_lsn, Ordering::Release);
+        self.flush_count.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn reader(&self, from: Lsn) -> LogReader {
+        let segments = self.segments.lock().unwrap().clone();
+        LogReader::new(&self.path, &segments, from, self.flushed_lsn())
+    }
+
+    /// 读取从 `from` 开始所有已落盘的记录
+    pub async fn read_records(&self, from: Lsn) -> io::Result<Vec<(Lsn, WalRecord)>> {
+        let mut reader = self.reader(from);
+        let mut records = Vec::new();
+        loop {
+            let chunk = reader.next_chunk().await?;
+            if chunk.is_empty() {
+                return Ok(records);
+            }
+            records.extend(chunk);
+        }
+    }
+
+    /// 崩溃恢复：把 `from` 之后记录的页镜像按顺序写回数据文件并 fsync，返回写回的页号。
+    /// 同一页的多个镜像以最后一个为准，所以重复重放是安全的。
+    pub async fn replay(&self, from: Lsn, disk_manager: &DiskManager) -> io::Result<BTreeSet<PageId>> {
+        let mut reader = self.reader(from);
+        let mut pages = HashMap::new();
+        let mut replayed = BTreeSet::new();
+        let mut written = false;
+        loop {
+            let records = reader.next_chunk().await?;
+            if records.is_empty() {
+                break;
+            }
+            for (_, record) in records {
+                match record {
+                    WalRecord::PageImage { page_id, image } => {
+                        replayed.insert(page_id);
+                        pages.insert(page_id, image);
+                    }
+                }
+            }
+            // Later batches overwrite earlier ones, so writing early keeps the order.
+            if pages.len() >= REPLAY_BATCH_PAGES {
+                write_images(disk_manager, &mut pages).await?;
+                written = true;
+            }
+        }
+        if !pages.is_empty() {
+            write_images(disk_manager, &mut pages).await?;
+            written = true;
+        }
+        if written {
+            disk_manager.sync_data().await?;
+        }
+        Ok(replayed)
+    }
+
+    /// 删除整段都在 `redo_lsn` 之前的段，在检查点写入控制文件之后调用。返回删除的段数
+    pub fn recycle(&self, redo_lsn: Lsn) -> io::Result<usize> {
+        let mut segments = self.segments.lock().unwrap();
+        // A segment ends where the next one starts; the current segment is never removed.
+        let obsolete = segments.windows(2).take_while(|pair| pair[1] <= redo_lsn).count();
+        for &start in &segments[..obsolete] {
+            std::fs::remove_file(segment_path(&self.path, start))?;
+        }
+        segments.drain(..obsolete);
+        Ok(obsolete)
+    }
+}
+
+/// 删除日志 `path` 的所有段
+#[cfg(test)]
+pub(crate) fn remove_log(path: &str) {
+    for start in list_segments(path).unwrap_or_default() {
+        let _ = std::fs::remove_file(segment_path(path, start));
+    }
+}
+
+async fn write_images(disk_manager: &DiskManager, pages: &mut HashMap<PageId, Vec<u8>>) -> io::Result<()> {
+    let pages = pages
+        .drain()
+        .map(|(page_id, mut image)| {
+            Page::update_checksum(&mut image);
+            (page_id, image)
+        })
+        .collect();
+    let (res, _) = disk_manager.write_pages(pages).await;
+    res
+}
+
+/// 日志刷写任务的主循环。每个 `LogManager` 只能启动一个，之后的调用直接返回。
+///
+/// 每一批从第一个排队的提交开始：等待它的 `commit_delay`，取出此时排队的所有提交，
+/// 把缓冲区整个写出并 fsync 一次，然后唤醒这一批的所有等待者。
+pub async fn run_log_flusher(log_manager: Arc<LogManager>) {
+    let Some(mut requests) = log_manager.commit_rx.lock().unwrap().take() else {
+        return;
+    };
+    log_manager.flusher_running.store(true, Ordering::Release);
+    while let Some(first) = requests.next().await {
+        if !first.delay.is_zero() {
+            monoio::time::sleep(first.delay).await;
+        }
+        let mut batch = vec![first];
+        while let Ok(Some(request)) = requests.try_next() {
+            batch.push(request);
+        }
+        let lsn = batch.iter().map(|request| request.lsn).max().unwrap_or(0);
+        let res = log_manager
+            .flush(lsn.max(log_manager.insert_lsn()))
+            .await
+            .map_err(|e| e.to_string());
+        for request in batch {
+            let _ = request.done.send(res.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::page::PAGE_SIZE;
+
+    fn temp_file(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("ringdb_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let path = path.to_string_lossy().into_owned();
+        remove_log(&path);
+        path
+    }
+
+    #[monoio::test]
+    async fn test_flushed_records_survive_reopen_and_torn_tail_is_dropped() {
+        let path = temp_file("wal_reopen.wal");
+        let wal = LogManager::open(&path).await.unwrap();
+        let first = wal.append(&WalRecord::PageImage {
+            page_id: 3,
+            image: vec![1; PAGE_SIZE],
+        });
+        let second = wal.append(&WalRecord::PageImage {
+            page_id: 4,
+            image: vec![2; PAGE_SIZE],
+        });
+        assert_eq!(wal.insert_lsn(), second);
+        wal.flush(first).await.unwrap();
+        assert_eq!(wal.flushed_lsn(), second);
+        // Never flushed, so it is lost on "crash".
+        wal.append(&WalRecord::PageImage {
+            page_id: 5,
+            image: vec![3; PAGE_SIZE],
+        });
+        drop(wal);
+
+        // Simulate a torn write of a third record.
+        let file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(segment_path(&path, 0))
+            .unwrap();
+        std::io::Write::write_all(&mut &file, &[7u8; 20]).unwrap();
+        drop(file);
+
+        let wal = LogManager::open(&path).await.unwrap();
+        assert_eq!(wal.insert_lsn(), second);
+        let records = wal.read_records(0).await.unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].0, first);
+        assert_eq!(records[1].0, second);
+        assert_eq!(wal.read_records(first).await.unwrap().len(), 1);
+        remove_log(&path);
+    }
+
+    #[monoio::test(timer_enabled = true)]
+    async fn test_concurrent_commits_share_one_fsync() {
+        let path = temp_file("wal_group.wal");
+        let wal = Arc::new(LogManager::open(&path).await.unwrap());
+        monoio::spawn(run_log_flusher(wal.clone()));
+        monoio::time::sleep(Duration::from_millis(1)).await;
+
+        const COMMITS: u8 = 16;
+        let commits: Vec<_> = (0..COMMITS)
+            .map(|i| {
+                let wal = wal.clone();
+                monoio::spawn(async move {
+                    let lsn = wal.append(&WalRecord::PageImage {
+                        page_id: i as PageId,
+                        image: vec![i; 64],
+                    });
+                    wal.commit(lsn, Duration::from_millis(5)).await.unwrap();
+                    assert!(wal.flushed_lsn() >= lsn);
+                })
+            })
+            .collect();
+        for commit in commits {
+            commit.await;
+        }
+        assert!(
+            wal.flush_count() < COMMITS as u64 / 2,
+            "{} fsyncs for {} commits",
+            wal.flush_count(),
+            COMMITS
+        );
+        assert_eq!(wal.read_records(0).await.unwrap().len(), COMMITS as usize);
+        remove_log(&path);
+    }
+
+    #[monoio::test]
+    async fn test_segments_before_redo_point_are_recycled() {
+        let path = temp_file("wal_segments.wal");
+        // A one-byte segment size starts a new segment at every flush.
+        let wal = LogManager::open_with_segment_size(&path, 1).await.unwrap();
+        let mut lsns = Vec::new();
+        for page_id in 0..4 {
+            let lsn = wal.append(&WalRecord::PageImage {
+                page_id,
+                image: vec![page_id as u8; PAGE_SIZE],
+            });
+            wal.flush(lsn).await.unwrap();
+            lsns.push(lsn);
+        }
+        assert_eq!(list_segments(&path).unwrap(), vec![0, lsns[0], lsns[1], lsns[2]]);
+        // Reading streams across segment boundaries.
+        let records = wal.read_records(lsns[0]).await.unwrap();
+        assert_eq!(records.iter().map(|(lsn, _)| *lsn).collect::<Vec<_>>(), lsns[1..]);
+
+        // The segment holding the redo point stays; whole segments before it go.
+        assert_eq!(wal.recycle(lsns[1] + 1).unwrap(), 2);
+        assert_eq!(list_segments(&path).unwrap(), vec![lsns[1], lsns[2]]);
+        drop(wal);
+
+        let wal = LogManager::open_with_segment_size(&path, 1).await.unwrap();
+        assert_eq!(wal.insert_lsn(), lsns[3]);
+        // Replaying from a recycled point starts at the oldest segment left.
+        let records = wal.read_records(0).await.unwrap();
+        assert_eq!(records.iter().map(|(lsn, _)| *lsn).collect::<Vec<_>>(), lsns[2..]);
+        remove_log(&path);
+    }
+
+    #[monoio::test]
+    async fn test_replay_restores_pages_whose_data_was_never_written() {
+        use crate::storage::{buffer_pool::BufferPoolManager, replacer::ReplacerKind};
+        use std::sync::Arc;
+
+        let db = temp_file("wal_replay.db");
+        let wal_path = format!("{}.wal", db);
+        remove_log(&wal_path);
+        {
+            let dm = Arc::new(DiskManager::new(&db).await.unwrap());
+            let wal = Arc::new(LogManager::open(&wal_path).await.unwrap());
+            let bpm = BufferPoolManager::with_log_manager(4, ReplacerKind::default(), dm, wal.clone());
+            for page_id in 0..3 {
+                bpm.fetch_page_mut(page_id).await.unwrap()[100] = page_id as u8 + 1;
+            }
+            // Commit: only the log reaches the disk before the "crash".
+            wal.flush(wal.insert_lsn()).await.unwrap();
+        }
+
+        let dm = DiskManager::new(&db).await.unwrap();
+        let wal = LogManager::open(&wal_path).await.unwrap();
+        assert_eq!(wal.replay(0, &dm).await.unwrap(), (0..3).collect());
+        for page_id in 0..3 {
+            let (res, buf) = dm.read_page(page_id, vec![0; PAGE_SIZE]).await;
+            res.unwrap();
+            Page::verify(&buf).unwrap();
+            assert_eq!(buf[100], page_id as u8 + 1);
+        }
+        let _ = std::fs::remove_file(&db);
+        remove_log(&wal_path);
+    }
+}
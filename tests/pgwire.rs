@@ -0,0 +1,513 @@
+//! 用 PostgreSQL 客户端库连接本地启动的 `bin/server`，检查简单查询、扩展查询和错误码。
+use std::{
+    io::{Read, Write},
+    net::TcpStream,
+    path::PathBuf,
+    process::{Child, Command, ExitStatus, Stdio},
+    time::{Duration, Instant},
+};
+
+use postgres::{Client, NoTls, SimpleQueryMessage, error::SqlState, fallible_iterator::FallibleIterator, types::Type};
+
+const CONNECTION: &str = "host=127.0.0.1 user=test application_name=pgwire_test";
+
+struct Server {
+    child: Child,
+    dir: PathBuf,
+}
+
+impl Server {
+    fn start(port: u16, args: &[&str]) -> Self {
+        let dir = std::env::temp_dir().join(format!("ringdb_pgwire_{}_{}", port, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let child = Command::new(env!("CARGO_BIN_EXE_server"))
+            .arg(format!("--listen-addr=127.0.0.1:{}", port))
+            .arg("--auth-method=trust")
+            .args(args)
+            .current_dir(&dir)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("failed to start server");
+
+        let started = Instant::now();
+        while TcpStream::connect(("127.0.0.1", port)).is_err() {
+            assert!(started.elapsed() < Duration::from_secs(10), "server did not start");
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        Self { child, dir }
+    }
+
+    /// 发送 SIGTERM 并等待服务端退出
+    fn terminate(&mut self) -> ExitStatus {
+        let status = Command::new("kill")
+            .args(["-TERM", &self.child.id().to_string()])
+            .status()
+            .unwrap();
+        assert!(status.success());
+        self.child.wait().unwrap()
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn connect(port: u16) -> Client {
+    Client::connect(&format!("{} port={}", CONNECTION, port), NoTls).unwrap()
+}
+
+#[test]
+fn test_postgres_client() {
+    let _server = Server::start(54330, &[]);
+    let mut client = connect(54330);
+
+    // Simple query protocol, several statements in one message.
+    client
+        .batch_execute(
+            "CREATE TABLE users (id INT, name VARCHAR, avatar BYTEA);
+             INSERT INTO users VALUES (1, 'Alice', X'00ff');",
+        )
+        .unwrap();
+
+    // Extended query protocol: Parse/Describe/Bind/Execute.
+    assert_eq!(client.execute("INSERT INTO users VALUES (2, 'Bob', NULL)", &[]).unwrap(), 1);
+    let rows = client.query("SELECT id, name, avatar FROM users", &[]).unwrap();
+    assert_eq!(rows.len(), 2);
+    assert_eq!(rows[0].get::<_, i64>("id"), 1);
+    assert_eq!(rows[0].get::<_, String>("name"), "Alice");
+    assert_eq!(rows[0].get::<_, Option<Vec<u8>>>("avatar"), Some(vec![0x00, 0xff]));
+    assert_eq!(rows[1].get::<_, &str>(1), "Bob");
+    assert_eq!(rows[1].get::<_, Option<Vec<u8>>>(2), None);
+
+    // A prepared statement can be executed more than once.
+    let statement = client.prepare("SELECT name FROM users").unwrap();
+    assert_eq!(statement.columns()[0].name(), "name");
+    for _ in 0..2 {
+        assert_eq!(client.query(&statement, &[]).unwrap().len(), 2);
+    }
+
+    // Text results through the simple query protocol.
+    let messages = client.simple_query("SELECT id, avatar FROM users").unwrap();
+    let SimpleQueryMessage::Row(row) = &messages[1] else {
+        panic!("expected a row, got {:?}", messages[1]);
+    };
+    assert_eq!(row.get("id"), Some("1"));
+    assert_eq!(row.get("avatar"), Some("\\x00ff"));
+    assert!(matches!(messages.last(), Some(SimpleQueryMessage::CommandComplete(2))));
+
+    // Errors carry a SQLSTATE and leave the connection usable.
+    let error = client.query("SELECT id FROM missing", &[]).unwrap_err();
+    assert_eq!(error.code(), Some(&SqlState::UNDEFINED_TABLE));
+    let error = client.batch_execute("SELECT FROM users").unwrap_err();
+    assert_eq!(error.code(), Some(&SqlState::SYNTAX_ERROR));
+    let error = client.execute("CREATE TABLE users (id INT)", &[]).unwrap_err();
+    assert_eq!(error.code(), Some(&SqlState::DUPLICATE_TABLE));
+    let error = client.batch_execute("SET work_mem = 64").unwrap_err();
+    assert_eq!(error.code(), Some(&SqlState::UNDEFINED_OBJECT));
+
+    assert_eq!(client.query("SELECT id FROM users", &[]).unwrap().len(), 2);
+    client.batch_execute("").unwrap();
+
+    // Each connection has its own session.
+    let mut other = connect(54330);
+    client.batch_execute("SET synchronous_commit = off").unwrap();
+    assert_eq!(other.query("SELECT name FROM users", &[]).unwrap().len(), 2);
+}
+
+#[test]
+fn test_dispatch_accept_mode() {
+    let _server = Server::start(54331, &["--accept=dispatch"]);
+    let mut clients: Vec<Client> = (0..4).map(|_| connect(54331)).collect();
+    clients[0].batch_execute("CREATE TABLE t (id INT)").unwrap();
+    for (i, client) in clients.iter_mut().enumerate() {
+        client.batch_execute(&format!("INSERT INTO t VALUES ({})", i)).unwrap();
+    }
+    assert_eq!(clients[3].query("SELECT id FROM t", &[]).unwrap().len(), 4);
+}
+
+#[test]
+fn test_graceful_shutdown() {
+    let mut server = Server::start(54332, &["--shutdown-timeout=5"]);
+    let mut client = connect(54332);
+    client.batch_execute("CREATE TABLE t (id INT); INSERT INTO t VALUES (1)").unwrap();
+
+    assert!(server.terminate().success());
+    let error = client.batch_execute("SELECT id FROM t").unwrap_err();
+    assert!(error.is_closed() || error.code() == Some(&SqlState::ADMIN_SHUTDOWN), "{:?}", error);
+    assert!(server.dir.join("database.db.control").exists());
+}
+
+/// 读取一条后端消息
+fn read_message(stream: &mut TcpStream) -> (u8, Vec<u8>) {
+    let mut header = [0u8; 5];
+    stream.read_exact(&mut header).unwrap();
+    let len = i32::from_be_bytes(header[1..5].try_into().unwrap()) as usize;
+    let mut body = vec![0u8; len - 4];
+    stream.read_exact(&mut body).unwrap();
+    (header[0], body)
+}
+
+fn startup_packet(version: i32, params: &[&str]) -> Vec<u8> {
+    let mut packet = vec![0, 0, 0, 0];
+    packet.extend_from_slice(&version.to_be_bytes());
+    for param in params {
+        packet.extend_from_slice(param.as_bytes());
+        packet.push(0);
+    }
+    packet.push(0);
+    let len = packet.len() as i32;
+    packet[..4].copy_from_slice(&len.to_be_bytes());
+    packet
+}
+
+#[test]
+fn test_framing_limits_and_version_negotiation() {
+    let _server = Server::start(54333, &["--max-message-size=4096"]);
+
+    // A client asking for protocol 3.2 and an unknown option is told to use 3.0.
+    let mut stream = TcpStream::connect(("127.0.0.1", 54333)).unwrap();
+    let packet = startup_packet(0x0003_0002, &["user", "test", "_pq_.compression", "on"]);
+    stream.write_all(&packet).unwrap();
+    let (tag, body) = read_message(&mut stream);
+    assert_eq!(tag, b'v');
+    assert_eq!(&body[..8], &[0, 0, 0, 0, 0, 0, 0, 1]);
+    assert_eq!(&body[8..], b"_pq_.compression\0");
+    while read_message(&mut stream).0 != b'Z' {}
+
+    // The length prefix is checked before anything is allocated.
+    stream.write_all(&[b'Q', 0x7f, 0xff, 0xff, 0xff]).unwrap();
+    let (tag, body) = read_message(&mut stream);
+    assert_eq!(tag, b'E');
+    assert!(String::from_utf8_lossy(&body).contains("54000"));
+    assert_eq!(stream.read(&mut [0u8; 1]).unwrap(), 0, "connection should be closed");
+
+    // So is the startup packet, before the client has even identified itself.
+    let mut stream = TcpStream::connect(("127.0.0.1", 54333)).unwrap();
+    stream.write_all(&100_000i32.to_be_bytes()).unwrap();
+    assert_eq!(read_message(&mut stream).0, b'E');
+
+    // A driver's oversized query fails (the server may reset the connection before the
+    // FATAL is read), while messages within the limit still work.
+    let mut client = connect(54333);
+    assert!(client.batch_execute(&format!("SELECT '{}'", "x".repeat(8192))).is_err());
+    let mut client = connect(54333);
+    client.batch_execute("CREATE TABLE t (id INT)").unwrap();
+}
+
+/// 一条前端消息
+fn message(tag: u8, body: &[u8]) -> Vec<u8> {
+    let mut buf = vec![tag];
+    buf.extend_from_slice(&(body.len() as i32 + 4).to_be_bytes());
+    buf.extend_from_slice(body);
+    buf
+}
+
+#[test]
+fn test_streamed_results_and_portal_suspension() {
+    let _server = Server::start(54334, &[]);
+    let mut client = connect(54334);
+    client.batch_execute("SET synchronous_commit = off; CREATE TABLE t (id INT, pad VARCHAR)").unwrap();
+    let pad = "x".repeat(200);
+    for i in 0..500 {
+        client.execute(&format!("INSERT INTO t VALUES ({}, '{}')", i, pad), &[]).unwrap();
+    }
+    // Spans many pages and several flushes of the output buffer.
+    let ids: Vec<i64> = client.query("SELECT id FROM t", &[]).unwrap().iter().map(|row| row.get(0)).collect();
+    assert_eq!(ids, (0..500).collect::<Vec<_>>());
+
+    // Execute with a row limit suspends the portal until the rows run out.
+    let mut stream = TcpStream::connect(("127.0.0.1", 54334)).unwrap();
+    stream.write_all(&startup_packet(0x0003_0000, &["user", "test"])).unwrap();
+    while read_message(&mut stream).0 != b'Z' {}
+    let mut request = message(b'P', b"\0SELECT id FROM t\0\0\0");
+    request.extend(message(b'B', b"\0\0\0\0\0\0\0\0"));
+    for max_rows in [200i32, 200, 200, 0] {
+        let mut body = vec![0];
+        body.extend_from_slice(&max_rows.to_be_bytes());
+        request.extend(message(b'E', &body));
+    }
+    request.extend(message(b'S', b""));
+    stream.write_all(&request).unwrap();
+
+    let mut replies = Vec::new();
+    let mut rows = 0;
+    loop {
+        match read_message(&mut stream) {
+            (b'D', _) => rows += 1,
+            (b'Z', _) => break,
+            (tag, body) => {
+                replies.push((rows, tag, body));
+                rows = 0;
+            }
+        }
+    }
+    let tags: Vec<_> = replies.iter().map(|(rows, tag, _)| (*rows, *tag)).collect();
+    assert_eq!(tags, [(0, b'1'), (0, b'2'), (200, b's'), (200, b's'), (100, b'C'), (0, b'C')]);
+    assert_eq!(replies[4].2, b"SELECT 100\0");
+    assert_eq!(replies[5].2, b"SELECT 0\0");
+}
+
+#[test]
+fn test_vacuum_while_portal_is_suspended() {
+    let _server = Server::start(54339, &[]);
+    let mut client = connect(54339);
+    client.batch_execute("SET synchronous_commit = off; CREATE TABLE t (id INT, pad VARCHAR)").unwrap();
+    let pad = "x".repeat(200);
+    for i in 0..300 {
+        client.execute(&format!("INSERT INTO t VALUES ({}, '{}')", i, pad), &[]).unwrap();
+    }
+
+    let mut stream = TcpStream::connect(("127.0.0.1", 54339)).unwrap();
+    // A scan that still held the table's locks would hang the VACUUM below.
+    stream.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+    stream.write_all(&startup_packet(0x0003_0000, &["user", "test"])).unwrap();
+    while read_message(&mut stream).0 != b'Z' {}
+    let execute = |max_rows: i32| {
+        let mut body = b"p\0".to_vec();
+        body.extend_from_slice(&max_rows.to_be_bytes());
+        message(b'E', &body)
+    };
+    let mut request = message(b'P', b"\0SELECT id FROM t\0\0\0");
+    request.extend(message(b'B', b"p\0\0\0\0\0\0\0\0"));
+    request.extend(execute(10));
+    request.extend(message(b'H', b""));
+    stream.write_all(&request).unwrap();
+    let mut rows = 0;
+    loop {
+        match read_message(&mut stream).0 {
+            b'D' => rows += 1,
+            b's' => break,
+            _ => {}
+        }
+    }
+
+    // VACUUM on the same connection while the named portal is suspended.
+    stream.write_all(&message(b'Q', b"VACUUM t\0")).unwrap();
+    let mut tags = Vec::new();
+    loop {
+        match read_message(&mut stream).0 {
+            b'Z' => break,
+            tag => tags.push(tag),
+        }
+    }
+    assert_eq!(tags, [b'N', b'C']);
+
+    let mut request = execute(0);
+    request.extend(message(b'S', b""));
+    stream.write_all(&request).unwrap();
+    loop {
+        match read_message(&mut stream).0 {
+            b'D' => rows += 1,
+            b'Z' => break,
+            _ => {}
+        }
+    }
+    assert_eq!(rows, 300);
+}
+
+#[test]
+fn test_show_sessions() {
+    let _server = Server::start(54335, &[]);
+    let mut client = connect(54335);
+    let mut other = Client::connect(
+        "host=127.0.0.1 port=54335 user=other application_name=reporter",
+        NoTls,
+    )
+    .unwrap();
+    other.batch_execute("SET synchronous_commit = off").unwrap();
+    let statement = other.prepare("SHOW synchronous_commit").unwrap();
+    assert_eq!(other.query_one(&statement, &[]).unwrap().get::<_, &str>(0), "off");
+    assert_eq!(client.query_one("SHOW synchronous_commit", &[]).unwrap().get::<_, &str>(0), "full");
+
+    let rows = client.query("SHOW SESSIONS", &[]).unwrap();
+    assert_eq!(rows.len(), 2);
+    let mine = &rows[0];
+    assert_eq!(mine.get::<_, &str>("user"), "test");
+    assert_eq!(mine.get::<_, &str>("state"), "active");
+    assert_eq!(mine.get::<_, &str>("query"), "SHOW SESSIONS");
+    assert!(mine.get::<_, &str>("client_addr").starts_with("127.0.0.1:"));
+    let theirs = &rows[1];
+    assert_eq!(theirs.get::<_, &str>("application_name"), "reporter");
+    assert_eq!(theirs.get::<_, &str>("state"), "idle");
+    assert_eq!(theirs.get::<_, i64>("prepared_statements"), 1);
+    assert!(mine.get::<_, i64>("id") < theirs.get::<_, i64>("id"));
+
+    // A session disappears once its connection is closed.
+    drop(other);
+    let started = Instant::now();
+    while client.query("SHOW SESSIONS", &[]).unwrap().len() != 1 {
+        assert!(started.elapsed() < Duration::from_secs(5), "session was not cleaned up");
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    let error = client.batch_execute("SHOW work_mem").unwrap_err();
+    assert_eq!(error.code(), Some(&SqlState::UNDEFINED_OBJECT));
+}
+
+#[test]
+fn test_prepared_statement_parameters() {
+    let _server = Server::start(54336, &[]);
+    let mut client = connect(54336);
+    client
+        .batch_execute("CREATE TABLE users (id INT, name VARCHAR, avatar BYTEA)")
+        .unwrap();
+
+    // Parameter types are inferred from the columns they are inserted into.
+    let insert = client.prepare("INSERT INTO users VALUES ($1, $2, $3)").unwrap();
+    assert_eq!(insert.params(), [Type::INT8, Type::VARCHAR, Type::BYTEA]);
+    assert_eq!(client.execute(&insert, &[&1i64, &"Alice", &vec![0u8, 0xff]]).unwrap(), 1);
+    assert_eq!(client.execute(&insert, &[&2i64, &None::<&str>, &None::<Vec<u8>>]).unwrap(), 1);
+    // A declared int4 parameter arrives as four bytes.
+    let typed = client
+        .prepare_typed("INSERT INTO users VALUES ($1, 'Carol', NULL)", &[Type::INT4])
+        .unwrap();
+    assert_eq!(client.execute(&typed, &[&3i64]).unwrap(), 1);
+
+    // SQL PREPARE/EXECUTE share the session's statements with the protocol.
+    client
+        .batch_execute("PREPARE add AS INSERT INTO users VALUES (?, ?, NULL); EXECUTE add (4, 'Dave')")
+        .unwrap();
+    let messages = client.simple_query("EXECUTE add (5, 'Eve')").unwrap();
+    assert!(matches!(messages.last(), Some(SimpleQueryMessage::CommandComplete(1))));
+    let rows = client.query("SELECT id, name, avatar FROM users", &[]).unwrap();
+    let names: Vec<Option<&str>> = rows.iter().map(|row| row.get("name")).collect();
+    assert_eq!(names, [Some("Alice"), None, Some("Carol"), Some("Dave"), Some("Eve")]);
+    assert_eq!(rows[0].get::<_, Vec<u8>>("avatar"), [0, 0xff]);
+
+    let error = client.batch_execute("EXECUTE add (6)").unwrap_err();
+    assert_eq!(error.code(), Some(&SqlState::SYNTAX_ERROR));
+    let error = client.batch_execute("PREPARE add AS SELECT id FROM users").unwrap_err();
+    assert_eq!(error.code(), Some(&SqlState::DUPLICATE_PSTATEMENT));
+    let error = client.batch_execute("INSERT INTO users VALUES ($1, 'x', NULL)").unwrap_err();
+    assert_eq!(error.code(), Some(&SqlState::UNDEFINED_PARAMETER));
+    let error = client.prepare("INSERT INTO users VALUES (1, 'x', NULL, $1)").unwrap_err();
+    assert_eq!(error.code(), Some(&SqlState::INDETERMINATE_DATATYPE));
+
+    client.batch_execute("DEALLOCATE add").unwrap();
+    let error = client.batch_execute("EXECUTE add (6, 'Frank')").unwrap_err();
+    assert_eq!(error.code(), Some(&SqlState::INVALID_SQL_STATEMENT_NAME));
+    // DEALLOCATE ALL also drops the statements the client library prepared.
+    client.batch_execute("DEALLOCATE ALL").unwrap();
+    let messages = client.simple_query("SHOW SESSIONS").unwrap();
+    let SimpleQueryMessage::Row(row) = &messages[1] else {
+        panic!("expected a row, got {:?}", messages[1]);
+    };
+    assert_eq!(row.get("prepared_statements"), Some("0"));
+}
+
+#[test]
+fn test_cancel_and_statement_timeout() {
+    // The whole table stays in the buffer pool.
+    let _server = Server::start(54337, &["--pool-size=4096"]);
+    let mut client = connect(54337);
+    client
+        .batch_execute("CREATE TABLE docs (id INT, body VARCHAR); SET synchronous_commit = off")
+        .unwrap();
+    // Far more than the socket buffers hold, so the scan is still running while the
+    // client stops reading.
+    let body = "x".repeat(1500);
+    for chunk in 0..20 {
+        let inserts: String = (0..500)
+            .map(|i| format!("INSERT INTO docs VALUES ({}, '{}');", chunk * 500 + i, body))
+            .collect();
+        client.batch_execute(&inserts).unwrap();
+    }
+
+    let cancel = client.cancel_token();
+    let mut rows = client.query_raw("SELECT id, body FROM docs", std::iter::empty::<i64>()).unwrap();
+    assert!(rows.next().unwrap().is_some());
+    cancel.cancel_query(NoTls).unwrap();
+    let error = loop {
+        match rows.next() {
+            Ok(Some(_)) => {}
+            Ok(None) => panic!("the scan finished despite the cancel request"),
+            Err(e) => break e,
+        }
+    };
+    drop(rows);
+    assert_eq!(error.code(), Some(&SqlState::QUERY_CANCELED));
+    assert!(error.as_db_error().unwrap().message().contains("user request"));
+
+    // The timeout is measured from the start of each statement.
+    client.batch_execute("SET statement_timeout = 1").unwrap();
+    let error = client.query("SELECT id, body FROM docs", &[]).unwrap_err();
+    assert_eq!(error.code(), Some(&SqlState::QUERY_CANCELED));
+    assert!(error.as_db_error().unwrap().message().contains("statement timeout"));
+    client.batch_execute("SET statement_timeout = 0").unwrap();
+    assert_eq!(client.query("SELECT id FROM docs", &[]).unwrap().len(), 10000);
+}
+
+#[test]
+fn test_password_authentication() {
+    let _server = Server::start(
+        54338,
+        &["--auth-method=scram-sha-256", "--user=admin", "--password=secret"],
+    );
+    let connect_as = |user: &str, password: &str| {
+        Client::connect(
+            &format!("host=127.0.0.1 port=54338 user={} password={}", user, password),
+            NoTls,
+        )
+    };
+    let rejected = |result: Result<Client, postgres::Error>| {
+        let error = result.err().expect("login should fail");
+        let error = error.as_db_error().expect("expected an error from the server");
+        assert_eq!(error.code(), &SqlState::INVALID_PASSWORD);
+        error.message().to_string()
+    };
+
+    // A wrong password and an unknown user look the same to the client.
+    assert_eq!(
+        rejected(connect_as("admin", "Secret")),
+        "password authentication failed for user \"admin\""
+    );
+    assert_eq!(
+        rejected(connect_as("nobody", "secret")),
+        "password authentication failed for user \"nobody\""
+    );
+    assert!(Client::connect("host=127.0.0.1 port=54338 user=admin", NoTls).is_err());
+
+    let mut admin = connect_as("admin", "secret").unwrap();
+    admin.batch_execute("CREATE USER alice WITH PASSWORD 'wonderland'").unwrap();
+    let error = admin.batch_execute("CREATE USER alice PASSWORD 'again'").unwrap_err();
+    assert_eq!(error.code(), Some(&SqlState::DUPLICATE_OBJECT));
+    rejected(connect_as("alice", "secret"));
+    let mut alice = connect_as("alice", "wonderland").unwrap();
+    alice.batch_execute("CREATE TABLE t (id INT)").unwrap();
+
+    // Only the configured user may create users, and passwords never show up in
+    // other sessions' query text.
+    let error = alice.batch_execute("CREATE USER mallory PASSWORD 'stolen'").unwrap_err();
+    assert_eq!(error.code(), Some(&SqlState::INSUFFICIENT_PRIVILEGE));
+    let queries: Vec<String> = admin
+        .query("SHOW SESSIONS", &[])
+        .unwrap()
+        .iter()
+        .map(|row| row.get("query"))
+        .collect();
+    assert!(queries.contains(&"CREATE USER mallory PASSWORD '********'".to_string()));
+    assert!(!queries.iter().any(|query| query.contains("stolen") || query.contains("wonderland")));
+
+    // A client that sends a query instead of answering the SASL request is disconnected
+    // without the query running.
+    let mut stream = TcpStream::connect(("127.0.0.1", 54338)).unwrap();
+    stream
+        .write_all(&startup_packet(0x0003_0000, &["user", "admin"]))
+        .unwrap();
+    let (tag, body) = read_message(&mut stream);
+    assert_eq!(tag, b'R');
+    assert_eq!(&body[..4], &10i32.to_be_bytes());
+    assert_eq!(&body[4..], b"SCRAM-SHA-256\0\0");
+    stream.write_all(&message(b'Q', b"CREATE TABLE u (id INT)\0")).unwrap();
+    let (tag, body) = read_message(&mut stream);
+    assert_eq!(tag, b'E');
+    assert!(String::from_utf8_lossy(&body).contains("08P01"));
+    assert_eq!(stream.read(&mut [0u8; 1]).unwrap(), 0, "connection should be closed");
+    let error = alice.batch_execute("SELECT id FROM u").unwrap_err();
+    assert_eq!(error.code(), Some(&SqlState::UNDEFINED_TABLE));
+}